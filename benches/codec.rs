@@ -0,0 +1,133 @@
+//! Compression and decompression throughput for each match finder, over a few corpora:
+//!
+//! - `bianco0`, the bundled test file;
+//! - `random`, incompressible noise;
+//! - `text`, generated prose, for something compressible but unlike game data.
+//!
+//! Set `YAZ0_BENCH_CORPUS` to a directory to bench every file in it as well, and `YAZ0_BENCH_LEN`
+//! to change how much of each corpus is used (0x10000 bytes by default; the naive match finder is
+//! slow). Criterion only measures time, so compression ratios are printed before the timings.
+//!
+//! Build with `--features=rayon` to bench the parallel window search instead; it only kicks in
+//! for inputs of 0x40000 bytes or more.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::distributions::Standard;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::env;
+use std::fs;
+
+use yaz0::CompressionLevel;
+
+/// The match finders to compare; add new ones here.
+const LEVELS: &[(&str, CompressionLevel)] = &[
+    ("naive", CompressionLevel::Naive { quality: 10 }),
+    ("lookahead", CompressionLevel::Lookahead { quality: 10 }),
+    ("hash_chain", CompressionLevel::HashChain { quality: 10 }),
+    ("lazy", CompressionLevel::Lazy { depth: 2 }),
+];
+
+const DEFAULT_LEN: usize = 0x10000;
+
+/// Generates `len` bytes of prose from a small vocabulary, deterministically.
+fn text(len: usize) -> Vec<u8> {
+    const WORDS: &[&str] = &[
+        "the", "of", "and", "a", "to", "in", "is", "you", "that", "it", "he", "was", "for", "on",
+        "are", "as", "with", "his", "they", "at", "be", "this", "have", "from", "or", "one", "had",
+        "by", "word", "but", "not", "what", "all", "were", "we", "when", "your", "can", "said",
+        "there", "use", "an", "each", "which", "she", "do", "how", "their", "if", "will", "up",
+        "princess", "castle", "star", "mushroom", "plumber", "island", "sunshine", "paint",
+    ];
+
+    let mut rng = StdRng::seed_from_u64(0x59617a30);
+    let mut text = Vec::with_capacity(len + 16);
+    while text.len() < len {
+        text.extend_from_slice(WORDS[rng.gen_range(0, WORDS.len())].as_bytes());
+        text.push(if rng.gen_range(0, 12) == 0 { b'\n' } else { b' ' });
+    }
+    text.truncate(len);
+    text
+}
+
+/// Collects the corpora to bench, each cut to at most `len` bytes.
+fn corpora(len: usize) -> Vec<(String, Vec<u8>)> {
+    let bianco: &[u8] = include_bytes!("../data/test");
+    let random: Vec<u8> = StdRng::seed_from_u64(0).sample_iter(&Standard).take(len).collect();
+
+    let mut corpora = vec![
+        ("bianco0".to_owned(), bianco[..len.min(bianco.len())].to_vec()),
+        ("random".to_owned(), random),
+        ("text".to_owned(), text(len)),
+    ];
+
+    if let Some(dir) = env::var_os("YAZ0_BENCH_CORPUS") {
+        let mut entries: Vec<_> = fs::read_dir(dir)
+            .expect("couldn't read YAZ0_BENCH_CORPUS")
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.is_file())
+            .collect();
+        entries.sort();
+
+        for path in entries {
+            let mut data = fs::read(&path).unwrap();
+            data.truncate(len);
+            corpora.push((path.file_name().unwrap().to_string_lossy().into_owned(), data));
+        }
+    }
+
+    corpora
+}
+
+fn bench_len() -> usize {
+    env::var("YAZ0_BENCH_LEN")
+        .ok()
+        .and_then(|len| len.parse().ok())
+        .unwrap_or(DEFAULT_LEN)
+}
+
+fn compression(c: &mut Criterion) {
+    let corpora = corpora(bench_len());
+
+    for (name, data) in &corpora {
+        for &(level_name, level) in LEVELS {
+            let compressed = yaz0::compress(data, level);
+            eprintln!(
+                "{}/{}: {} -> {} bytes ({:.1}%)",
+                name,
+                level_name,
+                data.len(),
+                compressed.len(),
+                compressed.len() as f64 * 100. / data.len().max(1) as f64
+            );
+        }
+    }
+
+    let mut group = c.benchmark_group("compress");
+    group.sample_size(10);
+    for (name, data) in &corpora {
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        for &(level_name, level) in LEVELS {
+            group.bench_with_input(BenchmarkId::new(level_name, name), data, |b, data| {
+                b.iter(|| yaz0::compress(data, level))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn decompression(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decompress");
+    for (name, data) in &corpora(bench_len()) {
+        let compressed = yaz0::compress(data, CompressionLevel::HashChain { quality: 10 });
+
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &compressed, |b, compressed| {
+            b.iter(|| yaz0::decompress(compressed).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, compression, decompression);
+criterion_main!(benches);
@@ -0,0 +1,32 @@
+//! Feeds arbitrary bytes to the decoder, which must report malformed data as an error rather
+//! than panicking, looping forever, or allocating without bound.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+use yaz0::Yaz0Archive;
+
+/// Headers asking for more output than this are skipped, so the fuzzer doesn't spend its time
+/// zeroing huge buffers.
+const MAX_EXPECTED_SIZE: usize = 1 << 24;
+
+fuzz_target!(|data: &[u8]| {
+    let mut archive = match Yaz0Archive::new(Cursor::new(data)) {
+        Ok(archive) if archive.expected_size() <= MAX_EXPECTED_SIZE => archive,
+        _ => return,
+    };
+
+    if let Ok(inflated) = archive.decompress() {
+        assert_eq!(inflated.len(), archive.expected_size());
+    }
+
+    // a destination one byte short must be refused, not overrun
+    if let Some(len) = archive.expected_size().checked_sub(1) {
+        let mut dest = vec![0u8; len];
+        assert!(archive.decompress_into(&mut dest[..]).is_err());
+    }
+
+    let _ = archive.verify();
+    let _ = archive.decompress_prefix(0x100);
+});
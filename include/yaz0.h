@@ -0,0 +1,46 @@
+/*
+ * C interface to the yaz0 crate, built with `cargo build --release --features=ffi`.
+ * Link against the resulting `libyaz0` shared library.
+ *
+ * Every function except yaz0_max_compressed_size returns one of the YAZ0_* status codes,
+ * writing its results through out-pointers.
+ */
+
+#ifndef YAZ0_H
+#define YAZ0_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define YAZ0_OK 0
+#define YAZ0_ERR_NULL_POINTER (-1)
+#define YAZ0_ERR_INVALID_MAGIC (-2)
+#define YAZ0_ERR_CORRUPT_DATA (-3)
+#define YAZ0_ERR_UNEXPECTED_EOF (-4)
+/* the size needed is written to the length out-pointer */
+#define YAZ0_ERR_BUFFER_TOO_SMALL (-5)
+#define YAZ0_ERR_INTERNAL (-6)
+
+/* An upper bound on the compressed size of `len` bytes of input. */
+size_t yaz0_max_compressed_size(size_t len);
+
+/* Reads the decompressed size from the header of the Yaz0 file in `src`. */
+int32_t yaz0_expected_size(const uint8_t *src, size_t src_len, size_t *out_size);
+
+/* Decompresses the Yaz0 file in `src` into `dst`, writing the decompressed size to `out_len`. */
+int32_t yaz0_decompress(const uint8_t *src, size_t src_len,
+                        uint8_t *dst, size_t dst_len, size_t *out_len);
+
+/* Compresses `src` at `quality` 1-10 into `dst`, writing the compressed size to `out_len`. */
+int32_t yaz0_compress(const uint8_t *src, size_t src_len, uint32_t quality,
+                      uint8_t *dst, size_t dst_len, size_t *out_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* YAZ0_H */
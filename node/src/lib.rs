@@ -0,0 +1,88 @@
+//! Node.js bindings, exposing compression and decompression of `Buffer`s, both synchronously and
+//! on the libuv thread pool.
+
+use napi::bindgen_prelude::{AsyncTask, Buffer};
+use napi::{Env, Error, Result, Task};
+use napi_derive::napi;
+use yaz0::CompressionLevel;
+
+/// Turns a codec error into a JS exception.
+fn to_js_error(err: yaz0::Error) -> Error {
+    Error::from_reason(err.to_string())
+}
+
+/// Picks the compression level for `quality`, defaulting to the best.
+fn level(quality: Option<u32>) -> CompressionLevel {
+    let quality = quality.unwrap_or(10).clamp(1, 10) as usize;
+    CompressionLevel::Lookahead { quality }
+}
+
+fn compress_data(data: &[u8], level: CompressionLevel) -> Result<Vec<u8>> {
+    Ok(yaz0::compress(data, level))
+}
+
+fn decompress_data(data: &[u8]) -> Result<Vec<u8>> {
+    yaz0::decompress(data).map_err(to_js_error)
+}
+
+/// Compresses `data` into a Yaz0 file, at `quality` from 1 to 10 (10 if not given).
+#[napi]
+pub fn compress(data: Buffer, quality: Option<u32>) -> Result<Buffer> {
+    compress_data(&data, level(quality)).map(Buffer::from)
+}
+
+/// Decompresses the Yaz0 file in `data`.
+#[napi]
+pub fn decompress(data: Buffer) -> Result<Buffer> {
+    decompress_data(&data).map(Buffer::from)
+}
+
+pub struct CompressTask {
+    data: Vec<u8>,
+    level: CompressionLevel,
+}
+
+impl Task for CompressTask {
+    type Output = Vec<u8>;
+    type JsValue = Buffer;
+
+    fn compute(&mut self) -> Result<Vec<u8>> {
+        compress_data(&self.data, self.level)
+    }
+
+    fn resolve(&mut self, _env: Env, output: Vec<u8>) -> Result<Buffer> {
+        Ok(output.into())
+    }
+}
+
+pub struct DecompressTask {
+    data: Vec<u8>,
+}
+
+impl Task for DecompressTask {
+    type Output = Vec<u8>;
+    type JsValue = Buffer;
+
+    fn compute(&mut self) -> Result<Vec<u8>> {
+        decompress_data(&self.data)
+    }
+
+    fn resolve(&mut self, _env: Env, output: Vec<u8>) -> Result<Buffer> {
+        Ok(output.into())
+    }
+}
+
+/// Like `compress`, but runs on the thread pool, returning a `Promise`.
+#[napi]
+pub fn compress_async(data: Buffer, quality: Option<u32>) -> AsyncTask<CompressTask> {
+    AsyncTask::new(CompressTask {
+        data: data.to_vec(),
+        level: level(quality),
+    })
+}
+
+/// Like `decompress`, but runs on the thread pool, returning a `Promise`.
+#[napi]
+pub fn decompress_async(data: Buffer) -> AsyncTask<DecompressTask> {
+    AsyncTask::new(DecompressTask { data: data.to_vec() })
+}
@@ -0,0 +1,559 @@
+//! Analysis of existing compressed streams.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+
+use crate::deflate::{CompressionLevel, Yaz0Writer};
+use crate::header::Yaz0Header;
+use crate::ops::{ByteIter, Op, OpIter};
+use crate::Error;
+
+/// How many operations [`fingerprint`] inspects; a prefix of the stream is plenty to tell
+/// encoders apart, and matching every operation against the whole window is slow.
+const FINGERPRINT_OPS: usize = 0x4000;
+
+/// Shortest run that any known encoder will emit a copy for.
+const MIN_RUN: usize = 3;
+
+/// The longest possible run, using a 3-byte packet.
+const MAX_RUN: usize = 0xff + 0x12;
+
+/// How far back copy runs can reach.
+const MAX_DISTANCE: usize = 0x1000;
+
+/// An encoder that a compressed stream may have been produced by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Encoder {
+    /// This crate, with [`CompressionLevel::Naive`](crate::CompressionLevel::Naive).
+    Naive,
+    /// This crate, with [`CompressionLevel::Lookahead`](crate::CompressionLevel::Lookahead).
+    Lookahead,
+    /// Nintendo's encoder, or a reimplementation of it like `yaz0enc`.
+    Nintendo,
+    /// The stream's characteristics don't match any known encoder.
+    Unknown,
+}
+
+/// Characteristics of a compressed stream, and the encoder they suggest produced it.
+#[derive(Debug, Clone)]
+pub struct Fingerprint {
+    /// The most likely encoder.
+    pub encoder: Encoder,
+    /// The longest copy distance used.
+    pub max_distance: usize,
+    /// The longest copy run used.
+    pub max_run: usize,
+    /// Literals emitted even though a copy run was available; a sign of lookahead matching.
+    pub lazy_literals: usize,
+    /// Copies for which an equally long run was available closer to the read head.
+    pub nearer_ties: usize,
+    /// Copies for which an equally long run was available further from the read head.
+    pub farther_ties: usize,
+    /// Number of operations inspected.
+    pub ops: usize,
+}
+
+impl Fingerprint {
+    /// Returns the compression level that comes closest to imitating the encoder that produced
+    /// the stream, searching back as far as it did.
+    ///
+    /// Nintendo's encoder is imitated exactly with [`CompressionLevel::MatchNintendo`]; unknown
+    /// encoders get [`CompressionLevel::Lookahead`].
+    pub fn closest_level(&self) -> CompressionLevel {
+        // the inverse of the quality -> lookback mapping the compressor uses
+        let quality = ((self.max_distance as f64 / (MAX_DISTANCE as f64 / 10.)).ceil() as usize).clamp(1, 10);
+
+        match self.encoder {
+            Encoder::Naive => CompressionLevel::Naive { quality },
+            Encoder::Nintendo => CompressionLevel::MatchNintendo,
+            _ => CompressionLevel::Lookahead { quality },
+        }
+    }
+}
+
+/// Finds the length of the match between the data `dist` bytes back from `pos` and the data at
+/// `pos`, capped at `cap`.
+fn match_len(data: &[u8], pos: usize, dist: usize, cap: usize) -> usize {
+    (0..cap)
+        .take_while(|&i| data[pos - dist + i] == data[pos + i])
+        .count()
+}
+
+/// Inspects the Yaz0 file in `reader`, guessing which encoder produced it.
+///
+/// Encoders are told apart by how they break ties between equally long runs, whether they ever
+/// emit literals when a run was available, and how far back they search.
+pub fn fingerprint<R>(mut reader: R) -> Result<Fingerprint, Error>
+where
+    R: Read,
+{
+    let header = Yaz0Header::parse(&mut reader)?;
+
+    // -- reconstruct the output covered by the ops we're looking at
+    let mut ops: Vec<Op> = Vec::new();
+    let mut data: Vec<u8> = Vec::new();
+    let mut iter = OpIter::new(&mut reader, header.expected_size);
+    while ops.len() < FINGERPRINT_OPS {
+        let op = match iter.next() {
+            Some(op) => op?,
+            None => break,
+        };
+
+        match op {
+            Op::Literal(byte) => data.push(byte),
+            Op::Copy { dist, len } => {
+                if dist > data.len() {
+                    let offset = iter.op_offset();
+                    return Err(Error::RunOutOfBounds { offset, dist, produced: data.len(), context: None });
+                }
+                for _ in 0..len {
+                    data.push(data[data.len() - dist]);
+                }
+            }
+        }
+        ops.push(op);
+    }
+
+    let mut fingerprint = Fingerprint {
+        encoder: Encoder::Unknown,
+        max_distance: 0,
+        max_run: 0,
+        lazy_literals: 0,
+        nearer_ties: 0,
+        farther_ties: 0,
+        ops: ops.len(),
+    };
+
+    // -- look at what else each op could have done
+    let mut pos = 0;
+    for op in &ops {
+        let window = pos.min(MAX_DISTANCE);
+        let cap = (data.len() - pos).min(MAX_RUN);
+
+        match *op {
+            Op::Literal(_) => {
+                if (1..=window).any(|d| match_len(&data, pos, d, MIN_RUN.min(cap)) >= MIN_RUN) {
+                    fingerprint.lazy_literals += 1;
+                }
+            }
+            Op::Copy { dist, len } => {
+                fingerprint.max_distance = fingerprint.max_distance.max(dist);
+                fingerprint.max_run = fingerprint.max_run.max(len);
+
+                if (1..dist).any(|d| match_len(&data, pos, d, len) == len) {
+                    fingerprint.nearer_ties += 1;
+                }
+                if (dist + 1..=window).any(|d| match_len(&data, pos, d, len) == len) {
+                    fingerprint.farther_ties += 1;
+                }
+            }
+        }
+
+        pos += op.output_len();
+    }
+
+    fingerprint.encoder = match fingerprint {
+        // this crate keeps the nearest of equally long runs...
+        Fingerprint { nearer_ties: 0, farther_ties: 1.., lazy_literals: 0, .. } => Encoder::Naive,
+        Fingerprint { nearer_ties: 0, farther_ties: 1.., .. } => Encoder::Lookahead,
+        // ...while Nintendo's keeps the furthest.
+        Fingerprint { nearer_ties: 1.., farther_ties: 0, .. } => Encoder::Nintendo,
+        _ => Encoder::Unknown,
+    };
+
+    Ok(fingerprint)
+}
+
+/// Compresses `data` into a Yaz0 file in the same style as the existing Yaz0 file in `original`,
+/// so that repacked files stay consistent with the files they replace.
+///
+/// If `original` recorded the settings it was compressed with (see
+/// [`Yaz0Writer::record_settings`]), those are reused, and recorded again. Otherwise, the
+/// original's encoder is [fingerprinted](fingerprint) and imitated as closely as possible.
+pub fn recompress_matching<R>(mut original: R, data: &[u8]) -> Result<Vec<u8>, Error>
+where
+    R: Read + Seek,
+{
+    let start = original.stream_position()?;
+    let header = Yaz0Header::parse(&mut original)?;
+
+    let recorded = CompressionLevel::from_settings_hash(header.reserved);
+
+    let level = match recorded {
+        Some(level) => level,
+        None => {
+            original.seek(SeekFrom::Start(start))?;
+            fingerprint(original)?.closest_level()
+        }
+    };
+
+    let mut out = Vec::new();
+    let writer = Yaz0Writer::new(&mut out);
+    let writer = if recorded.is_some() { writer.record_settings() } else { writer };
+    writer.compress_and_write(data, level)?;
+
+    Ok(out)
+}
+
+/// The decoded structure of a compressed stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct StreamStructure {
+    /// Expected size of the decompressed data, from the header.
+    pub expected_size: usize,
+    /// Groups of up to eight packets, each led by a code byte.
+    pub groups: Vec<Group>,
+}
+
+/// A group of up to eight packets, led by a code byte saying which packets are literals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Group {
+    /// Offset of the code byte within the packet stream.
+    pub offset: usize,
+    /// The code byte; set bits mark literal packets, from the most significant bit down.
+    pub code_byte: u8,
+    pub packets: Vec<Packet>,
+}
+
+/// A single packet within a [`Group`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Packet {
+    /// Offset of the packet within the packet stream.
+    pub offset: usize,
+    /// Offset in the decompressed data that the packet's output starts at.
+    pub output_offset: usize,
+    /// Length of the packet's encoding: 1 for literals, 2 or 3 for copies.
+    pub encoded_len: usize,
+    pub op: Op,
+}
+
+impl StreamStructure {
+    /// Serializes the structure as JSON, for external tooling.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("stream structure is always serializable")
+    }
+}
+
+/// Decodes the full structure of the Yaz0 file in `reader`: every group and packet, along with
+/// their offsets in the packet stream and in the decompressed output.
+pub fn structure<R>(mut reader: R) -> Result<StreamStructure, Error>
+where
+    R: Read,
+{
+    let header = Yaz0Header::parse(&mut reader)?;
+
+    let mut groups: Vec<Group> = Vec::new();
+    let mut output_offset = 0;
+
+    let mut ops = OpIter::new(&mut reader, header.expected_size);
+    loop {
+        let new_group = ops.at_group_start();
+        let offset = ops.offset();
+
+        let op = match ops.next() {
+            Some(op) => op?,
+            None => break,
+        };
+
+        // if we started a new group, the code byte came before the packet
+        let packet_offset = if new_group {
+            groups.push(Group {
+                offset,
+                code_byte: ops.group_code(),
+                packets: Vec::with_capacity(8),
+            });
+            offset + 1
+        } else {
+            offset
+        };
+
+        groups.last_mut().unwrap().packets.push(Packet {
+            offset: packet_offset,
+            output_offset,
+            encoded_len: ops.offset() - packet_offset,
+            op,
+        });
+        output_offset += op.output_len();
+    }
+
+    Ok(StreamStructure {
+        expected_size: header.expected_size,
+        groups,
+    })
+}
+
+/// The result of comparing the decompressed contents of two Yaz0 files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    /// Both files decompress to the same data.
+    Identical,
+    /// The files' decompressed data first differs at `offset`. If one file's data is a prefix
+    /// of the other's, this is the length of the shorter one.
+    Differs { offset: usize },
+}
+
+/// Compares the decompressed contents of the Yaz0 files in `a` and `b`, without decompressing
+/// either into memory.
+///
+/// Files which decompress to the same data are identical, no matter how they were compressed.
+pub fn compare_streams<A, B>(mut a: A, mut b: B) -> Result<Comparison, Error>
+where
+    A: Read,
+    B: Read,
+{
+    let header_a = Yaz0Header::parse(&mut a)?;
+    let header_b = Yaz0Header::parse(&mut b)?;
+
+    let bytes_a = ByteIter::<_>::new(a, header_a.expected_size);
+    let bytes_b = ByteIter::<_>::new(b, header_b.expected_size);
+
+    for (offset, (byte_a, byte_b)) in bytes_a.zip(bytes_b).enumerate() {
+        if byte_a? != byte_b? {
+            return Ok(Comparison::Differs { offset });
+        }
+    }
+
+    if header_a.expected_size != header_b.expected_size {
+        let offset = header_a.expected_size.min(header_b.expected_size);
+        return Ok(Comparison::Differs { offset });
+    }
+
+    Ok(Comparison::Identical)
+}
+
+/// Compares the decompressed contents of the Yaz0 files in `a` and `b`, without decompressing
+/// either into memory, returning every range of offsets at which they differ.
+///
+/// If one file's data is longer than the other's, the extra data is included as a final range.
+pub fn diff_streams<A, B>(mut a: A, mut b: B) -> Result<Vec<Range<usize>>, Error>
+where
+    A: Read,
+    B: Read,
+{
+    let header_a = Yaz0Header::parse(&mut a)?;
+    let header_b = Yaz0Header::parse(&mut b)?;
+
+    let bytes_a = ByteIter::<_>::new(a, header_a.expected_size);
+    let bytes_b = ByteIter::<_>::new(b, header_b.expected_size);
+
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    let mut push_range = |range: Range<usize>| match ranges.last_mut() {
+        Some(last) if last.end == range.start => last.end = range.end,
+        _ => ranges.push(range),
+    };
+
+    for (offset, (byte_a, byte_b)) in bytes_a.zip(bytes_b).enumerate() {
+        if byte_a? != byte_b? {
+            push_range(offset..offset + 1);
+        }
+    }
+
+    let shorter = header_a.expected_size.min(header_b.expected_size);
+    let longer = header_a.expected_size.max(header_b.expected_size);
+    if shorter != longer {
+        push_range(shorter..longer);
+    }
+
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    fn fingerprint_level(data: &[u8], level: CompressionLevel) -> Fingerprint {
+        let mut deflated = Vec::new();
+        Yaz0Writer::new(&mut deflated)
+            .compress_and_write(data, level)
+            .expect("Could not deflate");
+
+        fingerprint(Cursor::new(deflated)).unwrap()
+    }
+
+    #[test]
+    fn fingerprints() {
+        let reference: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+        let sample = &reference_decompressed[..0x2000];
+
+        // the reference file was produced by yaz0enc
+        let f = fingerprint(Cursor::new(reference)).unwrap();
+        assert_eq!(f.encoder, Encoder::Nintendo);
+        assert!(matches!(f.closest_level(), CompressionLevel::MatchNintendo));
+
+        let f = fingerprint_level(sample, CompressionLevel::MatchNintendo);
+        assert_eq!(f.encoder, Encoder::Nintendo);
+
+        let f = fingerprint_level(sample, CompressionLevel::Naive { quality: 10 });
+        assert_eq!(f.encoder, Encoder::Naive);
+
+        let f = fingerprint_level(sample, CompressionLevel::Lookahead { quality: 10 });
+        assert_eq!(f.encoder, Encoder::Lookahead);
+    }
+
+    #[test]
+    fn matching_recompression() {
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+        let sample = &reference_decompressed[..0x2000];
+        let modified = &reference_decompressed[0x2000..0x4000];
+
+        let compress = |level: CompressionLevel| {
+            let mut deflated = Vec::new();
+            Yaz0Writer::new(&mut deflated).compress_and_write(modified, level).unwrap();
+            deflated
+        };
+
+        // recorded settings are reused exactly
+        let level = CompressionLevel::Naive { quality: 3 };
+        let mut original = Vec::new();
+        Yaz0Writer::new(&mut original).record_settings().compress_and_write(sample, level).unwrap();
+        let recompressed = recompress_matching(Cursor::new(&original), modified).unwrap();
+        assert_eq!(&recompressed[0x10..], &compress(level)[0x10..]);
+        assert_eq!(&recompressed[0xc..0x10], &level.settings_hash().to_be_bytes()[..]);
+
+        // otherwise, the encoder's style is imitated
+        let f = fingerprint_level(sample, CompressionLevel::Naive { quality: 10 });
+        assert!(matches!(f.closest_level(), CompressionLevel::Naive { quality: 10 }));
+
+        let mut original = Vec::new();
+        Yaz0Writer::new(&mut original)
+            .compress_and_write(sample, CompressionLevel::Lookahead { quality: 10 })
+            .unwrap();
+        let recompressed = recompress_matching(Cursor::new(&original), modified).unwrap();
+        assert_eq!(&recompressed[..], &compress(CompressionLevel::Lookahead { quality: 10 })[..]);
+
+        // a malformed original is reported, rather than imitated
+        let err = recompress_matching(Cursor::new(RUN_BEFORE_START), modified).unwrap_err();
+        assert!(matches!(err, Error::RunOutOfBounds { .. }), "{:?}", err);
+    }
+
+    /// A correctly sized file whose first operation copies from before the start of the output.
+    #[rustfmt::skip]
+    const RUN_BEFORE_START: &[u8] = &[
+        b'Y', b'a', b'z', b'0', 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0,
+        0x00, /* run: */ 0x10, 0x00,
+    ];
+
+    #[test]
+    fn fingerprint_run_out_of_bounds() {
+        let err = fingerprint(Cursor::new(RUN_BEFORE_START)).unwrap_err();
+        assert!(matches!(err, Error::RunOutOfBounds { offset: 1, dist: 1, produced: 0, .. }), "{:?}", err);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn stream_structure() {
+        let mut file = b"Yaz0\x00\x00\x00\x15\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        file.extend_from_slice(&[
+            0xe0, /* | id:  */ 0, 1, 2,
+                  /*   run: */ 0x00, 0x02, 0x00,
+        ]);
+
+        let s = structure(Cursor::new(file)).unwrap();
+
+        assert_eq!(
+            s,
+            StreamStructure {
+                expected_size: 0x15,
+                groups: vec![Group {
+                    offset: 0,
+                    code_byte: 0xe0,
+                    packets: vec![
+                        Packet { offset: 1, output_offset: 0, encoded_len: 1, op: Op::Literal(0) },
+                        Packet { offset: 2, output_offset: 1, encoded_len: 1, op: Op::Literal(1) },
+                        Packet { offset: 3, output_offset: 2, encoded_len: 1, op: Op::Literal(2) },
+                        Packet { offset: 4, output_offset: 3, encoded_len: 3, op: Op::Copy { dist: 3, len: 0x12 } },
+                    ],
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+        let sample = &reference_decompressed[..0x2000];
+
+        let compress = |data: &[u8]| {
+            let mut deflated = Vec::new();
+            Yaz0Writer::new(&mut deflated)
+                .compress_and_write(data, CompressionLevel::Naive { quality: 10 })
+                .expect("Could not deflate");
+            Cursor::new(deflated)
+        };
+
+        let mut changed = sample.to_vec();
+        changed[0x10] ^= 1;
+        changed[0x100..0x104].copy_from_slice(b"abcd");
+        changed.extend_from_slice(b"more");
+
+        assert!(diff_streams(compress(sample), compress(sample)).unwrap().is_empty());
+        assert_eq!(
+            diff_streams(compress(sample), compress(&changed)).unwrap(),
+            vec![0x10..0x11, 0x100..0x104, 0x2000..0x2004]
+        );
+    }
+
+    #[test]
+    fn compare() {
+        let reference: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+        let mut sample = reference_decompressed[..0x2000].to_vec();
+
+        let compress = |data: &[u8]| {
+            let mut deflated = Vec::new();
+            Yaz0Writer::new(&mut deflated)
+                .compress_and_write(data, CompressionLevel::Naive { quality: 10 })
+                .expect("Could not deflate");
+            Cursor::new(deflated)
+        };
+
+        let sample_deflated = compress(&sample);
+
+        // differently compressed, but the same data
+        let mut prefix = reference[..0x10].to_vec();
+        prefix[4..8].copy_from_slice(&0x2000u32.to_be_bytes());
+        prefix.extend_from_slice(&reference[0x10..]);
+        assert_eq!(
+            compare_streams(Cursor::new(&prefix), sample_deflated.clone()).unwrap(),
+            Comparison::Identical
+        );
+
+        // a prefix of the same data
+        assert_eq!(
+            compare_streams(Cursor::new(reference), sample_deflated.clone()).unwrap(),
+            Comparison::Differs { offset: 0x2000 }
+        );
+
+        sample[0x1234] ^= 0xff;
+        assert_eq!(
+            compare_streams(sample_deflated, compress(&sample)).unwrap(),
+            Comparison::Differs { offset: 0x1234 }
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn stream_structure_json() {
+        let mut file = b"Yaz0\x00\x00\x00\x05\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        file.extend_from_slice(&[0x80, 0xaa, 0x30, 0x00]);
+
+        let json = structure(Cursor::new(file)).unwrap().to_json();
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"expected_size":5,"groups":[{"offset":0,"code_byte":128,"packets":["#,
+                r#"{"offset":1,"output_offset":0,"encoded_len":1,"op":{"literal":170}},"#,
+                r#"{"offset":2,"output_offset":1,"encoded_len":2,"op":{"copy":{"dist":1,"len":5}}}]}]}"#,
+            )
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Reporting the oddities tolerated when decompressing a file leniently.
+
+use std::fmt;
+use std::io::{Read, Seek};
+
+use crate::ops::{Op, OpIter};
+use crate::sink::Sink;
+use crate::{Error, Yaz0Archive};
+
+/// Something unusual about a Yaz0 file, which doesn't stop it being decompressed.
+/// Offsets are within the packet stream, like those of [`Error::CorruptData`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Anomaly {
+    /// The header's reserved field, zero in Nintendo's files, holds this value instead. That
+    /// includes a settings hash or checksum written there by [`Yaz0Writer`](crate::Yaz0Writer).
+    NonzeroReserved(u32),
+    /// The last copy run extends `excess` bytes past the end of the data given in the header.
+    RunOverrun { offset: usize, excess: usize },
+    /// The payload is followed by `len` bytes of zero padding.
+    TrailingPadding { offset: usize, len: usize },
+    /// The payload is followed by `len` bytes of something other than padding.
+    TrailingData { offset: usize, len: usize },
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Anomaly::NonzeroReserved(value) => {
+                write!(f, "reserved header field is nonzero ({:#010x})", value)
+            }
+            Anomaly::RunOverrun { offset, excess } => write!(
+                f,
+                "copy run at {:#x} extends {} bytes past the end of the data",
+                offset, excess
+            ),
+            Anomaly::TrailingPadding { offset, len } => {
+                write!(f, "{} bytes of zero padding after the payload, at {:#x}", len, offset)
+            }
+            Anomaly::TrailingData { offset, len } => {
+                write!(f, "{} bytes of trailing data after the payload, at {:#x}", len, offset)
+            }
+        }
+    }
+}
+
+/// Every [`Anomaly`] found in a file, in the order they occur in it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnomalyReport {
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl AnomalyReport {
+    /// Whether the file was entirely unremarkable.
+    pub fn is_clean(&self) -> bool {
+        self.anomalies.is_empty()
+    }
+}
+
+impl<R> Yaz0Archive<R>
+where
+    R: Read + Seek,
+{
+    /// Decompresses the Yaz0 file, tolerating oddities that don't stop it being decompressed, and
+    /// reporting what was tolerated alongside the decompressed data.
+    ///
+    /// The stream is checked before it's decompressed, as with [`Yaz0Archive::verify`], except
+    /// that a last copy run overrunning the end of the data is tolerated (and cut short).
+    pub fn decompress_lenient(&mut self) -> Result<(Vec<u8>, AnomalyReport), Error> {
+        let mut data = vec![0; self.expected_size()];
+        let report = self.decompress_lenient_into(&mut data[..])?;
+
+        Ok((data, report))
+    }
+
+    /// Decompresses the Yaz0 file into a destination buffer, or any other [`Sink`], tolerating
+    /// the same oddities as [`Yaz0Archive::decompress_lenient`], and reporting what was tolerated.
+    ///
+    /// As with [`Yaz0Archive::decompress_into`], a fixed-size `dest` must have a length of at least
+    /// [`Yaz0Archive::expected_size`].
+    pub fn decompress_lenient_into<S>(&mut self, dest: &mut S) -> Result<AnomalyReport, Error>
+    where
+        S: Sink + ?Sized,
+    {
+        let report = self.anomalies()?;
+        self.inflate_overrunning(dest, 0, &mut |_| {})?;
+
+        Ok(report)
+    }
+
+    /// Checks the structure of the stream without decompressing it, as with
+    /// [`Yaz0Archive::verify`], returning any anomalies found. Only the last copy run is allowed
+    /// to overrun the end of the data; malformed streams are still reported as errors.
+    pub fn anomalies(&mut self) -> Result<AnomalyReport, Error> {
+        let mut anomalies = Vec::new();
+        if self.reserved() != 0 {
+            anomalies.push(Anomaly::NonzeroReserved(self.reserved()));
+        }
+
+        let expected_size = self.expected_size();
+        let mut payload = self.raw_compressed_reader()?;
+        let payload_len = payload.limit() as usize;
+
+        let mut ops = OpIter::new(&mut payload, expected_size);
+        let mut dest_pos = 0;
+        while let Some(op) = ops.next() {
+            let op = op?;
+            let offset = ops.op_offset();
+
+            if let Op::Copy { dist, .. } = op {
+                if dist > dest_pos {
+                    return Err(Error::RunOutOfBounds { offset, dist, produced: dest_pos, context: None });
+                }
+            }
+
+            dest_pos += op.output_len();
+            if dest_pos > expected_size {
+                let excess = dest_pos - expected_size;
+                anomalies.push(Anomaly::RunOverrun { offset, excess });
+            }
+        }
+
+        let offset = ops.offset();
+        let mut trailing = Vec::new();
+        payload.read_to_end(&mut trailing)?;
+        if !trailing.is_empty() {
+            let len = payload_len - offset;
+            anomalies.push(if trailing.iter().all(|&byte| byte == 0) {
+                Anomaly::TrailingPadding { offset, len }
+            } else {
+                Anomaly::TrailingData { offset, len }
+            });
+        }
+
+        Ok(AnomalyReport { anomalies })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    #[test]
+    fn clean() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let (inflated, report) = Yaz0Archive::new(Cursor::new(data)).unwrap().decompress_lenient().unwrap();
+        assert!(inflated == reference_decompressed);
+        assert!(report.is_clean(), "{:?}", report);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn anomalies() {
+        let mut data = b"Yaz0\x00\x00\x00\x04\x00\x00\x00\x00\x00\x00\x00\x2a".to_vec();
+        data.extend_from_slice(&[
+            0xc0, /* | id:  */ 1, 2,
+                  /*   run: */ 0x10, 0x01,
+            /* trailing: */ 0, 0, 0,
+        ]);
+
+        let (inflated, report) = Yaz0Archive::new(Cursor::new(&data)).unwrap().decompress_lenient().unwrap();
+        assert_eq!(inflated, [1, 2, 1, 2]);
+        assert_eq!(
+            report.anomalies,
+            vec![
+                Anomaly::NonzeroReserved(0x2a),
+                Anomaly::RunOverrun { offset: 3, excess: 1 },
+                Anomaly::TrailingPadding { offset: 5, len: 3 },
+            ]
+        );
+
+        data[0x16] = 0xff;
+        let (_, report) = Yaz0Archive::new(Cursor::new(&data)).unwrap().decompress_lenient().unwrap();
+        assert_eq!(report.anomalies[2], Anomaly::TrailingData { offset: 5, len: 3 });
+    }
+}
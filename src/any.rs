@@ -0,0 +1,234 @@
+//! Decompressing data in any supported format, detected from its magic bytes.
+//!
+//! Besides the formats this crate implements, downstream crates can [`register`] their own
+//! [`Codec`]s at runtime, to have [`decompress`] handle them too.
+
+use byteorder::{BigEndian, ByteOrder};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::sync::{Arc, RwLock};
+
+use crate::{Error, Mio0Archive, Yay0Archive, Yaz0Header};
+
+/// A compressed format that [`decompress`] can detect and decompress.
+pub trait Codec: Send + Sync {
+    /// A short name for the format, like `"yaz0"`.
+    fn name(&self) -> &'static str;
+
+    /// Whether `head`, the first bytes of some data (at least 16 of them, if the data is that
+    /// long), looks like this format.
+    fn detect(&self, head: &[u8]) -> bool;
+
+    /// Decompresses `data`, which [`Codec::detect`] has accepted.
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+/// The Yaz0 format.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Yaz0Codec;
+
+impl Codec for Yaz0Codec {
+    fn name(&self) -> &'static str {
+        "yaz0"
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        head.starts_with(b"Yaz0")
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        crate::decompress(data)
+    }
+}
+
+/// The Yay0 format.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Yay0Codec;
+
+impl Codec for Yay0Codec {
+    fn name(&self) -> &'static str {
+        "yay0"
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        head.starts_with(b"Yay0")
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        Yay0Archive::new(Cursor::new(data))?.decompress()
+    }
+}
+
+/// The MIO0 format.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mio0Codec;
+
+impl Codec for Mio0Codec {
+    fn name(&self) -> &'static str {
+        "mio0"
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        head.starts_with(b"MIO0")
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        Mio0Archive::new(Cursor::new(data))?.decompress()
+    }
+}
+
+/// A compressed format recognized by [`sniff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Variant {
+    Yaz0,
+    /// Laid out just like Yaz0, under a different magic; found in a few GameCube titles.
+    Yaz1,
+    Yay0,
+    Mio0,
+}
+
+/// What [`sniff`] found out about some compressed data from its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FormatInfo {
+    pub variant: Variant,
+    /// The decompressed size given in the header.
+    pub expected_size: usize,
+}
+
+/// Identifies the compressed format of the data at the reader's position from its header,
+/// returning `None` if it isn't one this crate knows of. The reader is left where it was.
+///
+/// Only the 16-byte header is read, so this is cheap enough to run over every file in a pipeline
+/// that handles a mix of compressed and uncompressed assets.
+pub fn sniff<R>(reader: &mut R) -> Result<Option<FormatInfo>, Error>
+where
+    R: Read + Seek,
+{
+    let start = reader.stream_position()?;
+    let mut head = Vec::with_capacity(0x10);
+    reader.take(0x10).read_to_end(&mut head)?;
+    reader.seek(SeekFrom::Start(start))?;
+
+    if head.len() < 8 {
+        return Ok(None);
+    }
+    let variant = match &head[..4] {
+        b"Yaz0" => Variant::Yaz0,
+        b"Yaz1" => Variant::Yaz1,
+        b"Yay0" => Variant::Yay0,
+        b"MIO0" => Variant::Mio0,
+        _ => return Ok(None),
+    };
+
+    let expected_size = match variant {
+        // Yaz0 headers can be either byte order
+        Variant::Yaz0 if head.len() == 0x10 => Yaz0Header::parse(&mut &head[..])?.expected_size,
+        _ => BigEndian::read_u32(&head[4..]) as usize,
+    };
+
+    Ok(Some(FormatInfo { variant, expected_size }))
+}
+
+/// Codecs registered with [`register`], in order of registration.
+static REGISTRY: RwLock<Vec<Arc<dyn Codec>>> = RwLock::new(Vec::new());
+
+/// Registers `codec`, so that [`detect`] and [`decompress`] will consider it after the formats
+/// built into this crate, and after any codecs registered before it.
+pub fn register<C>(codec: C)
+where
+    C: Codec + 'static,
+{
+    REGISTRY
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .push(Arc::new(codec));
+}
+
+/// Returns the codec for the format of `data`, if any built-in or registered codec detects it.
+pub fn detect(data: &[u8]) -> Option<Arc<dyn Codec>> {
+    let builtin: [Arc<dyn Codec>; 3] =
+        [Arc::new(Yaz0Codec), Arc::new(Yay0Codec), Arc::new(Mio0Codec)];
+    let registry = REGISTRY.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    builtin
+        .iter()
+        .chain(registry.iter())
+        .find(|codec| codec.detect(data))
+        .cloned()
+}
+
+/// Decompresses `data`, in whichever format it's detected to be in.
+/// Data in an unrecognized format is reported as [`Error::InvalidMagic`].
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    match detect(data) {
+        Some(codec) => codec.decompress(data),
+        None => Err(Error::InvalidMagic),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A toy format: the magic, followed by the data stored backwards.
+    struct Reversed;
+
+    impl Codec for Reversed {
+        fn name(&self) -> &'static str {
+            "reversed"
+        }
+
+        fn detect(&self, head: &[u8]) -> bool {
+            head.starts_with(b"REV!")
+        }
+
+        fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+            Ok(data[4..].iter().rev().copied().collect())
+        }
+    }
+
+    #[test]
+    fn sniffing() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let mut reader = Cursor::new(data);
+        reader.set_position(0x10);
+        assert_eq!(sniff(&mut reader).unwrap(), None);
+
+        reader.set_position(0);
+        let info = sniff(&mut reader).unwrap().unwrap();
+        assert_eq!(reader.position(), 0);
+        assert_eq!(info, FormatInfo { variant: Variant::Yaz0, expected_size: include_bytes!("../data/test").len() });
+
+        let mut yaz1 = Cursor::new(b"Yaz1\x00\x00\x01\x00".to_vec());
+        let info = sniff(&mut yaz1).unwrap().unwrap();
+        assert_eq!((info.variant, info.expected_size), (Variant::Yaz1, 0x100));
+        assert_eq!(sniff(&mut Cursor::new(b"Yaz0")).unwrap(), None);
+        assert!(crate::is_yaz0(data) && !crate::is_yaz0(b"Yaz1\x00\x00\x01\x00"));
+    }
+
+    #[test]
+    fn builtin_and_registered() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+        assert_eq!(detect(data).unwrap().name(), "yaz0");
+        assert!(decompress(data).unwrap() == reference_decompressed);
+
+        let mut yay0 = Vec::new();
+        crate::Yay0Writer::new(&mut yay0)
+            .compress_and_write(b"hello hello", crate::CompressionLevel::Naive { quality: 10 })
+            .unwrap();
+        assert_eq!(detect(&yay0).unwrap().name(), "yay0");
+        assert_eq!(decompress(&yay0).unwrap(), b"hello hello");
+
+        let mut mio0 = Vec::new();
+        crate::Mio0Writer::new(&mut mio0)
+            .compress_and_write(b"hello hello", crate::CompressionLevel::Naive { quality: 10 })
+            .unwrap();
+        assert_eq!(detect(&mio0).unwrap().name(), "mio0");
+        assert_eq!(decompress(&mio0).unwrap(), b"hello hello");
+
+        assert!(matches!(decompress(b"REV!olleh"), Err(Error::InvalidMagic)));
+        register(Reversed);
+        assert_eq!(decompress(b"REV!olleh").unwrap(), b"hello");
+    }
+}
@@ -0,0 +1,170 @@
+//! Decompression and compression over `tokio`'s async i/o traits, for services (like ones
+//! ingesting `.szs` uploads) that mustn't block their executor waiting on i/o.
+//!
+//! The payload is read into memory, then decoded in one go on tokio's blocking thread pool, so
+//! that the executor's threads are left free while the codec runs.
+
+use std::io::{self, Cursor, SeekFrom};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+use crate::header::Yaz0Header;
+use crate::{CompressionLevel, Error, Yaz0Archive, Yaz0Writer};
+
+/// Wraps an async reader of Yaz0 data, providing decompression methods.
+#[derive(Debug)]
+pub struct Yaz0AsyncArchive<R>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    reader: R,
+
+    data_start: u64,
+    header: Yaz0Header,
+}
+
+impl<R> Yaz0AsyncArchive<R>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    /// Creates a new `Yaz0AsyncArchive` from a reader positioned at the start of the header.
+    pub async fn new(mut reader: R) -> Result<Yaz0AsyncArchive<R>, Error> {
+        let mut header = [0u8; 0x10];
+        reader.read_exact(&mut header).await?;
+        let header = Yaz0Header::parse(&mut Cursor::new(&header[..]))?;
+
+        let data_start = reader.stream_position().await?;
+
+        Ok(Yaz0AsyncArchive {
+            reader,
+            data_start,
+            header,
+        })
+    }
+
+    /// Get the expected size of inflated data from the parsed `Yaz0Header`.
+    pub fn expected_size(&self) -> usize {
+        self.header.expected_size
+    }
+
+    /// Decompresses the Yaz0 file, producing a `Vec<u8>` of the decompressed data.
+    ///
+    /// No more is read than the largest payload the header's size could need, so a reader that
+    /// runs on past the payload (or doesn't end at all) isn't read into memory in full.
+    pub async fn decompress(&mut self) -> Result<Vec<u8>, Error> {
+        self.reader.seek(SeekFrom::Start(self.data_start)).await?;
+
+        let expected_size = self.header.expected_size;
+        let max_payload = crate::max_compressed_size(expected_size) - 0x10;
+        let mut payload = Vec::new();
+        (&mut self.reader).take(max_payload as u64).read_to_end(&mut payload).await?;
+
+        run_blocking(move || Yaz0Archive::from_raw(Cursor::new(payload), expected_size)?.decompress()).await
+    }
+}
+
+/// Compresses data and writes it out to an async writer.
+pub struct Yaz0AsyncWriter<'a, W>
+where
+    W: AsyncWrite + Unpin,
+{
+    writer: &'a mut W,
+}
+
+impl<'a, W> Yaz0AsyncWriter<'a, W>
+where
+    W: AsyncWrite + Unpin,
+{
+    pub fn new(writer: &'a mut W) -> Yaz0AsyncWriter<'a, W> {
+        Yaz0AsyncWriter { writer }
+    }
+
+    /// Compress and write the passed `data`, at compression level `level`.
+    ///
+    /// The file is compressed into memory on the blocking thread pool, working from a copy of
+    /// `data`, then written out and flushed.
+    pub async fn compress_and_write(self, data: &[u8], level: CompressionLevel) -> Result<(), Error> {
+        let data = data.to_vec();
+        let compressed = run_blocking(move || {
+            let mut compressed = Vec::with_capacity(crate::max_compressed_size(data.len()));
+            Yaz0Writer::new(&mut compressed).compress_and_write(&data, level)?;
+            Ok(compressed)
+        })
+        .await?;
+
+        self.writer.write_all(&compressed).await?;
+        self.writer.flush().await?;
+
+        Ok(())
+    }
+}
+
+/// Runs the codec work `f` on tokio's blocking thread pool, passing on any panic in it.
+async fn run_blocking<T, F>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+        Err(err) => Err(io::Error::other(err).into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn roundtrip() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x8000];
+
+        let mut compressed = Vec::new();
+        Yaz0AsyncWriter::new(&mut compressed)
+            .compress_and_write(data, CompressionLevel::Lookahead { quality: 10 })
+            .await
+            .unwrap();
+
+        let mut archive = Yaz0AsyncArchive::new(Cursor::new(compressed)).await.unwrap();
+        assert_eq!(archive.expected_size(), data.len());
+        assert!(archive.decompress().await.unwrap() == data);
+
+        let err = Yaz0AsyncArchive::new(Cursor::new(b"not yaz0 data!!!")).await.unwrap_err();
+        assert!(matches!(err, Error::InvalidMagic), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn doesnt_block_executor() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        // a task that keeps ticking over, as long as it gets a turn on the (single) executor thread
+        let done = Arc::new(AtomicBool::new(false));
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticker = tokio::spawn({
+            let (done, ticks) = (done.clone(), ticks.clone());
+            async move {
+                while !done.load(Ordering::Relaxed) {
+                    ticks.fetch_add(1, Ordering::Relaxed);
+                    tokio::task::yield_now().await;
+                }
+            }
+        });
+        tokio::task::yield_now().await;
+
+        // followed by more data than could belong to the payload, which shouldn't all be read
+        let mut input = data.to_vec();
+        input.resize(data.len() + 0x40_0000, 0xff);
+        let mut archive = Yaz0AsyncArchive::new(Cursor::new(input)).await.unwrap();
+        let before = ticks.load(Ordering::Relaxed);
+        assert!(archive.decompress().await.unwrap() == reference_decompressed);
+        let during = ticks.load(Ordering::Relaxed) - before;
+        assert_eq!(archive.reader.position(), crate::max_compressed_size(reference_decompressed.len()) as u64);
+
+        done.store(true, Ordering::Relaxed);
+        ticker.await.unwrap();
+        assert!(during > 10, "the executor was blocked while decompressing ({} ticks)", during);
+    }
+}
@@ -1,33 +1,620 @@
 extern crate clap;
+#[cfg(not(target_os = "wasi"))]
 extern crate indicatif;
+extern crate memmap2;
+extern crate serde_json;
 extern crate yaz0;
 
 use std::io::Write;
-use clap::{App, AppSettings, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+#[cfg(not(target_os = "wasi"))]
 use indicatif::ProgressBar;
+#[cfg(not(target_os = "wasi"))]
+use memmap2::Mmap;
+use memmap2::MmapMut;
+use serde_json::{json, Value};
 use std::error::Error;
-use std::fs::File;
-use std::io::{Read, BufReader};
+use std::fmt;
+#[cfg(not(target_os = "wasi"))]
+use std::collections::BTreeSet;
+#[cfg(not(target_os = "wasi"))]
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{Cursor, Read, BufReader, Seek};
+use std::ops::Deref;
+#[cfg(not(target_os = "wasi"))]
 use std::sync::mpsc;
+#[cfg(not(target_os = "wasi"))]
 use std::thread;
-use std::path::Path;
+use std::path::{Component, Path};
+use std::process;
+use std::time::Duration;
+use yaz0::anomaly::{Anomaly, AnomalyReport};
+use yaz0::exit_code;
 use yaz0::{Yaz0Archive, Yaz0Writer, CompressionLevel};
+#[cfg(not(target_os = "wasi"))]
 use yaz0::deflate::ProgressMsg;
+use yaz0::format::{InnerFormat, SNIFF_LEN};
+use yaz0::stats::InflateStats;
+use yaz0::volume::{VolumeReader, VolumeWriter};
 
-fn main() -> Result<(), Box<dyn Error>> {
+/// Compresses `data` with a writer set up by `setup`, drawing a progress bar from a separate
+/// thread as we go.
+#[cfg(not(target_os = "wasi"))]
+fn compress<F>(data: &[u8], quality: CompressionLevel, setup: F) -> Result<Vec<u8>, Box<dyn Error>>
+where
+    F: for<'a> FnOnce(Yaz0Writer<'a, Vec<u8>>) -> Yaz0Writer<'a, Vec<u8>>,
+{
+    let pb = ProgressBar::new(data.len() as u64);
+    let (tx, rx) = mpsc::channel::<ProgressMsg>();
+    thread::spawn(move || {
+        while let Ok(progress) = rx.recv() {
+            pb.set_position(progress.read_head as u64);
+        }
+    });
+
+    let mut d = Vec::new();
+    setup(Yaz0Writer::new(&mut d)).compress_and_write_with_progress(data, quality, tx)?;
+    Ok(d)
+}
+
+/// Compresses `data` with a writer set up by `setup`, on the current thread; wasm32-wasi can't
+/// spawn any others.
+#[cfg(target_os = "wasi")]
+fn compress<F>(data: &[u8], quality: CompressionLevel, setup: F) -> Result<Vec<u8>, Box<dyn Error>>
+where
+    F: for<'a> FnOnce(Yaz0Writer<'a, Vec<u8>>) -> Yaz0Writer<'a, Vec<u8>>,
+{
+    let mut d = Vec::new();
+    setup(Yaz0Writer::new(&mut d)).compress_and_write(data, quality)?;
+    Ok(d)
+}
+
+/// Decompresses `yazfile`, drawing a progress bar as we go.
+#[cfg(not(target_os = "wasi"))]
+fn decompress<R: Read + Seek>(yazfile: &mut Yaz0Archive<R>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let pb = ProgressBar::new(yazfile.expected_size() as u64);
+    let inflated = yazfile.decompress_with_callback(|progress| pb.set_position(progress.output_len as u64))?;
+    pb.finish_and_clear();
+    Ok(inflated)
+}
+
+/// Decompresses `yazfile`; there's no progress bar on wasm32-wasi.
+#[cfg(target_os = "wasi")]
+fn decompress<R: Read + Seek>(yazfile: &mut Yaz0Archive<R>) -> Result<Vec<u8>, Box<dyn Error>> {
+    Ok(yazfile.decompress()?)
+}
+
+/// Inputs at least this big are memory-mapped rather than read in.
+#[cfg(not(target_os = "wasi"))]
+const MMAP_THRESHOLD: u64 = 0x100_0000;
+
+/// Reads the whole of the file at `path`, memory-mapping it if it's large.
+#[cfg(not(target_os = "wasi"))]
+fn read_input(path: &Path) -> Result<Box<dyn Deref<Target = [u8]>>, Box<dyn Error>> {
+    let file = File::open(path)?;
+    if file.metadata()?.len() >= MMAP_THRESHOLD {
+        // safety: the map is only ever read, and we don't expect the input to change underneath us.
+        return Ok(Box::new(unsafe { Mmap::map(&file)? }));
+    }
+
+    let mut data = Vec::new();
+    BufReader::new(file).read_to_end(&mut data)?;
+    Ok(Box::new(data))
+}
+
+/// Reads the whole of the file at `path`; there's no memory-mapping on wasm32-wasi.
+#[cfg(target_os = "wasi")]
+fn read_input(path: &Path) -> Result<Box<dyn Deref<Target = [u8]>>, Box<dyn Error>> {
+    Ok(Box::new(std::fs::read(path)?))
+}
+
+/// Decompresses `yazfile` to `out_path` as the `decompress` subcommand's flags ask. `size` is the
+/// decompressed size given for a `--no-header` stream, if there was one.
+fn write_decompressed<R: Read + Seek>(
+    mut yazfile: Yaz0Archive<R>,
+    matches: &ArgMatches<'_>,
+    size: Option<usize>,
+    out_path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    // anomalies can only be looked for once we know where the data should end
+    let known_size = !matches.is_present("no-header") || size.is_some();
+    if known_size && matches.is_present("strict") {
+        let report = yazfile.anomalies()?;
+        for anomaly in &report.anomalies {
+            eprintln!("warning: {}", anomaly);
+        }
+        if !report.is_clean() {
+            return Err(Anomalous(report.anomalies.len()).into());
+        }
+    }
+    // decoding leniently finds the anomalies as it goes, and tolerates a run overrunning the end
+    let lenient = known_size && matches.is_present("lenient");
+    let warn = |report: AnomalyReport| {
+        for anomaly in &report.anomalies {
+            eprintln!("warning: {}", anomaly);
+        }
+    };
+
+    if !known_size {
+        let inflated = yazfile.decompress_recovering_size()?;
+
+        let mut outfile = File::create(out_path)?;
+        outfile.write_all(&inflated)?;
+    } else if matches.is_present("mmap") {
+        let outfile = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(out_path)?;
+        outfile.set_len(yazfile.expected_size() as u64)?;
+
+        // safety: we just created this file, and nothing else should be touching it.
+        let mut map = unsafe { MmapMut::map_mut(&outfile)? };
+        if lenient {
+            warn(yazfile.decompress_lenient_into(&mut map[..])?);
+        } else {
+            yazfile.decompress_into(&mut map[..])?;
+        }
+        map.flush()?;
+    } else {
+        let inflated = if lenient {
+            let (inflated, report) = yazfile.decompress_lenient()?;
+            warn(report);
+            inflated
+        } else {
+            decompress(&mut yazfile)?
+        };
+
+        let mut outfile = File::create(out_path)?;
+        outfile.write_all(&inflated)?;
+    }
+    Ok(())
+}
+
+/// The breakdown of the packets making up a compressed stream, for `--json`.
+fn stats_json(stats: &InflateStats) -> Value {
+    let packets = stats.packets;
+    json!({
+        "compressed_size": stats.compressed_len,
+        "ratio": stats.ratio(),
+        "literals": packets.literals,
+        "short_runs": packets.short_runs,
+        "long_runs": packets.long_runs,
+        "avg_run_len": packets.avg_run_len(),
+        "avg_run_distance": packets.avg_distance(),
+    })
+}
+
+/// The result of checking one file in a batch, for `--json`: its decompressed size if that was
+/// found, any anomalies, and the error it failed with, if it did.
+fn file_json(path: &Path, size: Option<usize>, anomalies: &[Anomaly], err: Option<&dyn Error>) -> Value {
+    json!({
+        "path": path.display().to_string(),
+        "ok": err.is_none(),
+        "size": size,
+        "anomalies": anomalies.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        "error": err.map(error_chain),
+    })
+}
+
+/// Describes `err` along with everything that caused it, since the codec's IO errors say little
+/// on their own.
+fn error_chain(err: &dyn Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message = format!("{}: {}", message, err);
+        source = err.source();
+    }
+    message
+}
+
+/// Prints the breakdown of the packets making up a compressed stream.
+fn print_stats(stats: &InflateStats) {
+    let packets = stats.packets;
+    println!("compressed size:   {} ({:.1}%)", stats.compressed_len, stats.ratio() * 100.);
+    println!("literals:          {}", packets.literals);
+    println!("2-byte runs:       {}", packets.short_runs);
+    println!("3-byte runs:       {}", packets.long_runs);
+    println!("avg run length:    {:.2}", packets.avg_run_len());
+    println!("avg run distance:  {:.2}", packets.avg_distance());
+}
+
+/// The flags choosing a compression level, shared by the subcommands that compress.
+fn level_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("level")
+            .long("level")
+            .takes_value(true)
+            .value_name("0-10")
+            .help("How far back to look for matches, from 0 (not at all) to 10 (the whole window, the default)"),
+        Arg::with_name("naive")
+            .long("naive")
+            .conflicts_with_all(&["fast", "best", "nintendo", "store"])
+            .help("Take the longest match at each position, without looking ahead for a better one"),
+        Arg::with_name("fast")
+            .long("fast")
+            .conflicts_with("best")
+            .help("Find matches with hash chains: much faster on large files, at some cost in size"),
+        Arg::with_name("best")
+            .long("best")
+            .conflicts_with_all(&["nintendo", "store"])
+            .help("Look ahead for better matches, for the smallest output (the default)"),
+        Arg::with_name("lazy")
+            .long("lazy")
+            .takes_value(true)
+            .value_name("1-2")
+            .conflicts_with_all(&["naive", "fast", "best", "level", "nintendo", "store"])
+            .help("Look up to this many bytes ahead for better matches: slower than --best, but smaller on text"),
+        Arg::with_name("auto")
+            .long("auto")
+            .takes_value(true)
+            .value_name("RATIO")
+            .conflicts_with_all(&["naive", "fast", "best", "level", "lazy", "nintendo", "store"])
+            .help("Pick the fastest level that shrinks the input to this fraction of its size, going by samples of it"),
+        Arg::with_name("time-budget")
+            .long("time-budget")
+            .takes_value(true)
+            .value_name("SECONDS")
+            .requires("auto")
+            .help("With --auto, don't pick levels expected to take longer than this"),
+        Arg::with_name("nintendo")
+            .long("nintendo")
+            .conflicts_with_all(&["fast", "level", "store"])
+            .help("Pick matches exactly as Nintendo's encoder does, reproducing original files byte for byte"),
+        Arg::with_name("store")
+            .long("store")
+            .conflicts_with_all(&["fast", "level"])
+            .help("Don't compress at all, just store the data; for files that are already compressed"),
+    ]
+}
+
+/// Picks the compression level asked for with the flags from [level_args].
+fn compression_level(matches: &ArgMatches<'_>) -> Result<CompressionLevel, Box<dyn Error>> {
+    let quality = match matches.value_of("level") {
+        Some(level) => match level.parse::<usize>() {
+            Ok(level) if level <= 10 => level,
+            _ => return Err("--level must be an integer from 0 to 10".into()),
+        },
+        None => 10,
+    };
+
+    Ok(if matches.is_present("store") {
+        CompressionLevel::Store
+    } else if let Some(ratio) = matches.value_of("auto") {
+        let target_ratio = match ratio.parse::<f64>() {
+            Ok(ratio) if ratio >= 0. => ratio,
+            _ => return Err("--auto must be a ratio like 0.5".into()),
+        };
+        let time_budget = match matches.value_of("time-budget") {
+            Some(secs) => match secs.parse().ok().and_then(|secs| Duration::try_from_secs_f64(secs).ok()) {
+                Some(budget) => budget,
+                None => return Err("--time-budget must be a number of seconds".into()),
+            },
+            None => Duration::MAX,
+        };
+        CompressionLevel::Auto { target_ratio, time_budget }
+    } else if let Some(depth) = matches.value_of("lazy") {
+        match depth.parse::<usize>() {
+            Ok(depth @ 1..=2) => CompressionLevel::Lazy { depth },
+            _ => return Err("--lazy must be 1 or 2".into()),
+        }
+    } else if matches.is_present("nintendo") {
+        CompressionLevel::MatchNintendo
+    } else if matches.is_present("naive") {
+        CompressionLevel::Naive { quality }
+    } else if matches.is_present("fast") {
+        CompressionLevel::HashChain { quality }
+    } else {
+        CompressionLevel::Lookahead { quality }
+    })
+}
+
+/// Reads the SARC archive at `path`, decompressing it first if it's Yaz0-compressed.
+fn read_sarc(path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+
+    if data.starts_with(b"Yaz0") {
+        data = yaz0::decompress(&data)?;
+    }
+    Ok(data)
+}
+
+/// Whether a path stored in an archive stays inside the directory it's extracted to.
+fn is_contained(path: &Path) -> bool {
+    path.components().all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// A file with anomalies was rejected by `--strict`.
+#[derive(Debug)]
+struct Anomalous(usize);
+
+impl fmt::Display for Anomalous {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} anomalies found; not proceeding with --strict", self.0)
+    }
+}
+
+impl Error for Anomalous {}
+
+/// Picks an exit code for a failure, so that scripts can tell bad usage, bad data and IO trouble
+/// apart. Errors that are neither codec nor IO errors come from argument validation.
+fn exit_code_for(err: &(dyn Error + 'static)) -> i32 {
+    if let Some(err) = err.downcast_ref::<yaz0::Error>() {
+        err.exit_code()
+    } else if err.is::<Anomalous>() {
+        exit_code::DATA_ERROR
+    } else if err.is::<std::io::Error>() {
+        exit_code::IO_ERROR
+    } else {
+        exit_code::USAGE
+    }
+}
+
+/// How long `watch` waits for a burst of file events to die down before compressing anything,
+/// since editors often save a file in several steps.
+#[cfg(not(target_os = "wasi"))]
+const WATCH_SETTLE: Duration = Duration::from_millis(200);
+
+/// Whether `watch` should leave `path` alone: hidden files, and editors' swap and backup files,
+/// which come and go as files are edited.
+#[cfg(not(target_os = "wasi"))]
+fn is_scratch_file(path: &Path) -> bool {
+    match path.file_name() {
+        Some(name) => {
+            let name = name.to_string_lossy();
+            name.starts_with('.') || name.ends_with('~')
+        }
+        None => true,
+    }
+}
+
+/// Compresses the file at `path` to `out_path` for `watch`, writing a temporary file first so
+/// that anything reloading the output never sees it half-written. Returns the sizes before and
+/// after.
+#[cfg(not(target_os = "wasi"))]
+fn compress_to(path: &Path, out_path: &Path, level: CompressionLevel) -> Result<(usize, usize), Box<dyn Error>> {
+    let data = fs::read(path)?;
+    let mut deflated = Vec::new();
+    Yaz0Writer::new(&mut deflated).compress_and_write(&data, level)?;
+
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut tmp_path = out_path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, &deflated)?;
+    fs::rename(&tmp_path, out_path)?;
+
+    Ok((data.len(), deflated.len()))
+}
+
+/// Watches `src_dir`, compressing each file saved under it to the same place under `dst_dir`,
+/// with its extension replaced by `extension`, until interrupted.
+#[cfg(not(target_os = "wasi"))]
+fn watch(
+    src_dir: &Path,
+    dst_dir: &Path,
+    level: CompressionLevel,
+    extension: &str,
+    json: bool,
+) -> Result<(), Box<dyn Error>> {
+    use notify::{EventKind, RecursiveMode, Watcher};
+
+    // events name files by where they were found under the watched path, so make both absolute
+    fs::create_dir_all(dst_dir)?;
+    let src_dir = src_dir.canonicalize()?;
+    let dst_dir = dst_dir.canonicalize()?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&src_dir, RecursiveMode::Recursive)?;
+    eprintln!("watching {} for changes; press Ctrl-C to stop", src_dir.display());
+
+    while let Ok(event) = rx.recv() {
+        // gather up the rest of the burst, so that each file is only compressed once
+        let mut changed = BTreeSet::new();
+        let mut next = Some(event);
+        while let Some(event) = next {
+            match event {
+                Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                    changed.extend(event.paths);
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("warning: {}", e),
+            }
+            next = rx.recv_timeout(WATCH_SETTLE).ok();
+        }
+
+        for path in changed {
+            // skip directories, files deleted again since, and our own output, should it be
+            // written inside the source directory
+            if !path.is_file() || path.starts_with(&dst_dir) || is_scratch_file(&path) {
+                continue;
+            }
+            let out_path = match path.strip_prefix(&src_dir) {
+                Ok(relative) => dst_dir.join(relative).with_extension(extension),
+                Err(_) => continue,
+            };
+
+            match compress_to(&path, &out_path, level) {
+                Ok((size, compressed_size)) => {
+                    let ratio = compressed_size as f64 / size.max(1) as f64;
+                    if json {
+                        println!("{}", json!({
+                            "path": path.display().to_string(),
+                            "output": out_path.display().to_string(),
+                            "ok": true,
+                            "input_size": size,
+                            "compressed_size": compressed_size,
+                            "ratio": ratio,
+                        }));
+                    } else {
+                        println!(
+                            "{} -> {}: {} -> {} bytes ({:.1}%)",
+                            path.display(),
+                            out_path.display(),
+                            size,
+                            compressed_size,
+                            ratio * 100.,
+                        );
+                    }
+                }
+                Err(e) => {
+                    if json {
+                        println!("{}", json!({
+                            "path": path.display().to_string(),
+                            "output": out_path.display().to_string(),
+                            "ok": false,
+                            "error": error_chain(&*e),
+                        }));
+                    } else {
+                        println!("{}: FAILED: {}", path.display(), e);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("error: {}", err);
+        process::exit(exit_code_for(&*err));
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
     let matches = App::new("yaztool")
         .author("Erin Moon <erin@hashbang.sh>")
         .about("(de)compresses Yaz0 files")
         .setting(AppSettings::ArgRequiredElseHelp)
+        .arg(Arg::with_name("json")
+            .long("json")
+            .global(true)
+            .help("Print results as JSON, for scripts: sizes and ratios, and any errors for each file"))
         .subcommand(SubCommand::with_name("decompress")
                     .arg(Arg::with_name("INPUT")
                         .required(true))
                     .arg(Arg::with_name("OUTPUT")
-                        .required(true)))
+                        .required(true))
+                    .arg(Arg::with_name("mmap")
+                        .long("mmap")
+                        .help("Decompress straight into a memory-mapped output file, rather than buffering in memory"))
+                    .arg(Arg::with_name("volumes")
+                        .long("volumes")
+                        .help("Read the input from volumes INPUT.000, INPUT.001, ... written with `compress --split-size`"))
+                    .arg(Arg::with_name("no-header")
+                        .long("no-header")
+                        .help("Treat the input as a bare packet stream, without a Yaz0 header"))
+                    .arg(Arg::with_name("size")
+                        .long("size")
+                        .takes_value(true)
+                        .value_name("BYTES")
+                        .requires("no-header")
+                        .help("The decompressed size of a --no-header stream; if not given, decode until the input runs out"))
+                    .arg(Arg::with_name("strict")
+                        .long("strict")
+                        .conflicts_with("lenient")
+                        .help("Fail on any anomaly in the input, like trailing data or a nonzero reserved field"))
+                    .arg(Arg::with_name("lenient")
+                        .long("lenient")
+                        .help("Print a warning for each anomaly in the input, and carry on")))
         .subcommand(SubCommand::with_name("compress")
             .arg(Arg::with_name("INPUT")
                 .required(true))
             .arg(Arg::with_name("OUTPUT")
+                .required(true))
+            .arg(Arg::with_name("no-header")
+                .long("no-header")
+                .help("Write only the bare packet stream, without a Yaz0 header"))
+            .args(&level_args())
+            .arg(Arg::with_name("split-size")
+                .long("split-size")
+                .takes_value(true)
+                .value_name("BYTES")
+                .help("Split the output across volumes OUTPUT.000, OUTPUT.001, ... of at most BYTES each")))
+        .subcommand(SubCommand::with_name("recompress")
+            .about("Decompresses a Yaz0 file and compresses it again, keeping its header's alignment and byte order")
+            .arg(Arg::with_name("INPUT")
+                .required(true))
+            .arg(Arg::with_name("OUTPUT")
+                .required(true)
+                .help("Where to write the recompressed file; can be the same as INPUT"))
+            .args(&level_args())
+            .arg(Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .help("Print the file's size before and after")))
+        .subcommand(SubCommand::with_name("check")
+            .about("Checks that files are well-formed, without decompressing them")
+            .arg(Arg::with_name("INPUT")
+                .required(true)
+                .multiple(true)))
+        .subcommand(SubCommand::with_name("verify")
+            .about("Decompresses files without writing the output anywhere, to check that they decode to exactly the size in their headers")
+            .arg(Arg::with_name("INPUT")
+                .required(true)
+                .multiple(true))
+            .arg(Arg::with_name("strict")
+                .long("strict")
+                .help("Also fail on any anomaly in the input, like trailing data or a nonzero reserved field")))
+        .subcommand(SubCommand::with_name("info")
+            .about("Prints a Yaz0 file's header fields")
+            .arg(Arg::with_name("INPUT")
+                .required(true))
+            .arg(Arg::with_name("stats")
+                .long("stats")
+                .help("Also print a breakdown of the packets making up the compressed stream")))
+        .subcommand(SubCommand::with_name("stats")
+            .about("Prints a breakdown of the packets making up a Yaz0 file's compressed stream")
+            .arg(Arg::with_name("INPUT")
+                .required(true)))
+        .subcommand(SubCommand::with_name("list")
+            .about("Lists the files in a SARC archive, Yaz0-compressed or not")
+            .arg(Arg::with_name("INPUT")
+                .required(true)))
+        .subcommand(SubCommand::with_name("extract")
+            .about("Extracts the files in a SARC archive, Yaz0-compressed or not, into a directory")
+            .arg(Arg::with_name("INPUT")
+                .required(true))
+            .arg(Arg::with_name("OUTPUT")
+                .required(true)))
+        .subcommand(SubCommand::with_name("type")
+            .about("Reports the container format of a file, and the format of the data inside it")
+            .arg(Arg::with_name("INPUT")
+                .required(true)))
+        .subcommand(SubCommand::with_name("diff")
+            .about("Reports the ranges at which the decompressed contents of two files differ")
+            .arg(Arg::with_name("A")
+                .required(true))
+            .arg(Arg::with_name("B")
+                .required(true))
+            .arg(Arg::with_name("hex")
+                .long("hex")
+                .help("Print the start of each differing range from both files")))
+        .subcommand(SubCommand::with_name("watch")
+            .about("Watches a directory, compressing files into another as they're saved, until interrupted")
+            .arg(Arg::with_name("SRC_DIR")
+                .required(true))
+            .arg(Arg::with_name("DST_DIR")
+                .required(true)
+                .help("Where to write the compressed files, in the same layout as SRC_DIR"))
+            .args(&level_args())
+            .arg(Arg::with_name("extension")
+                .long("extension")
+                .takes_value(true)
+                .value_name("EXT")
+                .default_value("szs")
+                .help("The extension to give the compressed files, replacing their own")))
+        .subcommand(SubCommand::with_name("dump")
+            .about("Prints the structure of a compressed stream as JSON")
+            .arg(Arg::with_name("INPUT")
                 .required(true)))
         .get_matches();
 
@@ -36,43 +623,332 @@ fn main() -> Result<(), Box<dyn Error>> {
             let in_path = Path::new(matches.value_of("INPUT").unwrap());
             let out_path = Path::new(matches.value_of("OUTPUT").unwrap());
 
-            let reader = BufReader::new(File::open(in_path)?);
-
-            let mut yazfile = Yaz0Archive::new(reader)?;
-            let inflated = yazfile.decompress()?;
+            let size = match matches.value_of("size") {
+                Some(size) => Some(size.parse::<usize>().map_err(|_| "--size must be an integer")?),
+                None => None,
+            };
+            let headerless = matches.is_present("no-header");
 
-            let mut outfile = File::create(out_path)?;
-            outfile.write_all(&inflated)?;
+            if matches.is_present("volumes") {
+                let reader = BufReader::new(VolumeReader::open(in_path)?);
+                let yazfile = if headerless {
+                    Yaz0Archive::from_raw(reader, size.unwrap_or(0))?
+                } else {
+                    Yaz0Archive::new(reader)?
+                };
+                write_decompressed(yazfile, matches, size, out_path)?;
+            } else {
+                let input = read_input(in_path)?;
+                let yazfile = if headerless {
+                    Yaz0Archive::from_raw(Cursor::new(&input[..]), size.unwrap_or(0))?
+                } else {
+                    Yaz0Archive::from_bytes(&input)?
+                };
+                write_decompressed(yazfile, matches, size, out_path)?;
+            }
         },
         ("compress", Some(matches)) => {
             let in_path = Path::new(matches.value_of("INPUT").unwrap());
             let out_path = Path::new(matches.value_of("OUTPUT").unwrap());
-
-            let data = {
-                let mut d = Vec::new();
-                File::open(in_path)?.read_to_end(&mut d)?;
-                d
+            let split_size = match matches.value_of("split-size") {
+                Some(size) => Some(size.parse::<u64>().map_err(|_| "--split-size must be a positive integer")?),
+                None => None,
             };
 
-            let pb = ProgressBar::new(data.len() as u64);
-            let (tx, rx) = mpsc::channel::<ProgressMsg>();
-            thread::spawn(move || {
-                while let Ok(progress) = rx.recv() {
-                    pb.set_position(progress.read_head as u64);
+            let data = read_input(in_path)?;
+
+            let level = compression_level(matches)?;
+            let headerless = matches.is_present("no-header");
+            let deflated = compress(&data, level, |writer| if headerless { writer.headerless() } else { writer })?;
+
+            let ratio = deflated.len() as f64 / data.len().max(1) as f64;
+            if matches.is_present("json") {
+                println!("{}", json!({ "input_size": data.len(), "compressed_size": deflated.len(), "ratio": ratio }));
+            } else {
+                println!("{} -> {} bytes ({:.1}%)", data.len(), deflated.len(), ratio * 100.);
+            }
+
+            match split_size {
+                Some(0) => return Err("--split-size must be a positive integer".into()),
+                Some(size) => {
+                    let mut volumes = VolumeWriter::new(out_path, size);
+                    volumes.write_all(&deflated)?;
+                    volumes.flush()?;
+                }
+                None => {
+                    let mut outfile = File::create(out_path)?;
+                    outfile.write_all(&deflated)?;
+                }
+            }
+        },
+        ("recompress", Some(matches)) => {
+            let in_path = Path::new(matches.value_of("INPUT").unwrap());
+            let out_path = Path::new(matches.value_of("OUTPUT").unwrap());
+            let level = compression_level(matches)?;
+
+            let input = read_input(in_path)?;
+            let original_len = input.len();
+            let mut yazfile = Yaz0Archive::from_bytes(&input)?;
+            let (alignment, endianness) = (yazfile.alignment(), yazfile.endianness());
+            let data = decompress(&mut yazfile)?;
+            // let go of the input before writing, in case it's mapped and being overwritten
+            drop(yazfile);
+            drop(input);
+
+            let recompressed = compress(&data, level, |writer| writer.alignment(alignment).endianness(endianness))?;
+            File::create(out_path)?.write_all(&recompressed)?;
+
+            let ratio = recompressed.len() as f64 / original_len.max(1) as f64;
+            if matches.is_present("json") {
+                println!("{}", json!({ "original_size": original_len, "compressed_size": recompressed.len(), "ratio": ratio }));
+            } else if matches.is_present("verbose") {
+                println!("{} -> {} bytes ({:+.1}%)", original_len, recompressed.len(), (ratio - 1.) * 100.);
+            }
+        },
+        ("check", Some(matches)) => {
+            let json = matches.is_present("json");
+            let mut results = Vec::new();
+            let mut status = 0;
+            for in_path in matches.values_of("INPUT").unwrap().map(Path::new) {
+                let result = File::open(in_path)
+                    .map_err(yaz0::Error::from)
+                    .and_then(|file| Yaz0Archive::new(BufReader::new(file)))
+                    .and_then(|mut yazfile| {
+                        yazfile.verify()?;
+                        yazfile.anomalies()
+                    });
+
+                match result {
+                    Ok(report) if json => results.push(file_json(in_path, None, &report.anomalies, None)),
+                    Ok(report) => {
+                        println!("{}: ok", in_path.display());
+                        for anomaly in &report.anomalies {
+                            println!("  note: {}", anomaly);
+                        }
+                    }
+                    Err(e) => {
+                        if json {
+                            results.push(file_json(in_path, None, &[], Some(&e)));
+                        } else {
+                            println!("{}: FAILED: {}", in_path.display(), e);
+                        }
+                        status = status.max(e.exit_code());
+                    }
                 }
-            });
-
-            let quality = CompressionLevel::Lookahead {quality: 10};
-            let deflated = {
-                let mut d = Vec::new();
-                Yaz0Writer::new(&mut d)
-                    .compress_and_write_with_progress(&data, quality, tx)?;
-                d
+            }
+
+            if json {
+                println!("{}", Value::Array(results));
+            }
+            if status != 0 {
+                process::exit(status);
+            }
+        },
+        ("verify", Some(matches)) => {
+            let json = matches.is_present("json");
+            let mut results = Vec::new();
+            let mut status = 0;
+            for in_path in matches.values_of("INPUT").unwrap().map(Path::new) {
+                let result = File::open(in_path)
+                    .map_err(yaz0::Error::from)
+                    .and_then(|file| {
+                        let mut yazfile = Yaz0Archive::new(BufReader::new(file))?.annotate_errors();
+                        let size = yazfile.decompress_to_writer(&mut std::io::sink())?;
+                        Ok((size, yazfile.anomalies()?))
+                    });
+
+                match result {
+                    Ok((size, report)) => {
+                        let rejected = (matches.is_present("strict") && !report.is_clean())
+                            .then_some(Anomalous(report.anomalies.len()));
+                        if json {
+                            let err = rejected.as_ref().map(|err| err as &dyn Error);
+                            results.push(file_json(in_path, Some(size as usize), &report.anomalies, err));
+                        } else {
+                            for anomaly in &report.anomalies {
+                                println!("{}: note: {}", in_path.display(), anomaly);
+                            }
+                            match &rejected {
+                                Some(err) => println!("{}: FAILED: {}", in_path.display(), err),
+                                None => println!("{}: ok ({} bytes)", in_path.display(), size),
+                            }
+                        }
+                        if rejected.is_some() {
+                            status = status.max(exit_code::DATA_ERROR);
+                        }
+                    }
+                    Err(e) => {
+                        if json {
+                            results.push(file_json(in_path, None, &[], Some(&e)));
+                        } else {
+                            println!("{}: FAILED: {}", in_path.display(), e);
+                        }
+                        status = status.max(e.exit_code());
+                    }
+                }
+            }
+
+            if json {
+                println!("{}", Value::Array(results));
+            }
+            if status != 0 {
+                process::exit(status);
+            }
+        },
+        ("info", Some(matches)) => {
+            let in_path = Path::new(matches.value_of("INPUT").unwrap());
+
+            let mut reader = BufReader::new(File::open(in_path)?);
+            let header = yaz0::Yaz0Header::parse(&mut reader)?;
+            if matches.is_present("json") {
+                let mut info = json!({
+                    "decompressed_size": header.expected_size,
+                    "alignment": header.alignment,
+                    "reserved": header.reserved,
+                    "endianness": if header.endianness == yaz0::Endianness::Little { "little" } else { "big" },
+                });
+                if matches.is_present("stats") {
+                    reader.rewind()?;
+                    info["stats"] = stats_json(&Yaz0Archive::new(reader)?.inflate_stats()?);
+                }
+                println!("{}", info);
+                return Ok(());
+            }
+
+            println!("decompressed size: {}", header.expected_size);
+            println!("alignment:         {:#x}", header.alignment);
+            println!("reserved:          {:#010x}", header.reserved);
+            if header.endianness == yaz0::Endianness::Little {
+                println!("byte order:        little-endian");
+            }
+
+            if matches.is_present("stats") {
+                reader.rewind()?;
+                print_stats(&Yaz0Archive::new(reader)?.inflate_stats()?);
+            }
+        },
+        ("stats", Some(matches)) => {
+            let in_path = Path::new(matches.value_of("INPUT").unwrap());
+
+            let mut yazfile = Yaz0Archive::new(BufReader::new(File::open(in_path)?))?;
+            let stats = yazfile.inflate_stats()?;
+            if matches.is_present("json") {
+                let mut json = stats_json(&stats);
+                json["decompressed_size"] = stats.output_len.into();
+                println!("{}", json);
+            } else {
+                println!("decompressed size: {}", stats.output_len);
+                print_stats(&stats);
+            }
+        },
+        ("list", Some(matches)) => {
+            let in_path = Path::new(matches.value_of("INPUT").unwrap());
+
+            let data = read_sarc(in_path)?;
+            for entry in yaz0::sarc::entries(&data)? {
+                println!("{:>10}  {}", entry.data.len(), entry.display_name());
+            }
+        },
+        ("extract", Some(matches)) => {
+            let in_path = Path::new(matches.value_of("INPUT").unwrap());
+            let out_dir = Path::new(matches.value_of("OUTPUT").unwrap());
+
+            let data = read_sarc(in_path)?;
+            for entry in yaz0::sarc::entries(&data)? {
+                let name = entry.display_name();
+                if !is_contained(Path::new(&name)) {
+                    return Err(format!("refusing to extract {:?} outside of the output directory", name).into());
+                }
+
+                let path = out_dir.join(&name);
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                File::create(&path)?.write_all(entry.data)?;
+            }
+        },
+        ("type", Some(matches)) => {
+            let in_path = Path::new(matches.value_of("INPUT").unwrap());
+
+            let mut reader = BufReader::new(File::open(in_path)?);
+            let mut head = Vec::new();
+            (&mut reader).take(SNIFF_LEN as u64).read_to_end(&mut head)?;
+
+            match &head.get(..4) {
+                Some(b"Yaz0") => {
+                    reader.rewind()?;
+                    let (format, _) = Yaz0Archive::new(reader)?.detect_inner_format()?;
+                    println!("{}: Yaz0, containing {}", in_path.display(), format.name());
+                }
+                Some(magic @ (b"Yaz1" | b"Yay0")) => {
+                    // same idea, but not something we can decompress to look inside
+                    println!("{}: {}, containing unknown", in_path.display(), String::from_utf8_lossy(magic));
+                }
+                _ => {
+                    let (format, _) = InnerFormat::identify(&head);
+                    println!("{}: uncompressed {}", in_path.display(), format.name());
+                }
+            }
+        },
+        ("diff", Some(matches)) => {
+            let a_path = Path::new(matches.value_of("A").unwrap());
+            let b_path = Path::new(matches.value_of("B").unwrap());
+
+            let ranges = yaz0::diff_streams(
+                BufReader::new(File::open(a_path)?),
+                BufReader::new(File::open(b_path)?),
+            )?;
+            if ranges.is_empty() {
+                return Ok(());
+            }
+
+            // only decompress everything up front if we need to show excerpts
+            let contents = if matches.is_present("hex") {
+                let a = Yaz0Archive::new(BufReader::new(File::open(a_path)?))?.decompress()?;
+                let b = Yaz0Archive::new(BufReader::new(File::open(b_path)?))?.decompress()?;
+                Some((a, b))
+            } else {
+                None
             };
 
-            let mut outfile = File::create(out_path)?;
-            outfile.write_all(&deflated)?;
+            let differing: usize = ranges.iter().map(|range| range.len()).sum();
+            println!("{} differing ranges, {} bytes in total", ranges.len(), differing);
+            for range in ranges {
+                println!("{:#010x}..{:#010x} ({} bytes)", range.start, range.end, range.len());
+
+                if let Some((a, b)) = &contents {
+                    let excerpt = |data: &[u8]| {
+                        data.iter()
+                            .skip(range.start)
+                            .take(range.len().min(16))
+                            .map(|byte| format!("{:02x}", byte))
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    };
+                    println!("  a: {}", excerpt(a));
+                    println!("  b: {}", excerpt(b));
+                }
+            }
+
+            // like cmp(1), differences are reported with an exit code of 1
+            process::exit(1);
+        },
+        ("dump", Some(matches)) => {
+            let in_path = Path::new(matches.value_of("INPUT").unwrap());
+
+            let reader = BufReader::new(File::open(in_path)?);
+            println!("{}", yaz0::analysis::structure(reader)?.to_json());
+        },
+        #[cfg(not(target_os = "wasi"))]
+        ("watch", Some(matches)) => {
+            let src_dir = Path::new(matches.value_of("SRC_DIR").unwrap());
+            let dst_dir = Path::new(matches.value_of("DST_DIR").unwrap());
+            let level = compression_level(matches)?;
+
+            watch(src_dir, dst_dir, level, matches.value_of("extension").unwrap(), matches.is_present("json"))?;
         },
+        #[cfg(target_os = "wasi")]
+        ("watch", Some(_)) => return Err("watch isn't available on wasm32-wasi, which can't watch for file changes".into()),
         _ => unreachable!(),
     }
 
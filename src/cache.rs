@@ -0,0 +1,129 @@
+//! An on-disk cache of compressed data, for build pipelines that compress the same (unchanged)
+//! files over and over.
+//!
+//! Entries are keyed by the digest of the uncompressed data along with the
+//! [settings hash](crate::CompressionLevel::settings_hash) of the compression level, so changing
+//! either the data or the settings misses the cache. Each entry is a complete Yaz0 file.
+
+use digest::Digest;
+use std::fs;
+use std::io;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::deflate::{CompressionLevel, Yaz0Writer};
+use crate::Error;
+
+/// Counts the temporary files written by this process, so that threads adding the same entry at
+/// once each write their own.
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A cache of compressed files in a directory, keyed with the digest `D`.
+/// Use a collision-resistant digest (like SHA-256), since hits aren't checked against the input.
+#[derive(Debug, Clone)]
+pub struct CompressionCache<D> {
+    dir: PathBuf,
+    _digest: PhantomData<D>,
+}
+
+impl<D> CompressionCache<D>
+where
+    D: Digest,
+{
+    /// Creates a cache storing its entries in `dir`, which is created when first needed.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> CompressionCache<D> {
+        CompressionCache {
+            dir: dir.into(),
+            _digest: PhantomData,
+        }
+    }
+
+    /// Returns the path of the entry for `data` compressed at `level`.
+    fn entry_path(&self, data: &[u8], level: CompressionLevel) -> PathBuf {
+        let mut name: String = D::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect();
+        name.push_str(&format!("-{:08x}.yaz0", level.settings_hash()));
+        self.dir.join(name)
+    }
+
+    /// Looks up the Yaz0 file for `data` compressed at `level`, if it's been cached.
+    pub fn get(&self, data: &[u8], level: CompressionLevel) -> Result<Option<Vec<u8>>, Error> {
+        match fs::read(self.entry_path(data, level)) {
+            Ok(compressed) => Ok(Some(compressed)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns `data` compressed at `level` into a Yaz0 file, from the cache if it's there, and
+    /// otherwise compressing it and adding it to the cache.
+    pub fn compress(&self, data: &[u8], level: CompressionLevel) -> Result<Vec<u8>, Error> {
+        let path = self.entry_path(data, level);
+        if let Some(compressed) = self.get(data, level)? {
+            return Ok(compressed);
+        }
+
+        let mut compressed = Vec::new();
+        Yaz0Writer::new(&mut compressed).compress_and_write(data, level)?;
+
+        // write to a temporary file first, so that other processes (and threads) never see a
+        // partial entry
+        fs::create_dir_all(&self.dir)?;
+        let unique = TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let tmp_path = path.with_extension(format!("tmp{}-{}", std::process::id(), unique));
+        fs::write(&tmp_path, &compressed)?;
+        fs::rename(&tmp_path, &path)?;
+
+        Ok(compressed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sha2::Sha256;
+
+    #[test]
+    fn hit_and_miss() {
+        let dir = std::env::temp_dir().join(format!("yaz0-cache-{}", std::process::id()));
+        let cache = CompressionCache::<Sha256>::new(&dir);
+
+        let data: &[u8] = &include_bytes!("../data/test")[..0x2000];
+        let level = CompressionLevel::Naive { quality: 10 };
+        assert!(cache.get(data, level).unwrap().is_none());
+
+        let compressed = cache.compress(data, level).unwrap();
+        assert_eq!(cache.get(data, level).unwrap().as_deref(), Some(&compressed[..]));
+        assert_eq!(cache.compress(data, level).unwrap(), compressed);
+
+        // different settings or data miss the cache
+        assert!(cache.get(data, CompressionLevel::Lookahead { quality: 10 }).unwrap().is_none());
+        assert!(cache.get(&data[1..], level).unwrap().is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_compress() {
+        let dir = std::env::temp_dir().join(format!("yaz0-cache-concurrent-{}", std::process::id()));
+        let cache = CompressionCache::<Sha256>::new(&dir);
+
+        let data: &[u8] = &include_bytes!("../data/test")[..0x8000];
+        let level = CompressionLevel::Naive { quality: 10 };
+        let expected = cache.compress(data, level).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let results: Vec<Vec<u8>> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..8).map(|_| scope.spawn(|| cache.compress(data, level).unwrap())).collect();
+            workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+        });
+        let entries: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        let cached = cache.get(data, level).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(results.iter().all(|result| *result == expected));
+        assert_eq!(cached, Some(expected));
+        assert_eq!(entries.len(), 1, "{:?}", entries);
+    }
+}
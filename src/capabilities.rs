@@ -0,0 +1,118 @@
+//! Querying what this build of the library supports at runtime.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// What this build of the library supports, as returned by [`capabilities`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// The version of this crate.
+    pub version: &'static str,
+    /// Names of the compressed formats that can be read and written.
+    pub formats: Vec<&'static str>,
+    /// Names of the available [`CompressionLevel`](crate::CompressionLevel) variants, in
+    /// snake case.
+    pub compression_levels: Vec<&'static str>,
+    /// Which optional features are available.
+    pub features: Features,
+}
+
+/// Optional features, which depend on Cargo features or on the target platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[non_exhaustive]
+pub struct Features {
+    /// Multithreaded APIs, like [`pipeline`](crate::pipeline) and
+    /// [`shared`](crate::shared), can spawn threads.
+    pub threads: bool,
+    /// Files can be read from many threads at once through one handle, with
+    /// [`positioned`](crate::positioned).
+    pub positioned_io: bool,
+    /// Decompressed data can be hashed as it's produced (the `digest` feature).
+    pub digest: bool,
+    /// Data can be transcoded to and from gzip and zlib (the `flate2` feature).
+    pub transcode: bool,
+    /// Decompression into `SmallVec`s (the `smallvec` feature).
+    pub smallvec: bool,
+    /// Decompression into `BytesMut`s (the `bytes` feature).
+    pub bytes: bool,
+    /// Analysis results can be serialized (the `serde` feature).
+    pub serde: bool,
+    /// Stream structure can be dumped as JSON (the `json` feature).
+    pub json: bool,
+    /// Decompression and compression over `tokio`'s async i/o traits (the `async` feature).
+    pub async_io: bool,
+    /// JavaScript bindings through `wasm-bindgen` (the `wasm` feature).
+    pub wasm: bool,
+    /// The C interface in [`ffi`](crate::ffi) (the `ffi` feature).
+    pub ffi: bool,
+}
+
+/// Returns what this build of the library supports, for callers (like FFI consumers and plugin
+/// hosts) that can't check at compile time.
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        formats: vec!["yaz0", "yay0", "mio0"],
+        compression_levels: vec!["naive", "lookahead", "hash_chain", "match_nintendo", "lazy", "store", "auto"],
+        features: Features {
+            threads: cfg!(not(target_os = "wasi")),
+            positioned_io: cfg!(any(unix, windows)),
+            digest: cfg!(feature = "digest"),
+            transcode: cfg!(feature = "flate2"),
+            smallvec: cfg!(feature = "smallvec"),
+            bytes: cfg!(feature = "bytes"),
+            serde: cfg!(feature = "serde"),
+            json: cfg!(feature = "json"),
+            async_io: cfg!(feature = "async"),
+            wasm: cfg!(feature = "wasm"),
+            ffi: cfg!(feature = "ffi"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::CompressionLevel;
+    use std::time::Duration;
+
+    #[test]
+    fn reports_build() {
+        let caps = capabilities();
+        assert_eq!(caps.version, env!("CARGO_PKG_VERSION"));
+        assert!(caps.formats.contains(&"yaz0"));
+        assert_eq!(caps.features.digest, cfg!(feature = "digest"));
+    }
+
+    #[test]
+    fn lists_every_level() {
+        let levels = [
+            CompressionLevel::Naive { quality: 10 },
+            CompressionLevel::Lookahead { quality: 10 },
+            CompressionLevel::HashChain { quality: 10 },
+            CompressionLevel::MatchNintendo,
+            CompressionLevel::Lazy { depth: 2 },
+            CompressionLevel::Store,
+            CompressionLevel::Auto { target_ratio: 0.5, time_budget: Duration::MAX },
+        ];
+
+        let caps = capabilities();
+        for level in levels {
+            // matched exhaustively, so that new levels can't be left out
+            let name = match level {
+                CompressionLevel::Naive { .. } => "naive",
+                CompressionLevel::Lookahead { .. } => "lookahead",
+                CompressionLevel::HashChain { .. } => "hash_chain",
+                CompressionLevel::MatchNintendo => "match_nintendo",
+                CompressionLevel::Lazy { .. } => "lazy",
+                CompressionLevel::Store => "store",
+                CompressionLevel::Auto { .. } => "auto",
+            };
+            assert!(caps.compression_levels.contains(&name), "{} isn't listed", name);
+        }
+        assert_eq!(caps.compression_levels.len(), levels.len());
+    }
+}
@@ -0,0 +1,97 @@
+//! CRC-32 checksums of decompressed data, which some community tools store alongside Yaz0 files
+//! to catch corruption the format itself can't detect.
+//!
+//! The checksum is the common IEEE CRC-32 (as used by zlib and PNG), stored either in the header's
+//! reserved field or as a four-byte trailer after the payload; see [`ChecksumLocation`].
+
+use std::io::{self, Write};
+
+/// Where a file's checksum is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChecksumLocation {
+    /// In the header's reserved field, replacing anything else stored there.
+    Reserved,
+    /// In four bytes appended after the compressed payload, in the header's byte order.
+    Trailer,
+}
+
+/// The lookup table for the reflected IEEE polynomial.
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Computes a CRC-32 incrementally, as data is written to it.
+#[derive(Debug, Clone)]
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32::new()
+    }
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32 { crc: 0xffff_ffff }
+    }
+
+    /// Adds `data` to the checksum.
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.crc = TABLE[((self.crc ^ byte as u32) & 0xff) as usize] ^ (self.crc >> 8);
+        }
+    }
+
+    /// Returns the checksum of everything added so far.
+    pub fn finish(&self) -> u32 {
+        !self.crc
+    }
+}
+
+impl Write for Crc32 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the CRC-32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+        let mut crc = Crc32::new();
+        crc.write_all(b"1234").unwrap();
+        crc.write_all(b"56789").unwrap();
+        assert_eq!(crc.finish(), 0xcbf4_3926);
+    }
+}
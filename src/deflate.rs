@@ -1,7 +1,13 @@
 use arrayvec::{self, ArrayVec};
-use crate::header::Yaz0Header;
-use std::io::Write;
-use std::sync::mpsc::{self, Sender};
+use byteorder::{BigEndian, ByteOrder};
+use crate::checksum::{crc32, ChecksumLocation};
+use crate::header::{Endianness, Yaz0Header};
+use crate::ops::{encode_ops as encode_packets, ByteIter, Op, OpIter, WINDOW_SIZE};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::stats::{CompressionStats, PacketStats};
 use crate::Error;
 
 pub struct Yaz0Writer<'a, W: 'a>
@@ -9,10 +15,39 @@ where
     W: Write,
 {
     writer: &'a mut W,
+
+    /// The compressed payload is padded to start at a multiple of this many bytes from the
+    /// start of the header.
+    payload_alignment: usize,
+
+    /// Whether to record [`CompressionLevel::settings_hash`] in the header's reserved field.
+    record_settings: bool,
+
+    /// How long compression should take at most, if it's bounded.
+    deadline: Option<Duration>,
+
+    /// Whether to leave out the header, writing only the packet stream.
+    headerless: bool,
+
+    /// Values for the header's alignment and reserved fields.
+    alignment: u32,
+    reserved: u32,
+
+    /// The byte order to write the header in.
+    endianness: Endianness,
+
+    /// Limits on the runs the compressor may use.
+    options: CompressionOptions,
+
+    /// Whether to store the input as literals if compressing it doesn't make it any smaller.
+    store_incompressible: bool,
+
+    /// Where to store a checksum of the input, if anywhere.
+    checksum: Option<ChecksumLocation>,
 }
 
 /// Represents a compression run of length `length` starting at `cursor`.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Run {
     pub cursor: usize,
     pub length: usize,
@@ -38,69 +73,280 @@ impl Run {
 }
 
 
-/// Message sent by the compressor to inform other threads of the compression progress.
+/// Progress report from the compressor, passed to a progress callback or sent over a channel.
 #[derive(Debug)]
 pub struct ProgressMsg {
+    /// How much of the input has been compressed so far.
     pub read_head: usize,
+    /// Size of the compressed payload produced so far.
+    pub compressed_len: usize,
+    /// Number of bytes emitted as literals so far.
+    pub literals: usize,
+    /// Number of copy runs emitted so far.
+    pub runs: usize,
+}
+
+impl ProgressMsg {
+    /// The compression ratio achieved so far, as compressed size over input size.
+    pub fn ratio(&self) -> f64 {
+        self.compressed_len as f64 / self.read_head.max(1) as f64
+    }
+
+    /// The fraction of the input compressed so far that was covered by copy runs.
+    pub fn match_rate(&self) -> f64 {
+        (self.read_head - self.literals) as f64 / self.read_head.max(1) as f64
+    }
+}
+
+/// With the `rayon` feature, windows at least this long are searched on several threads, when
+/// the input is at least [PARALLEL_MIN_INPUT] bytes long.
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_LOOKBACK: usize = 0x400;
+
+/// Smaller inputs compress quickly enough that handing work between threads costs more than it
+/// saves.
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_INPUT: usize = 0x4_0000;
+
+/// Repeats of up to this many bytes are checked for before searching the whole window.
+const MAX_REPEAT_PERIOD: usize = 0x10;
+
+/// Looks for the data at `cursor` repeating the last few bytes before it, such as a run of one
+/// byte or a short pattern, which can be copied with a run overlapping itself; zero padding in
+/// game files is the common case.
+///
+/// A repeat is only returned if it's as long as any run here could be, so that the window needn't
+/// be searched for a longer one. The shortest such period is kept, which is the nearest run, as
+/// [find_naive_run] would pick.
+fn find_repeat(src: &[u8], cursor: usize, lookback: usize) -> Option<Run> {
+    let max_len = (src.len() - cursor).min(MAX_RUN);
+
+    (1..=MAX_REPEAT_PERIOD.min(lookback).min(cursor))
+        .map(|period| Run {
+            cursor: cursor - period,
+            length: naive_runlength(src, cursor - period, cursor),
+        })
+        .find(|run| run.length == max_len)
 }
 
 /// Naively looks back in the input stream, trying to find the longest possible
 /// substring that matches the data after the current read cursor.
 fn find_naive_run(src: &[u8], cursor: usize, lookback: usize) -> Run {
+    // long repeats of the last few bytes can't be beaten, so don't bother searching further.
+    if let Some(run) = find_repeat(src, cursor, lookback) {
+        return run;
+    }
+
     // the location which we start searching at, `lookback` bytes before
     // the current read cursor. saturating_sub prevents underflow.
     let search_start = cursor.saturating_sub(lookback);
 
+    #[cfg(feature = "rayon")]
+    {
+        if cursor - search_start >= PARALLEL_MIN_LOOKBACK && src.len() >= PARALLEL_MIN_INPUT {
+            return find_naive_run_parallel(src, cursor, search_start);
+        }
+    }
+
     // the best runlength we've seen so far, and where the match occured.
     let mut run = Run::zero();
 
     for search_head in search_start..cursor {
-        // incremental check for every possible substring after the read head.
-        let mut runlength = 0;
-        while runlength < src.len() - cursor {
-            if src[search_head + runlength] != src[cursor + runlength] {
-                break;
-            }
-            runlength += 1;
-        }
-
         // if this search position was better than we've seen before, update our best run.
         run = run.swap_if_better(Run {
             cursor: search_head,
-            length: runlength,
+            length: naive_runlength(src, search_head, cursor),
+        })
+    }
+
+    run
+}
+
+/// How many bytes from `search_head` on match those from `cursor` on, up to the longest
+/// encodable run.
+fn naive_runlength(src: &[u8], search_head: usize, cursor: usize) -> usize {
+    // incremental check for every possible substring after the read head, stopping at what a
+    // single packet can hold; anything longer would be clipped when written anyway.
+    let max_len = (src.len() - cursor).min(MAX_RUN);
+    let mut runlength = 0;
+    while runlength < max_len {
+        if src[search_head + runlength] != src[cursor + runlength] {
+            break;
+        }
+        runlength += 1;
+    }
+
+    runlength
+}
+
+/// Like [find_naive_run], but splits the window between rayon's threads.
+///
+/// Each search position is checked independently, so the only care needed is in combining them:
+/// `swap_if_better` keeps the later of two equally long runs, and rayon keeps the order of the
+/// positions it reduces, so the nearest of the longest runs still wins, as in the serial search.
+#[cfg(feature = "rayon")]
+fn find_naive_run_parallel(src: &[u8], cursor: usize, search_start: usize) -> Run {
+    use rayon::prelude::*;
+
+    (search_start..cursor)
+        .into_par_iter()
+        // checking one position is quick, so don't split the window any finer than this
+        .with_min_len(0x100)
+        .map(|search_head| Run {
+            cursor: search_head,
+            length: naive_runlength(src, search_head, cursor),
         })
+        .reduce_with(Run::swap_if_better)
+        .unwrap_or_else(Run::zero)
+}
+
+/// Like [find_naive_run], but keeps the furthest of equally long runs rather than the nearest, as
+/// Nintendo's encoder does.
+fn find_furthest_run(src: &[u8], cursor: usize, lookback: usize) -> Run {
+    let mut run = Run::zero();
+
+    let max_len = (src.len() - cursor).min(MAX_RUN);
+    for search_head in cursor.saturating_sub(lookback)..cursor {
+        let runlength = (0..max_len)
+            .take_while(|&i| src[search_head + i] == src[cursor + i])
+            .count();
+
+        if runlength > run.length {
+            run = Run {
+                cursor: search_head,
+                length: runlength,
+            };
+        }
     }
 
     run
 }
 
-/// Looks back in the input stream, finding a naive run; if one is found, it tries
+/// The most bytes [CompressionLevel::Lazy] will look ahead for a better run.
+const MAX_LAZY_DEPTH: usize = 2;
+
+/// Looks back in the input stream, finding a run with `find`; if one is found, it tries
 /// copying a single byte of that run and then finding a new one.
-/// If it's at least two bytes longer than the initial run, it picks that instead and signals
-/// that we need to copy that byte before copying the run.
+/// If it's at least two bytes longer than the initial run, it picks that instead, and tries the
+/// same again from the next byte, up to `depth` bytes ahead, as zlib's lazy matching does.
 ///
-/// Returns a tuple of whether we need to copy an initial byte for a lookahead run, and whatever run was found.
+/// Returns a tuple of how many bytes we need to copy before the run, and whatever run was found.
 ///
-/// This is much better than plain naive search in most cases. It's also pretty much what Nintendo does.
-fn find_lookahead_run(src: &[u8], cursor: usize, lookback: usize) -> (bool, Run) {
+/// This is much better than plain naive search in most cases. With a `depth` of 1, it's also
+/// pretty much what Nintendo does.
+fn find_lookahead_run(
+    src: &[u8],
+    cursor: usize,
+    lookback: usize,
+    depth: usize,
+    find: fn(&[u8], usize, usize) -> Run,
+) -> (usize, Run) {
     // get the best naive run.
-    let run = find_naive_run(src, cursor, lookback);
+    let mut best = (0, find(src, cursor, lookback));
+
+    // was this run worthwhile at all, and could a later one still beat it?
+    if best.1.length >= 3 && best.1.length + 2 <= MAX_RUN {
+        for skip in 1..=depth {
+            // if we look forward another byte and reencode, how does that look?
+            let lookahead_run = find(src, cursor + skip, lookback);
+
+            // if it's +2 better than the best run so far, pick it; otherwise, stop looking.
+            if lookahead_run.length < best.1.length + 2 {
+                break;
+            }
+            best = (skip, lookahead_run);
+        }
+    }
+
+    best
+}
+
+/// How many bits of the first three bytes at a position are kept in [HashChain]'s hashes.
+const HASH_BITS: usize = 15;
+
+/// The longest run that can be encoded in a single packet.
+const MAX_RUN: usize = 0xff + 0x12;
+
+/// Hashes the first three bytes of `bytes`.
+fn hash3(bytes: &[u8]) -> usize {
+    ((bytes[0] as usize) << 10 ^ (bytes[1] as usize) << 5 ^ bytes[2] as usize) & ((1 << HASH_BITS) - 1)
+}
+
+/// Finds runs by following a chain of the earlier positions whose first three bytes hash the same
+/// as those at the cursor, rather than checking every position in the lookback window.
+///
+/// Of equally long runs, the nearest is kept, as with [find_naive_run].
+struct HashChain {
+    /// The latest position with each hash, plus one; zero if there's none.
+    head: Vec<usize>,
+    /// For each position in the window (indexed modulo its size), the previous position with the
+    /// same hash, plus one; zero if there's none.
+    prev: Vec<usize>,
+    /// Positions before this one have been added to the chains.
+    inserted: usize,
+}
 
-    // was this run worthwhile at all?
-    if run.length >= 3 {
-        // if we look forward a single byte and reencode, how does that look?
-        let lookahead_run = find_naive_run(src, cursor + 1, lookback);
+impl HashChain {
+    /// Creates an empty set of chains, which will start from position `start`.
+    fn new(start: usize) -> HashChain {
+        HashChain {
+            head: vec![0; 1 << HASH_BITS],
+            prev: vec![0; WINDOW_SIZE],
+            inserted: start,
+        }
+    }
 
-        // if it's +2 better than the original naive run, pick it.
-        if lookahead_run.length >= run.length + 2 {
-            return (true, lookahead_run);
+    /// Adds every position before `end` to the chains.
+    fn insert_until(&mut self, src: &[u8], end: usize) {
+        while self.inserted < end {
+            let pos = self.inserted;
+            if pos + 3 <= src.len() {
+                let hash = hash3(&src[pos..]);
+                self.prev[pos % WINDOW_SIZE] = self.head[hash];
+                self.head[hash] = pos + 1;
+            }
+            self.inserted += 1;
         }
     }
 
-    return (false, run);
+    /// Finds the longest run (up to the longest encodable) matching the data at `cursor`,
+    /// starting at most `lookback` bytes before it.
+    fn find_run(&mut self, src: &[u8], cursor: usize, lookback: usize) -> Run {
+        self.insert_until(src, cursor);
+
+        let mut run = Run::zero();
+        if cursor + 3 > src.len() {
+            return run;
+        }
+
+        let search_start = cursor.saturating_sub(lookback);
+        let max_len = (src.len() - cursor).min(MAX_RUN);
+
+        let mut candidate = self.head[hash3(&src[cursor..])];
+        while candidate > search_start {
+            let pos = candidate - 1;
+            let length = (0..max_len).take_while(|&i| src[pos + i] == src[cursor + i]).count();
+            if length > run.length {
+                run = Run { cursor: pos, length };
+                if length == max_len {
+                    break;
+                }
+            }
+
+            // chains only ever lead further back
+            let next = self.prev[pos % WINDOW_SIZE];
+            if next >= candidate {
+                break;
+            }
+            candidate = next;
+        }
+
+        run
+    }
 }
 
-/// Writes a [Run] to the `destination`, with the cursor at `read_head`.
+/// Writes a [Run] to the `destination`, with the cursor at `read_head`. The run must already be
+/// clipped to an encodable length. Returns how many bytes the written run covers.
 fn write_run<A>(read_head: usize, run: &Run, destination: &mut ArrayVec<A>) -> usize
 where
     A: arrayvec::Array<Item = u8>,
@@ -108,9 +354,11 @@ where
     // compute how far back the start of the run is from the read head, minus an offset of 1
     // due to the offst, reading the byte before the read head is encoded as dist = 0.
     let dist = read_head - run.cursor - 1;
+    let length = run.length;
+    debug_assert!((3..=MAX_RUN).contains(&length));
 
     // if the run is longer than 18 bytes, we must use a 3-byte packet instead of a 2-byte one.
-    if run.length >= 0x12 {
+    if length >= 0x12 {
         // 3-byte packet. this looks like the following:
         //
         // 1 byte                   2 bytes         3 bytes
@@ -120,10 +368,9 @@ where
 
         destination.push((dist as u32 >> 8) as u8);
         destination.push((dist as u32 & 0xff) as u8);
-        let actual_runlength = run.length.min(0xff + 0x12); // clip to maximum possible runlength
-        destination.push((actual_runlength - 0x12) as u8);
+        destination.push((length - 0x12) as u8);
 
-        return actual_runlength;
+        length
     } else {
         // 2-byte packet. this looks like the following:
         //
@@ -132,35 +379,291 @@ where
         // │ length-2 │ dist (4 msbs) │ dist (8 lsbs) │
         // └──────────┴───────────────┴───────────────┘
 
-        destination.push(((run.length as u8 - 2) << 4) | (dist as u32 >> 8) as u8);
+        destination.push(((length as u8 - 2) << 4) | (dist as u32 >> 8) as u8);
         destination.push((dist as u32 & 0xff) as u8);
 
-        return run.length;
+        length
+    }
+}
+
+/// Encoder state at a group boundary, from which compression of the same input can be resumed
+/// with [`compress_segment`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Checkpoint {
+    /// How much of the input has been compressed.
+    pub read_head: usize,
+    /// Size of the compressed payload produced up to this point.
+    pub compressed_len: usize,
+
+    // running totals, for progress reports
+    literals: usize,
+    runs: usize,
+
+    /// A run found by lookahead, to be written at the start of the next group.
+    lookahead_cache: Option<Run>,
+}
+
+impl Checkpoint {
+    /// Length of a checkpoint serialized with [`Checkpoint::to_bytes`].
+    pub const SERIALIZED_LEN: usize = 0x38;
+
+    /// Returns the checkpoint at the very start of the input.
+    pub fn start() -> Checkpoint {
+        Checkpoint::default()
+    }
+
+    /// Returns a checkpoint at input offset `read_head`, as if the input before it had already
+    /// been compressed into a payload of `compressed_len` bytes. `read_head` must be at a group
+    /// boundary of that payload.
+    pub(crate) fn at(read_head: usize, compressed_len: usize) -> Checkpoint {
+        Checkpoint {
+            read_head,
+            compressed_len,
+            ..Checkpoint::default()
+        }
+    }
+
+    /// Serializes the checkpoint into a compact, fixed-size form.
+    pub fn to_bytes(&self) -> [u8; Checkpoint::SERIALIZED_LEN] {
+        let (has_cache, cache) = match self.lookahead_cache {
+            Some(run) => (1, run),
+            None => (0, Run::zero()),
+        };
+
+        let mut bytes = [0; Checkpoint::SERIALIZED_LEN];
+        let fields = [
+            self.read_head,
+            self.compressed_len,
+            self.literals,
+            self.runs,
+            has_cache,
+            cache.cursor,
+            cache.length,
+        ];
+        for (chunk, field) in bytes.chunks_mut(8).zip(fields.iter()) {
+            BigEndian::write_u64(chunk, *field as u64);
+        }
+
+        bytes
+    }
+
+    /// Deserializes a checkpoint serialized with [`Checkpoint::to_bytes`].
+    /// Returns `None` if `bytes` isn't a valid checkpoint.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Checkpoint> {
+        if bytes.len() != Checkpoint::SERIALIZED_LEN {
+            return None;
+        }
+
+        let mut fields = bytes.chunks(8).map(|chunk| BigEndian::read_u64(chunk) as usize);
+        let mut next = || fields.next().unwrap();
+
+        let (read_head, compressed_len, literals, runs) = (next(), next(), next(), next());
+        let lookahead_cache = match next() {
+            0 => None,
+            1 => Some(Run {
+                cursor: next(),
+                length: next(),
+            }),
+            _ => return None,
+        };
+
+        Some(Checkpoint {
+            read_head,
+            compressed_len,
+            literals,
+            runs,
+            lookahead_cache,
+        })
+    }
+}
+
+/// How much of the input fed to a [`Compressor`] is held back until more arrives, since the
+/// runs chosen near the end of what's been fed could depend on what follows: enough for a whole
+/// group of the longest runs, one more run found by lookahead, and the lookahead itself.
+const FEED_MARGIN: usize = 9 * MAX_RUN + MAX_LAZY_DEPTH;
+
+/// How [`Compressor::finish`] fills in the header's decompressed size.
+enum HeaderSize<W> {
+    /// The size was given up front, and the header has already been written.
+    Known(usize),
+    /// A header with a size of zero was written at `pos`, to be overwritten through `seek`.
+    Patch {
+        pos: u64,
+        seek: fn(&mut W, SeekFrom) -> io::Result<u64>,
+    },
+}
+
+/// Compresses input fed to it a piece at a time, for data that arrives in chunks (from the
+/// network, say) and would otherwise have to be gathered into one slice first.
+///
+/// Only the window behind the compressor and the last few KiB fed are kept in memory; the rest
+/// is compressed and written out as soon as it's fed. The output is the same as compressing all
+/// of the input in one go would give, up to the choices made by [`CompressionLevel::Auto`], which
+/// only samples the start of the input.
+pub struct Compressor<W: Write> {
+    writer: W,
+    level: CompressionLevel,
+    options: CompressionOptions,
+    /// The input from up to a window behind the compressor onwards.
+    buf: Vec<u8>,
+    /// How far compression has got, relative to the start of `buf`.
+    state: Checkpoint,
+    /// How much input has been dropped from the front of `buf`.
+    dropped: usize,
+    size: HeaderSize<W>,
+}
+
+impl<W: Write + Seek> Compressor<W> {
+    /// Creates a compressor writing a Yaz0 file to `writer` at `level`. A placeholder header is
+    /// written straight away, and filled in by [`Compressor::finish`] once the size is known.
+    pub fn new(mut writer: W, level: CompressionLevel) -> Result<Compressor<W>, Error> {
+        let pos = writer.stream_position()?;
+        Yaz0Header::new(0).write(&mut writer)?;
+        Ok(Compressor::with_header(writer, level, HeaderSize::Patch { pos, seek: W::seek }))
+    }
+}
+
+impl<W: Write> Compressor<W> {
+    /// Creates a compressor writing a Yaz0 file to `writer` at `level`, for writers that can't
+    /// seek back to fill in the header. Exactly `size` bytes must be fed before
+    /// [`Compressor::finish`].
+    pub fn with_size(mut writer: W, level: CompressionLevel, size: usize) -> Result<Compressor<W>, Error> {
+        Yaz0Header::new(size).write(&mut writer)?;
+        Ok(Compressor::with_header(writer, level, HeaderSize::Known(size)))
+    }
+
+    fn with_header(writer: W, level: CompressionLevel, size: HeaderSize<W>) -> Compressor<W> {
+        Compressor {
+            writer,
+            level,
+            options: CompressionOptions::default(),
+            buf: Vec::new(),
+            state: Checkpoint::start(),
+            dropped: 0,
+            size,
+        }
+    }
+
+    /// Sets limits on the runs used, as with [`Yaz0Writer::options`].
+    pub fn options(mut self, options: CompressionOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Adds `data` to the input, compressing and writing out as much of it as can be.
+    pub fn feed(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.buf.extend_from_slice(data);
+
+        let until = self.buf.len().saturating_sub(FEED_MARGIN);
+        if until > self.state.read_head {
+            self.compress_until(until)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes everything compressed so far through to the writer. The last few KiB fed are
+    /// still held back, until more input or [`Compressor::finish`] decides how they're encoded.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// How much input has been fed so far.
+    pub fn total_in(&self) -> usize {
+        self.dropped + self.buf.len()
+    }
+
+    /// How many bytes of compressed payload have been written so far, not counting the header.
+    pub fn total_out(&self) -> usize {
+        self.state.compressed_len
+    }
+
+    /// Compresses the rest of the input, fills in the header, and returns the writer.
+    ///
+    /// Fails with an I/O error of kind [`InvalidInput`](io::ErrorKind::InvalidInput) if the
+    /// compressor was made with [`Compressor::with_size`] and a different amount was fed.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.compress_until(self.buf.len())?;
+        let total = self.total_in();
+
+        match self.size {
+            HeaderSize::Known(size) if size != total => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("compressor was promised {:#x} bytes, but fed {:#x}", size, total),
+                )
+                .into());
+            }
+            HeaderSize::Known(_) => {}
+            HeaderSize::Patch { pos, seek } => {
+                let end = seek(&mut self.writer, SeekFrom::Current(0))?;
+                seek(&mut self.writer, SeekFrom::Start(pos))?;
+                Yaz0Header::new(total).write(&mut self.writer)?;
+                seek(&mut self.writer, SeekFrom::Start(end))?;
+            }
+        }
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn compress_until(&mut self, until: usize) -> Result<(), Error> {
+        // Auto picks its level once, from the start of the input
+        self.level = self.level.resolve(&self.buf);
+        let encoded = compress_lookaround(&self.buf, self.level, &self.options, &mut |_| {}, &mut self.state, until);
+        self.writer.write_all(&encoded)?;
+
+        // drop input runs can no longer reach, once there's enough of it to be worth moving the rest
+        let dead = self.state.read_head.saturating_sub(WINDOW_SIZE);
+        if dead >= WINDOW_SIZE {
+            self.buf.drain(..dead);
+            self.dropped += dead;
+            self.state.read_head -= dead;
+            if let Some(run) = &mut self.state.lookahead_cache {
+                run.cursor -= dead;
+            }
+        }
+        Ok(())
     }
 }
 
 /// Compresses the data in `src` at [CompressionLevel] `level`, using either naive or
-/// lookahead compression, sending progress updates over `progress_tx`. Returns a [Vec] containing
-/// the compressed payload.
+/// lookahead compression, within the limits set by `options`, passing progress updates to
+/// `progress`.
+///
+/// Compression starts from `state`, and stops at the first group boundary at or after `until`;
+/// `state` is left at that boundary. Returns a [Vec] containing the compressed payload for that
+/// stretch of the input.
 fn compress_lookaround(
     src: &[u8],
     level: CompressionLevel,
-    progress_tx: Sender<ProgressMsg>,
+    options: &CompressionOptions,
+    progress: &mut dyn FnMut(ProgressMsg),
+    state: &mut Checkpoint,
+    until: usize,
 ) -> Vec<u8> {
-    let quality = match level {
-        CompressionLevel::Naive { quality } => quality,
-        CompressionLevel::Lookahead { quality } => quality,
-    };
-    const MAX_LOOKBACK: usize = 0x1000;
-    let lookback = (MAX_LOOKBACK as f32 / (10. / quality as f32)).floor() as usize;
-
-    // used to cache lookahead runs to put in the next packet,
-    // since we need to write a head packet first
-    let mut lookahead_cache: Option<Run> = None;
-    let mut read_head = 0;
+    let level = level.resolve(src);
+    if let CompressionLevel::Store = level {
+        return store_segment(src, progress, state, until);
+    }
+    let options = options.clamped();
+    let lookback = options.lookback(level);
+
+    let mut hash_chain = HashChain::new(state.read_head.saturating_sub(lookback));
+
+    let Checkpoint {
+        mut read_head,
+        mut literals,
+        mut runs,
+        // used to cache lookahead runs to put in the next packet,
+        // since we need to write a head packet first
+        mut lookahead_cache,
+        ..
+    } = *state;
     let mut encoded = Vec::new();
+
     // -- encode a packet stream
-    while read_head < src.len() {
+    while read_head < src.len() && read_head < until {
         // the chunk codon
         let mut codon: u8 = 0x0;
 
@@ -178,11 +681,30 @@ fn compress_lookaround(
             } else {
                 match level {
                     CompressionLevel::Lookahead { .. } => {
-                        find_lookahead_run(src, read_head, lookback)
+                        let (skip, run) = find_lookahead_run(src, read_head, lookback, 1, find_naive_run);
+                        (skip == 1, run)
+                    }
+                    CompressionLevel::MatchNintendo => {
+                        let (skip, run) = find_lookahead_run(src, read_head, lookback, 1, find_furthest_run);
+                        (skip == 1, run)
+                    }
+                    CompressionLevel::Lazy { depth } => {
+                        match find_lookahead_run(src, read_head, lookback, depth.clamp(1, MAX_LAZY_DEPTH), find_naive_run) {
+                            (0, run) => (false, run),
+                            (1, run) => (true, run),
+                            // the cache only holds a run starting at the next byte, so write this
+                            // one as a literal and look again from there
+                            _ => (false, Run::zero()),
+                        }
                     }
                     CompressionLevel::Naive { .. } => {
                         (false, find_naive_run(src, read_head, lookback))
                     }
+                    CompressionLevel::HashChain { .. } => {
+                        (false, hash_chain.find_run(src, read_head, lookback))
+                    }
+                    CompressionLevel::Store => unreachable!("stored without looking for runs"),
+                    CompressionLevel::Auto { .. } => unreachable!("resolved before compressing"),
                 }
             };
 
@@ -192,8 +714,14 @@ fn compress_lookaround(
 
             // if we hit a lookahead sequence, we need to write the head byte in preparation for the run.
             // otherwise, if the run was a compression, just do the thing.
-            if best_run.length >= 3 && !hit_lookahead {
-                read_head += write_run(read_head, &best_run, &mut packets);
+            if best_run.length >= options.min_run && !hit_lookahead {
+                let length = match level {
+                    // Nintendo's encoder writes 0x12-byte runs as they are
+                    CompressionLevel::MatchNintendo => best_run.length.min(options.max_run),
+                    _ => options.clip_run(best_run.length),
+                };
+                read_head += write_run(read_head, &Run { length, ..best_run }, &mut packets);
+                runs += 1;
             } else {
                 // force a failout if we've hit the end of the file.
                 if read_head >= src.len() {
@@ -208,6 +736,7 @@ fn compress_lookaround(
 
                 // push the read head forward
                 read_head += 1;
+                literals += 1;
             }
 
             // advance the packet counter
@@ -218,119 +747,1098 @@ fn compress_lookaround(
         encoded.push(codon);
         encoded.extend(&packets);
 
-        if read_head % 10 == 0 || read_head == src.len() - 1 {
-            // ignore errors if the rx is disconnected
-            let _ = progress_tx.send(ProgressMsg { read_head });
+        if read_head % 10 == 0 || read_head == src.len() {
+            progress(ProgressMsg {
+                read_head,
+                compressed_len: state.compressed_len + encoded.len(),
+                literals,
+                runs,
+            });
         }
     }
 
+    *state = Checkpoint {
+        read_head,
+        compressed_len: state.compressed_len + encoded.len(),
+        literals,
+        runs,
+        lookahead_cache,
+    };
+
     encoded
 }
 
-/// Compresses `data` with [CompressionLevel] `level`, sending progress updates over `progress_tx`.
-/// Returns a [Vec] of the compressed payload.
-fn compress_with_progress(
+/// Stores the data in `src` as literals, like [compress_lookaround] does at
+/// [`CompressionLevel::Store`], without looking for runs at all.
+fn store_segment(
+    src: &[u8],
+    progress: &mut dyn FnMut(ProgressMsg),
+    state: &mut Checkpoint,
+    until: usize,
+) -> Vec<u8> {
+    // carry on to the first group boundary at or after `until`
+    let start = state.read_head;
+    let end = (start + until.saturating_sub(start).div_ceil(8) * 8).min(src.len()).max(start);
+    let encoded = store(&src[start..end]);
+
+    *state = Checkpoint {
+        read_head: end,
+        compressed_len: state.compressed_len + encoded.len(),
+        literals: state.literals + (end - start),
+        runs: state.runs,
+        lookahead_cache: None,
+    };
+    progress(ProgressMsg {
+        read_head: end,
+        compressed_len: state.compressed_len,
+        literals: state.literals,
+        runs: state.runs,
+    });
+
+    encoded
+}
+
+/// Compresses a stretch of `data` at [CompressionLevel] `level`, starting from `checkpoint` and
+/// stopping at the first group boundary at or after input offset `until`. Returns the compressed
+/// payload for that stretch, and the checkpoint to carry on from.
+///
+/// Concatenating the payloads from successive calls, starting at [`Checkpoint::start`], produces
+/// exactly the payload that compressing `data` in one go would. The header isn't included; write
+/// it with [`Yaz0Header`] first. Long compressions can persist their checkpoints (see
+/// [`Checkpoint::to_bytes`]) alongside their output, and pick up where they left off after
+/// being interrupted by truncating the output to `checkpoint.compressed_len` bytes after the
+/// header and resuming from there.
+pub fn compress_segment(
     data: &[u8],
     level: CompressionLevel,
-    progress_tx: Sender<ProgressMsg>,
-) -> Vec<u8> {
-    match level {
-        CompressionLevel::Naive { .. } | CompressionLevel::Lookahead { .. } => {
-            compress_lookaround(data, level, progress_tx)
+    checkpoint: &Checkpoint,
+    until: usize,
+) -> (Vec<u8>, Checkpoint) {
+    let level = level.resolve(data);
+    let mut state = *checkpoint;
+    let options = CompressionOptions::default();
+    let encoded = compress_lookaround(data, level, &options, &mut |_| {}, &mut state, until);
+    (encoded, state)
+}
+
+/// Compresses `data` at [CompressionLevel] `level`, returning the operations chosen rather than
+/// an encoded packet stream, for formats that lay the same operations out differently.
+pub(crate) fn compress_ops(data: &[u8], level: CompressionLevel) -> Vec<Op> {
+    let (payload, _) = compress_segment(data, level, &Checkpoint::start(), data.len());
+
+    OpIter::new(&payload[..], data.len())
+        .collect::<Result<_, _>>()
+        .expect("the compressor produced a malformed packet stream")
+}
+
+/// Compresses `data` at [CompressionLevel] `level` as if it came straight after `dict`, so that
+/// runs can copy from the dictionary. Returns the compressed payload.
+pub(crate) fn compress_payload_with_dict(data: &[u8], dict: &[u8], level: CompressionLevel) -> Vec<u8> {
+    // runs can't reach back any further than this anyway
+    let dict = &dict[dict.len().saturating_sub(WINDOW_SIZE)..];
+    let src = [dict, data].concat();
+
+    let mut state = Checkpoint::at(dict.len(), 0);
+    compress_lookaround(&src, level, &CompressionOptions::default(), &mut |_| {}, &mut state, src.len())
+}
+
+/// Writes a Yaz0 file made of exactly the operations `ops`, for match finders living outside this
+/// crate, or for repacking a file with the operations read out of another (see
+/// [`Yaz0Archive::ops`](crate::Yaz0Archive::ops)). The header's decompressed size is the total
+/// output of the operations.
+///
+/// Copy runs must reach back between 1 and `0x1000` bytes, but no further than the start of the
+/// output, and copy between 3 and `0x111` bytes; otherwise [`Error::InvalidOp`] is returned and
+/// nothing is written.
+pub fn encode_ops<I, W>(ops: I, writer: &mut W) -> Result<(), Error>
+where
+    I: IntoIterator<Item = Op>,
+    W: Write,
+{
+    let ops: Vec<Op> = ops.into_iter().collect();
+
+    let mut produced = 0;
+    for (index, op) in ops.iter().enumerate() {
+        if let Op::Copy { dist, len } = *op {
+            let reason = if dist == 0 || dist > WINDOW_SIZE {
+                Some("copy distance is out of range")
+            } else if dist > produced {
+                Some("copy reaches back before the start of the output")
+            } else if !(3..=MAX_RUN).contains(&len) {
+                Some("copy length is out of range")
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                return Err(Error::InvalidOp { index, reason });
+            }
+        }
+        produced += op.output_len();
+    }
+
+    Yaz0Header::new(produced).write(writer)?;
+    writer.write_all(&encode_packets(&ops))?;
+
+    Ok(())
+}
+
+/// Encodes `data` as a packet stream of nothing but literals.
+fn store(data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(data.len() + data.len().div_ceil(8));
+    for group in data.chunks(8) {
+        // one bit set for each literal, from the top
+        encoded.push((0xff00u16 >> group.len()) as u8);
+        encoded.extend_from_slice(group);
+    }
+
+    encoded
+}
+
+/// Decodes the packet stream `payload`, returning the first offset at which it differs from
+/// `data`, if it does.
+fn first_mismatch(payload: &[u8], data: &[u8]) -> Option<usize> {
+    let mut decoded = ByteIter::<_>::new(payload, data.len());
+
+    for (offset, &expected) in data.iter().enumerate() {
+        match decoded.next() {
+            Some(Ok(byte)) if byte == expected => {}
+            _ => return Some(offset),
         }
     }
+
+    None
+}
+
+/// How much input [`Yaz0Writer`] compresses at a time before writing out the compressed payload
+/// for it. A chunk's code bytes and packets are encoded into one buffer, so it goes out in a
+/// single `write_all`.
+const WRITE_CHUNK_LEN: usize = 0x10000;
+
+/// Returns the largest a Yaz0 file compressed from `len` bytes of data can be, including its header:
+/// the size when every byte is stored as a literal, with a code byte for every eight of them.
+/// Any padding added with [`Yaz0Writer::payload_alignment`] comes on top of this.
+pub fn max_compressed_size(len: usize) -> usize {
+    0x10 + len + len.div_ceil(8)
 }
 
 /// Compresses `data` with [CompressionLevel] `level`.
 /// Returns a [Vec] of the compressed payload.
+#[cfg(test)]
 fn compress(data: &[u8], level: CompressionLevel) -> Vec<u8> {
-    let (tx, _) = mpsc::channel();
-    compress_with_progress(data, level, tx)
+    compress_with_options(data, level, CompressionOptions::default())
+}
+
+/// Like [compress], but within the limits set by `options`.
+#[cfg(test)]
+fn compress_with_options(data: &[u8], level: CompressionLevel, options: CompressionOptions) -> Vec<u8> {
+    compress_lookaround(data, level, &options, &mut |_| {}, &mut Checkpoint::start(), data.len())
 }
 
 impl<'a, W> Yaz0Writer<'a, W>
 where
     W: Write,
 {
-    pub fn new(writer: &'a mut W) -> Yaz0Writer<W>
+    pub fn new(writer: &'a mut W) -> Yaz0Writer<'a, W>
     where
         W: Write,
     {
-        Yaz0Writer { writer }
+        Yaz0Writer {
+            writer,
+            payload_alignment: 1,
+            record_settings: false,
+            deadline: None,
+            headerless: false,
+            alignment: 0,
+            reserved: 0,
+            endianness: Endianness::Big,
+            options: CompressionOptions::default(),
+            store_incompressible: false,
+            checksum: None,
+        }
     }
 
-    /// Compress and write the passed `data`, at compression level `level`.
-    pub fn compress_and_write(self, data: &[u8], level: CompressionLevel) -> Result<(), Error> {
-        // -- construct and write the header
-        let header = Yaz0Header::new(data.len());
-        header.write(self.writer)?;
+    /// Sets the header's alignment field, a hint some games use to decide how to align the
+    /// decompressed data in memory (Breath of the Wild, for one). It's zero by default.
+    pub fn alignment(mut self, alignment: u32) -> Self {
+        self.alignment = alignment;
+        self
+    }
 
-        // -- compress and write the data
-        let compressed = compress(data, level);
-        self.writer.write_all(&compressed)?;
+    /// Sets the header's reserved field, which is zero by default. Overridden by
+    /// [`Yaz0Writer::record_settings`] and [`Yaz0Writer::checksum`].
+    ///
+    /// The value isn't reported by
+    /// [`Yaz0Archive::settings_hash`](crate::Yaz0Archive::settings_hash), unless it happens to be
+    /// the settings hash of one of the compression levels.
+    pub fn reserved(mut self, reserved: u32) -> Self {
+        self.reserved = reserved;
+        self
+    }
 
-        Ok(())
+    /// Records a hash of the compression settings used (see [`CompressionLevel::settings_hash`])
+    /// in the header's reserved field, where it can be read back with
+    /// [`Yaz0Archive::settings_hash`](crate::Yaz0Archive::settings_hash). Build caches can use
+    /// this to tell whether a file needs recompressing after settings change.
+    pub fn record_settings(mut self) -> Self {
+        self.record_settings = true;
+        self
     }
 
-    /// Compress and write the passed `data`, at compression level `level`.
-    /// Progress updates are streamed out of `progress_tx`.
-    pub fn compress_and_write_with_progress(
-        self,
-        data: &[u8],
-        level: CompressionLevel,
-        progress_tx: Sender<ProgressMsg>,
-    ) -> Result<(), Error> {
-        // -- construct and write the header
-        let header = Yaz0Header::new(data.len());
-        header.write(self.writer)?;
+    /// Pads the gap between the header and the compressed payload with zeroes, so that the payload
+    /// starts at a multiple of `alignment` bytes from the start of the header.
+    ///
+    /// Readers need to be told where the payload starts to decompress files written this way;
+    /// see [`Yaz0Archive::with_payload_offset`](crate::Yaz0Archive::with_payload_offset).
+    pub fn payload_alignment(mut self, alignment: usize) -> Self {
+        self.payload_alignment = alignment.max(1);
+        self
+    }
 
-        // -- compress and write the data
-        let compressed = compress_with_progress(data, level, progress_tx);
-        self.writer.write_all(&compressed)?;
+    /// Tries to finish compressing within `deadline`. Compression starts at the requested level,
+    /// but if it looks like it won't finish in time, it switches to faster (and worse) levels as
+    /// it goes; the output is always valid, just not necessarily as small.
+    ///
+    /// Since the output then depends on timing, it isn't reproducible. Any settings hash recorded
+    /// with [`Yaz0Writer::record_settings`] is that of the requested level.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
 
-        Ok(())
+    /// Sets the byte order the header is written in; big-endian, as Nintendo's files are, by
+    /// default.
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
     }
-}
 
-/// Represents the agressiveness of lookback used by the compressor.
-#[derive(Clone, Copy)]
-pub enum CompressionLevel {
-    Naive {
-        /// Lookback distance. Set between 1 and 10; 10 corresponds to greatest lookback distance.
-        quality: usize
-    },
-    Lookahead {
-        /// Lookback distance. Set between 1 and 10; 10 corresponds to greatest lookback distance.
-        quality: usize
-    },
-}
+    /// Limits the runs the compressor may use, for decoders that can't handle the full range the
+    /// format allows. See [`CompressionOptions`].
+    pub fn options(mut self, options: CompressionOptions) -> Self {
+        self.options = options;
+        self
+    }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use pretty_assertions::assert_eq;
+    /// Stores the input as a stream of literals if compressing it doesn't make it any smaller, as
+    /// with data that's already compressed. Any Yaz0 decoder reads such a stream back, and it's
+    /// only an eighth bigger than the input.
+    ///
+    /// The compressed payload then has to be held in memory until it's known to be smaller.
+    pub fn store_incompressible(mut self) -> Self {
+        self.store_incompressible = true;
+        self
+    }
 
-    #[test]
-    #[cfg_attr(rustfmt, rustfmt_skip)] // don't mess up our arrays 😅
-    fn deflate_naive() {
-        const Q: CompressionLevel = CompressionLevel::Naive {quality: 10};
+    /// Stores a CRC-32 of the input at `location`, as some community tools do, so that it can be
+    /// checked after decompressing with
+    /// [`Yaz0Archive::verify_checksum`](crate::Yaz0Archive::verify_checksum). Headerless streams
+    /// can only have a trailer.
+    ///
+    /// A checksum in the reserved field overrides any value set with [`Yaz0Writer::reserved`].
+    /// There's no room left for a settings hash, so combining it with
+    /// [`Yaz0Writer::record_settings`] fails with [`Error::InvalidOptions`] before anything is
+    /// written.
+    ///
+    /// Decoders that don't know about trailers ignore it, but may warn about the trailing data.
+    pub fn checksum(mut self, location: ChecksumLocation) -> Self {
+        self.checksum = Some(location);
+        self
+    }
 
-        assert_eq!(compress(&[12, 34, 56], Q), [0xe0, 12, 34, 56]);
+    /// Writes only the bare packet stream, without the 16-byte header (or any alignment padding),
+    /// for container formats that embed Yaz0 payloads and store the decompressed size themselves.
+    /// Read such streams back with [`Yaz0Archive::from_raw`](crate::Yaz0Archive::from_raw).
+    pub fn headerless(mut self) -> Self {
+        self.headerless = true;
+        self
+    }
 
-        assert_eq!(
-            compress(&[0, 1, 2, 0xa, 0, 1, 2, 3, 0xb, 0, 1, 2, 3, 4, 5, 6, 7], Q),
-            [
-                0xf6, /* | id:  */ 0, 1, 2, 0xa,
-                      /*   run: */ 0x10, 0x03,
-                      /*   id:  */ 3, 0xb,
-                      /*   run: */ 0x20, 0x04,
-                0xf0, /* | id:  */ 4, 5, 6, 7,
+    /// Writes the header for `data`, followed by any padding needed to align the payload.
+    fn write_header(&mut self, data: &[u8], level: CompressionLevel) -> Result<(), Error> {
+        if self.record_settings && self.checksum == Some(ChecksumLocation::Reserved) {
+            let reason = "a checksum in the reserved field leaves no room for the settings hash";
+            return Err(Error::InvalidOptions { reason });
+        }
+        if self.headerless {
+            return Ok(());
+        }
+
+        let mut header = Yaz0Header::new(data.len());
+        header.alignment = self.alignment;
+        header.reserved = self.reserved;
+        header.endianness = self.endianness;
+        if self.record_settings {
+            header.reserved = level.settings_hash();
+        }
+        if self.checksum == Some(ChecksumLocation::Reserved) {
+            header.reserved = crc32(data);
+        }
+        header.write(self.writer)?;
+
+        let header_len = 0x10;
+        let align = self.payload_alignment;
+        let padding = (align - header_len % align) % align;
+        self.writer.write_all(&vec![0x0; padding])?;
+
+        Ok(())
+    }
+
+    /// Writes the checksum of `data` after the payload, if it's to go in a trailer.
+    fn write_trailer(&mut self, data: &[u8]) -> Result<(), Error> {
+        if self.checksum == Some(ChecksumLocation::Trailer) {
+            let crc = crc32(data);
+            let bytes = match self.endianness {
+                Endianness::Big => crc.to_be_bytes(),
+                Endianness::Little => crc.to_le_bytes(),
+            };
+            self.writer.write_all(&bytes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates a writer compressing into `payload` the way this one would, headerless, for
+    /// payloads that need looking over before they're written.
+    fn payload_writer<'b>(&self, payload: &'b mut Vec<u8>) -> Yaz0Writer<'b, Vec<u8>> {
+        let mut writer = Yaz0Writer::new(payload);
+        writer.deadline = self.deadline;
+        writer.options = self.options;
+        writer.store_incompressible = self.store_incompressible;
+        writer
+    }
+
+    /// Compresses `data` and writes out the payload a chunk at a time as it's produced, so that
+    /// the whole compressed payload is never held in memory at once (unless the input might be
+    /// stored instead). Returns the length of the payload.
+    fn write_payload(
+        &mut self,
+        data: &[u8],
+        level: CompressionLevel,
+        progress: &mut dyn FnMut(ProgressMsg),
+        mut stats: Option<&mut PacketStats>,
+    ) -> Result<usize, Error> {
+        if self.store_incompressible {
+            let mut payload = Vec::with_capacity(max_compressed_size(data.len()));
+            let mut inner = self.payload_writer(&mut payload);
+            inner.store_incompressible = false;
+            inner.write_payload(data, level, progress, None)?;
+
+            let stored = store(data);
+            let payload = if payload.len() < stored.len() { payload } else { stored };
+            if let Some(stats) = stats {
+                stats.add_payload(&payload, data.len())?;
+            }
+            self.writer.write_all(&payload)?;
+            return Ok(payload.len());
+        }
+
+        let started = Instant::now();
+        let mut level = level;
+
+        let mut state = Checkpoint::start();
+        while state.read_head < data.len() {
+            let chunk_start = state.read_head;
+            let until = state.read_head + WRITE_CHUNK_LEN;
+            let encoded = compress_lookaround(data, level, &self.options, progress, &mut state, until);
+            self.writer.write_all(&encoded)?;
+
+            // chunks end on group boundaries, so each can be tallied separately
+            if let Some(stats) = stats.as_deref_mut() {
+                stats.add_payload(&encoded, state.read_head - chunk_start)?;
+            }
+
+            // if we're on track to miss the deadline, speed up for the next chunk
+            if let Some(deadline) = self.deadline {
+                let projected = started.elapsed().mul_f64(data.len() as f64 / state.read_head as f64);
+                if projected > deadline {
+                    level = level.faster().unwrap_or(level);
+                }
+            }
+        }
+
+        Ok(state.compressed_len)
+    }
+
+    /// Compress and write the passed `data`, at compression level `level`.
+    pub fn compress_and_write(mut self, data: &[u8], level: CompressionLevel) -> Result<(), Error> {
+        let level = level.resolve(data);
+
+        // -- construct and write the header
+        self.write_header(data, level)?;
+
+        // -- compress and write the data
+        self.write_payload(data, level, &mut |_| {}, None)?;
+        self.write_trailer(data)?;
+
+        Ok(())
+    }
+
+    /// Compress the passed `data` at compression level `level`, then decompress the result in
+    /// memory and check it matches `data` before writing anything. If it doesn't,
+    /// [`Error::VerificationFailed`] is returned, and nothing is written.
+    pub fn compress_verify_and_write(mut self, data: &[u8], level: CompressionLevel) -> Result<(), Error> {
+        let level = level.resolve(data);
+        let mut payload = Vec::with_capacity(max_compressed_size(data.len()));
+        self.payload_writer(&mut payload).write_payload(data, level, &mut |_| {}, None)?;
+
+        if let Some(offset) = first_mismatch(&payload, data) {
+            return Err(Error::VerificationFailed { offset });
+        }
+
+        self.write_header(data, level)?;
+        self.writer.write_all(&payload)?;
+        self.write_trailer(data)?;
+
+        Ok(())
+    }
+
+    /// Compress and write the passed `data`, at compression level `level`, splitting the work
+    /// across `threads` threads (or as many as there are CPUs, if `None`).
+    ///
+    /// The input is split into one chunk per thread, each compressed on its own; runs can still
+    /// reach back into earlier chunks, so the output is only slightly different from (and barely
+    /// larger than) compressing it in one go.
+    ///
+    /// A [deadline](Yaz0Writer::deadline) applies to each chunk, since they're compressed at the
+    /// same time: each thread speeds up if its own chunk looks like it won't finish in time.
+    pub fn compress_and_write_parallel(
+        mut self,
+        data: &[u8],
+        level: CompressionLevel,
+        threads: Option<usize>,
+    ) -> Result<(), Error> {
+        let level = level.resolve(data);
+        self.write_header(data, level)?;
+
+        let threads = threads
+            .or_else(|| thread::available_parallelism().ok().map(|n| n.get()))
+            .unwrap_or(1)
+            .max(1);
+        let chunk_len = data.len().div_ceil(threads).max(1);
+        let options = &self.options;
+        let deadline = self.deadline;
+
+        let chunks: Vec<Vec<u8>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..data.len())
+                .step_by(chunk_len)
+                .map(|start| {
+                    let end = (start + chunk_len).min(data.len());
+                    scope.spawn(move || {
+                        let started = Instant::now();
+                        let mut level = level;
+
+                        let mut encoded = Vec::new();
+                        let mut state = Checkpoint::at(start, 0);
+                        while state.read_head < end {
+                            let until = (state.read_head + WRITE_CHUNK_LEN).min(end);
+                            let src = &data[..end];
+                            encoded.extend(compress_lookaround(src, level, options, &mut |_| {}, &mut state, until));
+
+                            // as in write_payload, but going by this chunk's progress
+                            if let Some(deadline) = deadline {
+                                let done = (state.read_head - start) as f64;
+                                let projected = started.elapsed().mul_f64((end - start) as f64 / done);
+                                if projected > deadline {
+                                    level = level.faster().unwrap_or(level);
+                                }
+                            }
+                        }
+                        encoded
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("compression thread panicked"))
+                .collect()
+        });
+
+        // each chunk's last group is usually partial, so the chunks can't just be concatenated;
+        // regroup their operations instead, carrying leftovers into the next chunk.
+        let mut payload = Vec::with_capacity(max_compressed_size(data.len()));
+        let mut carried = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let start = i * chunk_len;
+            let end = (start + chunk_len).min(data.len());
+
+            let mut ops = std::mem::take(&mut carried);
+            for op in OpIter::new(&chunk[..], end - start) {
+                ops.push(op?);
+            }
+
+            if i + 1 < chunks.len() {
+                carried = ops.split_off(ops.len() - ops.len() % 8);
+            }
+            payload.extend(encode_packets(&ops));
+        }
+
+        if self.store_incompressible && payload.len() >= max_compressed_size(data.len()) - 0x10 {
+            payload = store(data);
+        }
+        self.writer.write_all(&payload)?;
+        self.write_trailer(data)?;
+
+        Ok(())
+    }
+
+    /// Compress and write the passed `data`, at compression level `level`, returning statistics
+    /// about the compressed payload produced.
+    pub fn compress_and_write_with_stats(
+        mut self,
+        data: &[u8],
+        level: CompressionLevel,
+    ) -> Result<CompressionStats, Error> {
+        let level = level.resolve(data);
+        self.write_header(data, level)?;
+
+        let mut packets = PacketStats::default();
+        let compressed_len = self.write_payload(data, level, &mut |_| {}, Some(&mut packets))?;
+        self.write_trailer(data)?;
+
+        Ok(CompressionStats {
+            input_len: data.len(),
+            compressed_len,
+            packets,
+        })
+    }
+
+    /// Compress and write the passed `data`, at compression level `level`.
+    /// Progress updates are streamed out of `progress_tx`.
+    pub fn compress_and_write_with_progress(
+        self,
+        data: &[u8],
+        level: CompressionLevel,
+        progress_tx: Sender<ProgressMsg>,
+    ) -> Result<(), Error> {
+        self.compress_and_write_with_callback(data, level, |progress| {
+            // ignore errors if the rx is disconnected
+            let _ = progress_tx.send(progress);
+        })
+    }
+
+    /// Compress and write the passed `data`, at compression level `level`.
+    /// `progress` is called with progress updates as compression goes, on the calling thread.
+    pub fn compress_and_write_with_callback<F>(
+        mut self,
+        data: &[u8],
+        level: CompressionLevel,
+        mut progress: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(ProgressMsg),
+    {
+        let level = level.resolve(data);
+
+        // -- construct and write the header
+        self.write_header(data, level)?;
+
+        // -- compress and write the data
+        self.write_payload(data, level, &mut progress, None)?;
+        self.write_trailer(data)?;
+
+        Ok(())
+    }
+}
+
+/// Limits on the runs the compressor may use, alongside a [CompressionLevel], for decoders that
+/// can't handle everything the format allows (some games can't decode runs longer than 0x111
+/// bytes, for instance, or only keep a smaller window of output around).
+///
+/// Values outside what the format can encode are clamped to it. The defaults are the format's
+/// own limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionOptions {
+    /// How far back runs may start, in bytes; at most 0x1000. A [CompressionLevel]'s quality
+    /// scales this, rather than the whole 0x1000-byte window (see
+    /// [`CompressionOptions::lookback`]).
+    pub window: usize,
+    /// The longest run to write, in bytes; at most 0x111.
+    pub max_run: usize,
+    /// The shortest run worth writing, in bytes; at least 3. Shorter matches are stored as
+    /// literals.
+    pub min_run: usize,
+}
+
+impl Default for CompressionOptions {
+    fn default() -> CompressionOptions {
+        CompressionOptions {
+            window: WINDOW_SIZE,
+            max_run: MAX_RUN,
+            min_run: 3,
+        }
+    }
+}
+
+impl CompressionOptions {
+    /// Returns how far back the compressor looks for runs at `level`: `quality` tenths of the
+    /// window, rounded down, or the whole window for [CompressionLevel::MatchNintendo],
+    /// [CompressionLevel::Lazy] and [CompressionLevel::Auto].
+    ///
+    /// Qualities are clamped to 1 to 10 first, so the lookback only ever grows with quality, up
+    /// to the window at quality 10; a longer lookback finds runs at least as long, making the
+    /// output smaller, but takes longer to search.
+    pub fn lookback(&self, level: CompressionLevel) -> usize {
+        let window = self.clamped().window;
+        match level {
+            CompressionLevel::Naive { quality }
+            | CompressionLevel::Lookahead { quality }
+            | CompressionLevel::HashChain { quality } => window * quality.clamp(1, 10) / 10,
+            // whatever `Auto` picks looks back no further than this
+            CompressionLevel::MatchNintendo | CompressionLevel::Lazy { .. } | CompressionLevel::Auto { .. } => window,
+            CompressionLevel::Store => 0,
+        }
+    }
+
+    /// Returns how many bytes of a `length`-byte match the compressor writes as a run: at most
+    /// `max_run`, and 0x11 rather than 0x12. An 0x12-byte run is the shortest needing a 3-byte
+    /// packet, where an 0x11-byte one fits in two, and the byte left over is usually picked up by
+    /// the next run for free.
+    ///
+    /// [CompressionLevel::MatchNintendo] only clips to `max_run`, as Nintendo's encoder does.
+    pub fn clip_run(&self, length: usize) -> usize {
+        let options = self.clamped();
+        match length.min(options.max_run) {
+            0x12 if options.min_run <= 0x11 => 0x11,
+            length => length,
+        }
+    }
+
+    /// Clamps the limits to what the format can encode.
+    fn clamped(self) -> CompressionOptions {
+        let min_run = self.min_run.clamp(3, MAX_RUN);
+        CompressionOptions {
+            window: self.window.min(WINDOW_SIZE),
+            max_run: self.max_run.clamp(min_run, MAX_RUN),
+            min_run,
+        }
+    }
+}
+
+/// Represents the agressiveness of lookback used by the compressor.
+///
+/// Qualities outside of 1 to 10 are clamped into that range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompressionLevel {
+    Naive {
+        /// Lookback distance. Set between 1 and 10; 10 corresponds to greatest lookback distance.
+        quality: usize
+    },
+    Lookahead {
+        /// Lookback distance. Set between 1 and 10; 10 corresponds to greatest lookback distance.
+        quality: usize
+    },
+    /// Like `Naive`, but finds runs with hash chains, which is much faster on large inputs.
+    HashChain {
+        /// Lookback distance. Set between 1 and 10; 10 corresponds to greatest lookback distance.
+        quality: usize
+    },
+    /// Picks runs exactly as Nintendo's encoder (and `yaz0enc`) does, so that recompressing a
+    /// file from a game reproduces it byte for byte. Like `Lookahead` at quality 10, but keeping
+    /// the furthest of equally long runs rather than the nearest.
+    MatchNintendo,
+    /// Like `Lookahead` at quality 10, but after finding a better run a byte ahead, carries on
+    /// looking for a better one still, up to `depth` bytes ahead, like zlib's lazy matching. A
+    /// depth of 2 searches the window up to three times per run; it's slower than `Lookahead`,
+    /// for output that's usually a little smaller.
+    Lazy {
+        /// How many bytes to look ahead; clamped to 1 (the same as `Lookahead`) or 2.
+        depth: usize
+    },
+    /// Doesn't look for runs at all, storing every byte as a literal. The output is an eighth
+    /// bigger than the input, but it's produced about as fast as it can be copied; for data
+    /// that's incompressible anyway, like already-compressed textures.
+    Store,
+    /// Picks one of the other levels for each input, by compressing samples of it; see
+    /// [`CompressionLevel::resolve`]. For batches of varied files, where tuning each by hand
+    /// isn't practical.
+    Auto {
+        /// The compressed size, as a fraction of the input's, that's good enough; the fastest
+        /// level reaching it on the samples is picked.
+        target_ratio: f64,
+        /// How long compressing the whole input should take at most, going by how long the
+        /// samples took; `Duration::MAX` for no limit.
+        time_budget: Duration,
+    },
+}
+
+/// How many bytes of the input [CompressionLevel::Auto] compresses at each level it tries, in
+/// [AUTO_SAMPLES] slices spread through the input.
+const AUTO_SAMPLE_LEN: usize = 0x8000;
+const AUTO_SAMPLES: usize = 4;
+
+/// The levels [CompressionLevel::Auto] picks between, from fastest to smallest output.
+const AUTO_CANDIDATES: [CompressionLevel; 5] = [
+    CompressionLevel::HashChain { quality: 1 },
+    CompressionLevel::HashChain { quality: 4 },
+    CompressionLevel::HashChain { quality: 10 },
+    CompressionLevel::Lookahead { quality: 10 },
+    CompressionLevel::Lazy { depth: 2 },
+];
+
+/// Picks the slices of `data` that [CompressionLevel::Auto] tries each level on.
+fn auto_samples(data: &[u8]) -> Vec<&[u8]> {
+    if data.len() <= AUTO_SAMPLE_LEN {
+        return vec![data];
+    }
+
+    let len = AUTO_SAMPLE_LEN / AUTO_SAMPLES;
+    let stride = (data.len() - len) / (AUTO_SAMPLES - 1);
+    (0..AUTO_SAMPLES).map(|i| &data[i * stride..i * stride + len]).collect()
+}
+
+impl Default for CompressionLevel {
+    fn default() -> CompressionLevel {
+        CompressionLevel::best()
+    }
+}
+
+impl From<u8> for CompressionLevel {
+    /// Maps a 0-9 level, as used by zlib and most compression tools, onto a compression level:
+    /// 0 stores the data uncompressed, 1 to 8 use hash chains with increasing quality, and 9
+    /// (or anything above it) is [`CompressionLevel::best`].
+    fn from(level: u8) -> CompressionLevel {
+        match level {
+            0 => CompressionLevel::Store,
+            1..=8 => CompressionLevel::HashChain { quality: level as usize * 10 / 8 },
+            _ => CompressionLevel::best(),
+        }
+    }
+}
+
+impl CompressionLevel {
+    /// The fastest level that still compresses the data.
+    pub fn fastest() -> CompressionLevel {
+        CompressionLevel::HashChain { quality: 1 }
+    }
+
+    /// The level producing the smallest output.
+    pub fn best() -> CompressionLevel {
+        CompressionLevel::Lookahead { quality: 10 }
+    }
+
+    /// Returns the level [`CompressionLevel::Auto`] picks for `data`, or the level itself if it's
+    /// any other.
+    ///
+    /// Each candidate level, from fastest to slowest, compresses up to 32 KiB sampled from
+    /// through `data`. The first whose output meets `target_ratio` is picked, unless it's
+    /// projected to take longer than `time_budget` on the whole of `data`; failing that, the one
+    /// with the smallest output within the budget is. Data that the fastest level can't shrink at
+    /// all is stored. Since this goes by timings, the pick can differ from run to run.
+    pub fn resolve(self, data: &[u8]) -> CompressionLevel {
+        let (target_ratio, time_budget) = match self {
+            CompressionLevel::Auto { target_ratio, time_budget } => (target_ratio, time_budget),
+            level => return level,
+        };
+
+        let samples = auto_samples(data);
+        let sample_len: usize = samples.iter().map(|sample| sample.len()).sum();
+
+        let mut best: Option<(CompressionLevel, f64)> = None;
+        for (i, &level) in AUTO_CANDIDATES.iter().enumerate() {
+            let started = Instant::now();
+            let compressed_len: usize = samples
+                .iter()
+                .map(|sample| compress_segment(sample, level, &Checkpoint::start(), sample.len()).0.len())
+                .sum();
+            let projected = started.elapsed().mul_f64(data.len() as f64 / sample_len.max(1) as f64);
+            let ratio = compressed_len as f64 / sample_len.max(1) as f64;
+
+            if i == 0 && ratio >= 1. {
+                return CompressionLevel::Store;
+            }
+            // the fastest level is kept even over budget, having nothing faster to fall back on
+            let over_budget = projected > time_budget;
+            if over_budget && i > 0 {
+                break;
+            }
+            if ratio <= target_ratio {
+                return level;
+            }
+            if best.map_or(true, |(_, best_ratio)| ratio < best_ratio) {
+                best = Some((level, ratio));
+            }
+            // slower levels won't fit the budget either
+            if over_budget {
+                break;
+            }
+        }
+
+        best.map_or(CompressionLevel::fastest(), |(level, _)| level)
+    }
+
+    /// Returns the next faster level to fall back to when short on time, if there is one.
+    fn faster(self) -> Option<CompressionLevel> {
+        match self {
+            CompressionLevel::Lookahead { quality } | CompressionLevel::Naive { quality } => {
+                Some(CompressionLevel::HashChain { quality })
+            }
+            CompressionLevel::MatchNintendo => Some(CompressionLevel::HashChain { quality: 10 }),
+            CompressionLevel::Lazy { .. } => Some(CompressionLevel::Lookahead { quality: 10 }),
+            CompressionLevel::Auto { .. } => Some(CompressionLevel::fastest()),
+            CompressionLevel::HashChain { quality } if quality > 1 => {
+                Some(CompressionLevel::HashChain { quality: quality / 2 })
+            }
+            CompressionLevel::HashChain { .. } => Some(CompressionLevel::Store),
+            CompressionLevel::Store => None,
+        }
+    }
+
+    /// Returns a short hash identifying these settings, which is stable across versions of this
+    /// crate as long as the settings produce the same output. It's never zero.
+    pub fn settings_hash(&self) -> u32 {
+        let (variant, quality) = match *self {
+            CompressionLevel::Naive { quality } => (0u8, quality.clamp(1, 10)),
+            CompressionLevel::Lookahead { quality } => (1u8, quality.clamp(1, 10)),
+            CompressionLevel::HashChain { quality } => (2u8, quality.clamp(1, 10)),
+            CompressionLevel::MatchNintendo => (3u8, 0),
+            CompressionLevel::Store => (4u8, 0),
+            CompressionLevel::Lazy { depth } => (5u8, depth.clamp(1, MAX_LAZY_DEPTH)),
+            CompressionLevel::Auto { .. } => (6u8, 0),
+        };
+
+        // 32-bit FNV-1a
+        let mut hash: u32 = 0x811c_9dc5;
+        for byte in [variant].iter().chain(&(quality as u32).to_be_bytes()) {
+            hash ^= *byte as u32;
+            hash = hash.wrapping_mul(0x0100_0193);
+        }
+
+        hash.max(1)
+    }
+
+    /// Returns the level whose [settings hash](CompressionLevel::settings_hash) is `hash`, if
+    /// there is one. `Auto` never is, since the level it picks is what gets recorded.
+    pub(crate) fn from_settings_hash(hash: u32) -> Option<CompressionLevel> {
+        (1..=10)
+            .flat_map(|quality| {
+                [
+                    CompressionLevel::Naive { quality },
+                    CompressionLevel::Lookahead { quality },
+                    CompressionLevel::HashChain { quality },
+                ]
+            })
+            .chain([CompressionLevel::MatchNintendo, CompressionLevel::Store])
+            .chain((1..=MAX_LAZY_DEPTH).map(|depth| CompressionLevel::Lazy { depth }))
+            .find(|level| level.settings_hash() == hash)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn parallel_naive_run() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x6000];
+
+        for cursor in (0..data.len()).step_by(0x3f) {
+            let serial = (cursor.saturating_sub(WINDOW_SIZE)..cursor).fold(Run::zero(), |run, search_head| {
+                run.swap_if_better(Run { cursor: search_head, length: naive_runlength(data, search_head, cursor) })
+            });
+            let parallel = find_naive_run_parallel(data, cursor, cursor.saturating_sub(WINDOW_SIZE));
+            assert_eq!(parallel, serial, "at {:#x}", cursor);
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip] // don't mess up our arrays 😅
+    fn deflate_naive() {
+        const Q: CompressionLevel = CompressionLevel::Naive {quality: 10};
+
+        assert_eq!(compress(&[12, 34, 56], Q), [0xe0, 12, 34, 56]);
+
+        assert_eq!(
+            compress(&[0, 1, 2, 0xa, 0, 1, 2, 3, 0xb, 0, 1, 2, 3, 4, 5, 6, 7], Q),
+            [
+                0xf6, /* | id:  */ 0, 1, 2, 0xa,
+                      /*   run: */ 0x10, 0x03,
+                      /*   id:  */ 3, 0xb,
+                      /*   run: */ 0x20, 0x04,
+                0xf0, /* | id:  */ 4, 5, 6, 7,
             ]
         );
     }
 
+    /// Check that the writer's output comes out in chunks, and matches compressing in one go.
     #[test]
-    #[cfg_attr(rustfmt, rustfmt_skip)] // don't mess up our arrays 😅
+    fn chunked_writes() {
+        struct ChunkRecorder(Vec<u8>, Vec<usize>);
+        impl Write for ChunkRecorder {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.extend_from_slice(buf);
+                self.1.push(buf.len());
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let data: &[u8] = include_bytes!("../data/test");
+        let data = &data[..0x48000];
+        let level = CompressionLevel::Naive { quality: 4 };
+
+        let mut out = ChunkRecorder(Vec::new(), Vec::new());
+        Yaz0Writer::new(&mut out).compress_and_write(data, level).unwrap();
+
+        assert!(out.1.len() > 4, "expected several chunks, got {:?}", out.1);
+        assert!(out.0[0x10..] == compress(data, level)[..]);
+    }
+
+    /// Even with no time at all, compression should produce valid output, just a worse one.
+    #[test]
+    fn deadline() {
+        use crate::Yaz0Archive;
+        use std::io::Cursor;
+
+        let data: &[u8] = &include_bytes!("../data/test")[..0x30000];
+        let level = CompressionLevel::Lookahead { quality: 10 };
+
+        let mut hurried = Vec::new();
+        Yaz0Writer::new(&mut hurried)
+            .deadline(Duration::from_secs(0))
+            .compress_and_write(data, level)
+            .unwrap();
+
+        let inflated = Yaz0Archive::new(Cursor::new(&hurried)).unwrap().decompress().unwrap();
+        assert!(inflated == data);
+        assert!(hurried.len() - 0x10 > compress(data, level).len());
+    }
+
+    #[test]
+    fn verify() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x4000];
+        let level = CompressionLevel::Lookahead { quality: 10 };
+
+        let mut verified = Vec::new();
+        Yaz0Writer::new(&mut verified).alignment(0x80).compress_verify_and_write(data, level).unwrap();
+
+        let mut plain = Vec::new();
+        Yaz0Writer::new(&mut plain).alignment(0x80).compress_and_write(data, level).unwrap();
+        assert_eq!(verified, plain);
+
+        let payload = &plain[0x10..];
+        assert_eq!(first_mismatch(payload, data), None);
+
+        let mut corrupted = data.to_vec();
+        corrupted[0x1234] ^= 1;
+        assert_eq!(first_mismatch(payload, &corrupted), Some(0x1234));
+        assert_eq!(first_mismatch(&payload[..0x100], data).map(|offset| offset < 0x200), Some(true));
+    }
+
+    #[test]
+    fn headerless() {
+        use crate::Yaz0Archive;
+        use std::io::Cursor;
+
+        let data: &[u8] = &include_bytes!("../data/test")[..0x2000];
+        let level = CompressionLevel::Naive { quality: 10 };
+
+        let mut raw = Vec::new();
+        Yaz0Writer::new(&mut raw).headerless().compress_and_write(data, level).unwrap();
+        assert_eq!(&raw[..], &compress(data, level)[..]);
+
+        let inflated = Yaz0Archive::from_raw(Cursor::new(&raw), data.len()).unwrap().decompress().unwrap();
+        assert!(inflated == data);
+    }
+
+    #[test]
+    fn parallel() {
+        use crate::Yaz0Archive;
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+        use std::io::Cursor;
+
+        let data: &[u8] = &include_bytes!("../data/test")[..0x10003];
+        let level = CompressionLevel::HashChain { quality: 10 };
+
+        for &threads in &[Some(1), Some(3), Some(7), None] {
+            let mut deflated = Vec::new();
+            Yaz0Writer::new(&mut deflated)
+                .compress_and_write_parallel(data, level, threads)
+                .unwrap();
+
+            let mut archive = Yaz0Archive::new(Cursor::new(&deflated)).unwrap();
+            archive.verify().unwrap();
+            assert!(archive.decompress().unwrap() == data);
+
+            if threads == Some(1) {
+                assert!(deflated[0x10..] == compress(data, level)[..]);
+            }
+        }
+
+        // options are honoured as they are when compressing serially
+        let mut rng = StdRng::seed_from_u64(542);
+        let noise: Vec<u8> = (0..0x1003).map(|_| rng.gen()).collect();
+        let mut stored = Vec::new();
+        Yaz0Writer::new(&mut stored)
+            .store_incompressible()
+            .compress_and_write_parallel(&noise, level, Some(3))
+            .unwrap();
+        assert_eq!(stored.len(), max_compressed_size(noise.len()));
+        assert_eq!(first_mismatch(&stored[0x10..], &noise), None);
+
+        let data: &[u8] = &include_bytes!("../data/test")[..0x30000];
+        let mut unhurried = Vec::new();
+        Yaz0Writer::new(&mut unhurried).compress_and_write_parallel(data, level, Some(2)).unwrap();
+        let mut hurried = Vec::new();
+        Yaz0Writer::new(&mut hurried)
+            .deadline(Duration::ZERO)
+            .compress_and_write_parallel(data, level, Some(2))
+            .unwrap();
+        let mut archive = Yaz0Archive::new(Cursor::new(&hurried)).unwrap();
+        assert!(archive.decompress().unwrap() == data);
+        assert!(hurried.len() > unhurried.len());
+    }
+
+    #[test]
+    fn max_size() {
+        use rand::distributions::Standard;
+        use rand::{self, Rng};
+
+        assert_eq!(max_compressed_size(0), 0x10);
+        assert_eq!(max_compressed_size(1), 0x12);
+        assert_eq!(max_compressed_size(8), 0x19);
+        assert_eq!(max_compressed_size(9), 0x1b);
+
+        for len in [0, 1, 7, 8, 9, 100, 1000].iter().copied() {
+            let data: Vec<u8> = rand::thread_rng().sample_iter(&Standard).take(len).collect();
+            for &level in &[CompressionLevel::Naive { quality: 10 }, CompressionLevel::Lookahead { quality: 10 }] {
+                let mut deflated = Vec::new();
+                Yaz0Writer::new(&mut deflated).compress_and_write(&data, level).unwrap();
+                assert!(deflated.len() <= max_compressed_size(len));
+            }
+        }
+    }
+
+    /// The hash chain match finder should find the same runs as the naive one, as long as none
+    /// are longer than can be encoded.
+    #[test]
+    fn deflate_hash_chain() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x20000];
+
+        for &quality in &[10, 3] {
+            let hashed = compress(data, CompressionLevel::HashChain { quality });
+            assert!(hashed == compress(data, CompressionLevel::Naive { quality }));
+        }
+
+        // long runs of a repeated byte are capped, but still valid
+        let mut data = vec![0; 0x1000];
+        data.extend(0..=255u8);
+        data.extend_from_slice(&[7; 0x800]);
+        let mut deflated = Vec::new();
+        Yaz0Writer::new(&mut deflated)
+            .compress_and_write(&data, CompressionLevel::HashChain { quality: 10 })
+            .unwrap();
+        let inflated = crate::Yaz0Archive::new(std::io::Cursor::new(&deflated)).unwrap().decompress().unwrap();
+        assert!(inflated == data);
+    }
+
+    #[test]
+    #[rustfmt::skip] // don't mess up our arrays 😅
     fn deflate_with_lookahead() {
         const Q: CompressionLevel = CompressionLevel::Lookahead {quality: 10};
 
@@ -344,8 +1852,259 @@ mod test {
         );
     }
 
+    /// The reference file was made by yaz0enc, so matching Nintendo's encoder should reproduce it.
+    #[test]
+    fn match_nintendo() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x4000];
+        let reference = &include_bytes!("../data/test.yaz0")[16..];
+
+        let compressed = compress(data, CompressionLevel::MatchNintendo);
+        // the last few packets differ, since the reference carries on past where we stop
+        let common = compressed.len() - 0x20;
+        assert!(compressed[..common] == reference[..common]);
+
+        let nearest = compress(data, CompressionLevel::Lookahead { quality: 10 });
+        assert!(nearest[..common] != reference[..common]);
+    }
+
+    #[test]
+    fn little_endian() {
+        use crate::inflate::Yaz0Archive;
+        use std::io::Cursor;
+
+        let data: &[u8] = &include_bytes!("../data/test")[..0x4321];
+        let mut compressed = Vec::new();
+        Yaz0Writer::new(&mut compressed)
+            .endianness(Endianness::Little)
+            .compress_and_write(data, CompressionLevel::HashChain { quality: 10 })
+            .unwrap();
+        assert_eq!(&compressed[4..8], [0x21, 0x43, 0, 0]);
+
+        let mut archive = Yaz0Archive::new(Cursor::new(&compressed)).unwrap();
+        assert_eq!(archive.endianness(), Endianness::Little);
+        assert!(archive.decompress().unwrap() == data);
+    }
+
+    #[test]
+    fn store() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x1003];
+
+        let stored = compress(data, CompressionLevel::Store);
+        assert_eq!(stored.len(), max_compressed_size(data.len()) - 0x10);
+        assert_eq!(&stored[..2], [0xff, data[0]]);
+        assert_eq!(stored[stored.len() - 4], 0xe0);
+        assert_eq!(first_mismatch(&stored, data), None);
+
+        // segments end on group boundaries, as with any other level
+        let (head, checkpoint) = compress_segment(data, CompressionLevel::Store, &Checkpoint::start(), 0x13);
+        assert_eq!((checkpoint.read_head, checkpoint.compressed_len), (0x18, 0x1b));
+        let (tail, _) = compress_segment(data, CompressionLevel::Store, &checkpoint, data.len());
+        assert!([head, tail].concat() == stored);
+    }
+
+    #[test]
+    fn level_presets() {
+        assert_eq!(CompressionLevel::from(0), CompressionLevel::Store);
+        assert_eq!(CompressionLevel::from(1), CompressionLevel::fastest());
+        assert_eq!(CompressionLevel::from(8), CompressionLevel::HashChain { quality: 10 });
+        assert_eq!(CompressionLevel::from(9), CompressionLevel::best());
+        assert_eq!(CompressionLevel::from(u8::MAX), CompressionLevel::default());
+
+        // higher levels never compress worse
+        let data: &[u8] = &include_bytes!("../data/test")[..0x4000];
+        let sizes: Vec<usize> = (0..=9).map(|level| compress(data, level.into()).len()).collect();
+        assert!(sizes.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", sizes);
+
+        // out-of-range qualities are clamped rather than panicking
+        assert!(compress(data, CompressionLevel::Lookahead { quality: 0 }) == compress(data, CompressionLevel::Lookahead { quality: 1 }));
+        assert!(compress(data, CompressionLevel::Naive { quality: 11 }) == compress(data, CompressionLevel::Naive { quality: 10 }));
+    }
+
+    #[test]
+    fn store_incompressible() {
+        use rand::{Rng, SeedableRng};
+        use rand::rngs::StdRng;
+
+        let mut rng = StdRng::seed_from_u64(542);
+        let noise: Vec<u8> = (0..0x1003).map(|_| rng.gen()).collect();
+
+        let mut stored = Vec::new();
+        Yaz0Writer::new(&mut stored)
+            .store_incompressible()
+            .compress_and_write(&noise, CompressionLevel::Lookahead { quality: 10 })
+            .unwrap();
+        assert_eq!(stored.len(), max_compressed_size(noise.len()));
+        assert_eq!(first_mismatch(&stored[0x10..], &noise), None);
+
+        // compressible data is compressed as usual
+        let data: &[u8] = &include_bytes!("../data/test")[..0x1000];
+        let mut compressed = Vec::new();
+        Yaz0Writer::new(&mut compressed)
+            .store_incompressible()
+            .compress_and_write(data, CompressionLevel::Lookahead { quality: 10 })
+            .unwrap();
+        assert!(compressed[0x10..] == compress(data, CompressionLevel::Lookahead { quality: 10 })[..]);
+    }
+
+    #[test]
+    fn repack_ops() {
+        use crate::inflate::Yaz0Archive;
+        use std::io::Cursor;
+
+        let reference: &[u8] = include_bytes!("../data/test.yaz0");
+        let mut archive = Yaz0Archive::new(Cursor::new(reference)).unwrap();
+        let ops: Vec<Op> = archive.ops().unwrap().collect::<Result<_, _>>().unwrap();
+
+        let mut repacked = Vec::new();
+        encode_ops(ops, &mut repacked).unwrap();
+        assert!(repacked[..] == reference[..repacked.len()]);
+
+        let mut out = Vec::new();
+        let err = encode_ops(vec![Op::Literal(1), Op::Copy { dist: 2, len: 3 }], &mut out).unwrap_err();
+        assert!(matches!(err, Error::InvalidOp { index: 1, .. }), "{:?}", err);
+        let err = encode_ops(vec![Op::Literal(1), Op::Copy { dist: 1, len: 0x112 }], &mut out).unwrap_err();
+        assert!(matches!(err, Error::InvalidOp { index: 1, .. }), "{:?}", err);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn options() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x4000];
+
+        let options = CompressionOptions { window: 0x100, max_run: 0x20, min_run: 4 };
+        let compressed = compress_with_options(data, CompressionLevel::Lookahead { quality: 10 }, options);
+        for op in OpIter::new(&compressed[..], data.len()) {
+            if let Op::Copy { dist, len } = op.unwrap() {
+                assert!(dist <= 0x100 && (4..=0x20).contains(&len), "{} {}", dist, len);
+            }
+        }
+        assert_eq!(first_mismatch(&compressed, data), None);
+
+        // the defaults are the format's own limits
+        let unlimited = CompressionOptions { window: usize::MAX, max_run: usize::MAX, min_run: 0 };
+        let level = CompressionLevel::HashChain { quality: 10 };
+        assert_eq!(compress_with_options(data, level, unlimited), compress(data, level));
+    }
+
+    #[test]
+    fn run_lengths() {
+        let options = CompressionOptions::default();
+        assert_eq!(options.clip_run(0x11), 0x11);
+        assert_eq!(options.clip_run(0x12), 0x11);
+        assert_eq!(options.clip_run(0x13), 0x13);
+        assert_eq!(options.clip_run(0x1000), MAX_RUN);
+        assert_eq!(CompressionOptions { min_run: 0x12, ..options }.clip_run(0x12), 0x12);
+
+        // only Nintendo's encoder writes 0x12-byte runs
+        let data: &[u8] = &include_bytes!("../data/test")[..0x4000];
+        let lengths = |level| -> Vec<usize> {
+            let compressed = compress_with_options(data, level, options);
+            OpIter::new(&compressed[..], data.len())
+                .filter_map(|op| match op.unwrap() {
+                    Op::Copy { len, .. } => Some(len),
+                    Op::Literal(_) => None,
+                })
+                .collect()
+        };
+        assert!(!lengths(CompressionLevel::Lookahead { quality: 10 }).contains(&0x12));
+        assert!(lengths(CompressionLevel::MatchNintendo).contains(&0x12));
+
+        // matches are only followed as far as a run can reach, so long stretches of one byte
+        // don't cost a scan to the end of the input for every position in the window
+        let zeros = vec![0; 0x10_0000];
+        assert_eq!(naive_runlength(&zeros, 0, 1), MAX_RUN);
+        assert_eq!(find_furthest_run(&zeros, 0x1000, WINDOW_SIZE), Run { cursor: 0, length: MAX_RUN });
+    }
+
+    #[test]
+    fn repeats() {
+        let mut data = include_bytes!("../data/test")[..0x2000].to_vec();
+        data.extend_from_slice(&[0; 0x800]);
+        data.extend_from_slice(&b"abc".repeat(0x200));
+        data.extend_from_slice(&[0; 0x10]);
+
+        // wherever a repeat is found, it's the run a full search of the window would have found
+        let mut found = 0;
+        for cursor in 0..data.len() {
+            if let Some(run) = find_repeat(&data, cursor, WINDOW_SIZE) {
+                let search = (cursor.saturating_sub(WINDOW_SIZE)..cursor).fold(Run::zero(), |best, search_head| {
+                    best.swap_if_better(Run { cursor: search_head, length: naive_runlength(&data, search_head, cursor) })
+                });
+                assert_eq!(run, search, "at {:#x}", cursor);
+                found += 1;
+            }
+        }
+        assert!(found > 0x800);
+
+        assert_eq!(find_repeat(&data, 0x2400, WINDOW_SIZE), Some(Run { cursor: 0x23ff, length: MAX_RUN }));
+        assert_eq!(find_repeat(&data, 0x2900, WINDOW_SIZE), Some(Run { cursor: 0x28fd, length: MAX_RUN }));
+        assert_eq!(find_repeat(&data, 0x2900, 2), None);
+    }
+
+    #[test]
+    fn lazy() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x4000];
+        let lookahead = compress(data, CompressionLevel::Lookahead { quality: 10 });
+        assert_eq!(compress(data, CompressionLevel::Lazy { depth: 1 }), lookahead);
+        assert_eq!(compress(data, CompressionLevel::Lazy { depth: 0 }), lookahead);
+
+        let lazy = compress(data, CompressionLevel::Lazy { depth: 2 });
+        assert_eq!(first_mismatch(&lazy, data), None);
+        assert!(lazy.len() < lookahead.len());
+    }
+
+    #[test]
+    fn auto() {
+        use rand::{Rng, SeedableRng};
+        use rand::rngs::StdRng;
+
+        let auto = |target_ratio, time_budget| CompressionLevel::Auto { target_ratio, time_budget };
+        let data: &[u8] = &include_bytes!("../data/test")[..0x20000];
+
+        // anything will do, so the fastest level does
+        assert_eq!(auto(1., Duration::MAX).resolve(data), CompressionLevel::fastest());
+        // nothing will do, so the smallest output does, unless there's no time for it
+        let smallest = auto(0., Duration::MAX).resolve(data);
+        assert!(!AUTO_CANDIDATES[..2].contains(&smallest), "{:?}", smallest);
+        assert_eq!(auto(0., Duration::ZERO).resolve(data), CompressionLevel::fastest());
+
+        let mut rng = StdRng::seed_from_u64(560);
+        let noise: Vec<u8> = (0..0x10000).map(|_| rng.gen()).collect();
+        assert_eq!(auto(0.5, Duration::MAX).resolve(&noise), CompressionLevel::Store);
+
+        // writers compress at the level picked, and record it as if it had been asked for
+        let mut compressed = Vec::new();
+        Yaz0Writer::new(&mut compressed)
+            .record_settings()
+            .compress_and_write(data, auto(1., Duration::MAX))
+            .unwrap();
+        assert_eq!(&compressed[0x10..], &compress(data, CompressionLevel::fastest())[..]);
+        assert_eq!(BigEndian::read_u32(&compressed[0xc..]), CompressionLevel::fastest().settings_hash());
+    }
+
+    #[test]
+    fn lookback() {
+        let options = CompressionOptions::default();
+        let lookback = |quality| options.lookback(CompressionLevel::HashChain { quality });
+        assert_eq!((lookback(1), lookback(5), lookback(10)), (0x199, 0x800, 0x1000));
+        assert_eq!((lookback(0), lookback(11)), (lookback(1), lookback(10)));
+        assert_eq!(options.lookback(CompressionLevel::MatchNintendo), WINDOW_SIZE);
+
+        let narrow = CompressionOptions { window: 0x100, ..options };
+        assert_eq!(narrow.lookback(CompressionLevel::Naive { quality: 5 }), 0x80);
+
+        // every step up in quality looks further back, and compresses at least as well
+        let data: &[u8] = &include_bytes!("../data/test")[..0x4000];
+        let mut last = (0, usize::MAX);
+        for quality in 1..=10 {
+            let size = compress(data, CompressionLevel::HashChain { quality }).len();
+            assert!(lookback(quality) > last.0 && size <= last.1, "quality {}", quality);
+            last = (lookback(quality), size);
+        }
+    }
+
     #[test]
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     fn deflate_run() {
         const Q: CompressionLevel = CompressionLevel::Lookahead {quality: 10};
 
@@ -376,6 +2135,248 @@ mod test {
         }
     }
 
+    #[test]
+    fn progress_stats() {
+        const Q: CompressionLevel = CompressionLevel::Naive { quality: 10 };
+        let data = [0, 1, 2, 0xa, 0, 1, 2, 3, 0xb, 0, 1, 2, 3, 4, 5, 6, 7];
+
+        let mut last = None;
+        let mut progress = |msg| last = Some(msg);
+        let options = CompressionOptions::default();
+        let compressed = compress_lookaround(&data, Q, &options, &mut progress, &mut Checkpoint::start(), data.len());
+        let last = last.expect("no progress was reported");
+
+        assert_eq!(last.read_head, data.len());
+        assert_eq!(last.compressed_len, compressed.len());
+        assert_eq!((last.literals, last.runs), (10, 2));
+        assert!((last.match_rate() - 7. / 17.).abs() < 1e-9);
+        assert!((last.ratio() - 16. / 17.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn progress_callback() {
+        const Q: CompressionLevel = CompressionLevel::HashChain { quality: 10 };
+        let data: &[u8] = &include_bytes!("../data/test")[..0x2000];
+
+        let mut reports = Vec::new();
+        let mut deflated = Vec::new();
+        Yaz0Writer::new(&mut deflated)
+            .compress_and_write_with_callback(data, Q, |progress| reports.push(progress.read_head))
+            .unwrap();
+
+        assert_eq!(reports.last(), Some(&data.len()));
+        assert!(reports.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        // the channel version reports the same progress
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut channeled = Vec::new();
+        Yaz0Writer::new(&mut channeled).compress_and_write_with_progress(data, Q, tx).unwrap();
+        assert_eq!(rx.iter().map(|progress| progress.read_head).collect::<Vec<_>>(), reports);
+        assert_eq!(channeled, deflated);
+    }
+
+    #[test]
+    fn resume_from_checkpoints() {
+        const Q: CompressionLevel = CompressionLevel::Lookahead { quality: 10 };
+        let data: &[u8] = &include_bytes!("../data/test")[..0x1000];
+
+        let whole = compress(data, Q);
+
+        let mut segmented = Vec::new();
+        let mut checkpoint = Checkpoint::start();
+        for until in (0..=data.len()).step_by(0x123).skip(1).chain(Some(data.len())) {
+            let (encoded, next) = compress_segment(data, Q, &checkpoint, until);
+            segmented.extend(encoded);
+            assert_eq!(next.compressed_len, segmented.len());
+
+            // round-trip through the serialized form, as if we'd been interrupted
+            checkpoint = Checkpoint::from_bytes(&next.to_bytes()).unwrap();
+            assert_eq!(checkpoint, next);
+        }
+
+        assert_eq!(checkpoint.read_head, data.len());
+        assert_eq!(segmented, whole);
+    }
+
+    #[test]
+    fn incremental() {
+        use rand::{Rng, SeedableRng};
+        use rand::rngs::StdRng;
+        use std::io::Cursor;
+
+        let data: &[u8] = &include_bytes!("../data/test")[..0x10000];
+        let mut rng = StdRng::seed_from_u64(561);
+
+        for level in [CompressionLevel::Lookahead { quality: 10 }, CompressionLevel::Lazy { depth: 2 }, CompressionLevel::Store] {
+            let whole = crate::compress(data, level);
+
+            let mut compressor = Compressor::new(Cursor::new(Vec::new()), level).unwrap();
+            let mut fed = 0;
+            while fed < data.len() {
+                let len = rng.gen_range(0, 0x3000).min(data.len() - fed);
+                compressor.feed(&data[fed..fed + len]).unwrap();
+                fed += len;
+                assert!(compressor.buf.len() <= 2 * WINDOW_SIZE + FEED_MARGIN + len);
+            }
+            compressor.flush().unwrap();
+            assert_eq!(compressor.total_in(), data.len());
+            assert_eq!(compressor.finish().unwrap().into_inner(), whole);
+        }
+
+        // without seeking, the size has to be given up front
+        let level = CompressionLevel::HashChain { quality: 4 };
+        let mut compressor = Compressor::with_size(Vec::new(), level, 0x2000).unwrap();
+        compressor.feed(&data[..0x2000]).unwrap();
+        assert_eq!(compressor.finish().unwrap(), crate::compress(&data[..0x2000], level));
+
+        let mut compressor = Compressor::with_size(Vec::new(), level, 0x2000).unwrap();
+        compressor.feed(&data[..0x1000]).unwrap();
+        assert!(matches!(compressor.finish(), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn settings_hash() {
+        use crate::inflate::Yaz0Archive;
+        use std::io::Cursor;
+
+        let levels = [
+            CompressionLevel::Naive { quality: 10 },
+            CompressionLevel::Naive { quality: 5 },
+            CompressionLevel::Lookahead { quality: 10 },
+            CompressionLevel::Lookahead { quality: 5 },
+        ];
+        for (i, a) in levels.iter().enumerate() {
+            for b in &levels[i + 1..] {
+                assert_ne!(a.settings_hash(), b.settings_hash());
+            }
+        }
+
+        let level = CompressionLevel::Lookahead { quality: 10 };
+        let mut deflated = Vec::new();
+        Yaz0Writer::new(&mut deflated)
+            .record_settings()
+            .compress_and_write(&[1, 2, 3], level)
+            .expect("Could not deflate");
+        let archive = Yaz0Archive::new(Cursor::new(&deflated)).unwrap();
+        assert_eq!(archive.settings_hash(), Some(level.settings_hash()));
+
+        let mut deflated = Vec::new();
+        Yaz0Writer::new(&mut deflated)
+            .compress_and_write(&[1, 2, 3], level)
+            .expect("Could not deflate");
+        let archive = Yaz0Archive::new(Cursor::new(&deflated)).unwrap();
+        assert_eq!(archive.settings_hash(), None);
+
+        // other values in the reserved field aren't taken for one
+        let mut deflated = Vec::new();
+        Yaz0Writer::new(&mut deflated)
+            .reserved(0x1234)
+            .compress_and_write(&[1, 2, 3], level)
+            .expect("Could not deflate");
+        let archive = Yaz0Archive::new(Cursor::new(&deflated)).unwrap();
+        assert_eq!(archive.settings_hash(), None);
+    }
+
+    #[test]
+    fn header_fields() {
+        use crate::inflate::Yaz0Archive;
+        use std::io::Cursor;
+
+        let level = CompressionLevel::Naive { quality: 10 };
+
+        let mut deflated = Vec::new();
+        Yaz0Writer::new(&mut deflated)
+            .alignment(0x2000)
+            .reserved(0x1234)
+            .compress_and_write(&[1, 2, 3], level)
+            .expect("Could not deflate");
+        assert_eq!(&deflated[8..0x10], &[0, 0, 0x20, 0, 0, 0, 0x12, 0x34]);
+
+        let archive = Yaz0Archive::new(Cursor::new(&deflated)).unwrap();
+        assert_eq!(archive.alignment(), 0x2000);
+        assert_eq!(archive.reserved(), 0x1234);
+    }
+
+    #[test]
+    fn checksum() {
+        use crate::checksum::{crc32, ChecksumLocation};
+        use crate::inflate::Yaz0Archive;
+
+        let data: &[u8] = &include_bytes!("../data/test")[..0x4000];
+        let level = CompressionLevel::HashChain { quality: 10 };
+
+        // in the reserved field, where it isn't mistaken for a settings hash
+        let mut deflated = Vec::new();
+        Yaz0Writer::new(&mut deflated)
+            .reserved(0x1234)
+            .checksum(ChecksumLocation::Reserved)
+            .compress_and_write(data, level)
+            .unwrap();
+        let mut archive = Yaz0Archive::new(&deflated[..]).unwrap();
+        assert_eq!(archive.reserved(), crc32(data));
+        assert_eq!(archive.settings_hash(), None);
+        archive.verify_checksum(ChecksumLocation::Reserved).unwrap();
+
+        // which leaves no room for one
+        let mut deflated = Vec::new();
+        let err = Yaz0Writer::new(&mut deflated)
+            .record_settings()
+            .checksum(ChecksumLocation::Reserved)
+            .compress_and_write(data, level)
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidOptions { .. }), "{:?}", err);
+        assert!(deflated.is_empty());
+
+        // in a trailer, after the payload
+        for endianness in [Endianness::Big, Endianness::Little] {
+            let mut deflated = Vec::new();
+            Yaz0Writer::new(&mut deflated)
+                .endianness(endianness)
+                .checksum(ChecksumLocation::Trailer)
+                .compress_and_write(data, level)
+                .unwrap();
+            assert!(deflated[0x10..deflated.len() - 4] == compress(data, level)[..]);
+            let mut archive = Yaz0Archive::new(&deflated[..]).unwrap().with_endianness(endianness);
+            archive.verify_checksum(ChecksumLocation::Trailer).unwrap();
+
+            let last = deflated.len() - 1;
+            deflated[last] ^= 1;
+            let mut archive = Yaz0Archive::new(&deflated[..]).unwrap().with_endianness(endianness);
+            let err = archive.verify_checksum(ChecksumLocation::Trailer).unwrap_err();
+            assert!(matches!(err, Error::ChecksumMismatch { actual, .. } if actual == crc32(data)), "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn payload_alignment() {
+        const Q: CompressionLevel = CompressionLevel::Lookahead { quality: 10 };
+        let data = [0, 1, 2, 0xa, 0, 1, 2, 3, 0xb, 0, 1, 2, 3, 4, 5, 6, 7];
+
+        let mut unaligned = Vec::new();
+        Yaz0Writer::new(&mut unaligned)
+            .compress_and_write(&data, Q)
+            .expect("Could not deflate");
+
+        let mut aligned = Vec::new();
+        Yaz0Writer::new(&mut aligned)
+            .payload_alignment(0x40)
+            .compress_and_write(&data, Q)
+            .expect("Could not deflate");
+
+        assert_eq!(&aligned[..0x10], &unaligned[..0x10]);
+        assert_eq!(&aligned[0x10..0x40], &[0; 0x30][..]);
+        assert_eq!(&aligned[0x40..], &unaligned[0x10..]);
+
+        // alignments which the header already satisfies shouldn't pad at all
+        let mut aligned = Vec::new();
+        Yaz0Writer::new(&mut aligned)
+            .payload_alignment(0x8)
+            .compress_and_write(&data, Q)
+            .expect("Could not deflate");
+
+        assert_eq!(aligned, unaligned);
+    }
+
     #[test]
     // this takes way too long on CI. TODO: figure out how to still test this on CI;
     // maybe just build _this one test_ with --release.
@@ -384,6 +2385,7 @@ mod test {
         use indicatif::{ProgressBar, ProgressDrawTarget};
         use crate::inflate::Yaz0Archive;
         use std::io::Cursor;
+        use std::sync::mpsc;
         use std::thread;
 
         let data: &[u8] = include_bytes!("../data/test");
@@ -400,7 +2402,7 @@ mod test {
         let mut deflated = Vec::new();
         Yaz0Writer::new(&mut deflated)
             .compress_and_write_with_progress(
-                &data,
+                data,
                 CompressionLevel::Lookahead { quality: 10 },
                 tx,
             )
@@ -0,0 +1,202 @@
+//! Recompressing modified data by reusing the unchanged parts of the original compressed stream.
+//!
+//! When only part of a large file has changed, the groups of packets encoding the unchanged data
+//! before and after the change can be copied from the original payload as-is, and only the
+//! changed middle needs compressing again.
+
+use std::io::Cursor;
+
+use crate::deflate::{compress_segment, Checkpoint, CompressionLevel};
+use crate::header::Yaz0Header;
+use crate::ops::{encode_ops, Op, OpIter, WINDOW_SIZE};
+use crate::{Error, Yaz0Archive};
+
+/// The start of a group of packets in a stream.
+#[derive(Debug, Clone, Copy)]
+struct GroupStart {
+    /// Offset within the packet stream.
+    compressed_offset: usize,
+    /// Offset within the decompressed data.
+    output_offset: usize,
+}
+
+/// Lists the group boundaries in `payload`, including the end of the stream if the last group is
+/// full.
+fn group_starts(payload: &[u8], expected_size: usize) -> Result<Vec<GroupStart>, Error> {
+    let mut starts = Vec::new();
+    let mut output_offset = 0;
+
+    let mut ops = OpIter::new(payload, expected_size);
+    loop {
+        if ops.at_group_start() {
+            starts.push(GroupStart {
+                compressed_offset: ops.offset(),
+                output_offset,
+            });
+        }
+
+        match ops.next() {
+            Some(op) => output_offset += op?.output_len(),
+            None => break,
+        }
+    }
+
+    Ok(starts)
+}
+
+/// Adds operations to `ops` (which decode to `data[start..]`) until there's a whole number of
+/// groups, by splitting copy runs. Returns `false` if there aren't enough copy runs to split.
+fn pad_to_group(ops: &mut Vec<Op>, data: &[u8], start: usize) -> bool {
+    while ops.len() % 8 != 0 {
+        // prefer peeling a literal off the front of a long run, which adds a single op
+        let split = ops.iter().rposition(|op| matches!(*op, Op::Copy { len, .. } if len > 3));
+        let expand = ops.iter().rposition(|op| matches!(*op, Op::Copy { .. }));
+
+        let i = match split.or(expand) {
+            Some(i) => i,
+            None => return false,
+        };
+        let pos = start + ops[..i].iter().map(Op::output_len).sum::<usize>();
+
+        match ops[i] {
+            Op::Copy { dist, len } if len > 3 => {
+                ops[i] = Op::Copy { dist, len: len - 1 };
+                ops.insert(i, Op::Literal(data[pos]));
+            }
+            _ => {
+                let len = ops[i].output_len();
+                ops.splice(i..=i, data[pos..pos + len].iter().map(|&byte| Op::Literal(byte)));
+            }
+        }
+    }
+
+    true
+}
+
+/// Compresses `modified` at `level` into a Yaz0 file, given `original`, a Yaz0 file whose
+/// contents `modified` is an edited version of.
+///
+/// Groups of packets in `original` encoding an unchanged prefix or suffix of the data are reused
+/// verbatim, so only the part in between is compressed again. This is much faster than
+/// compressing the whole file when the change is small, though the output may be slightly larger.
+pub fn recompress_delta(
+    original: &[u8],
+    modified: &[u8],
+    level: CompressionLevel,
+) -> Result<Vec<u8>, Error> {
+    let old_header = Yaz0Header::parse(&mut Cursor::new(original))?;
+    let old = Yaz0Archive::new(Cursor::new(original))?.decompress()?;
+    let payload = &original[0x10..];
+    let starts = group_starts(payload, old.len())?;
+
+    let prefix_len = old.iter().zip(modified).take_while(|(a, b)| a == b).count();
+    let suffix_len = old.iter().rev().zip(modified.iter().rev()).take_while(|(a, b)| a == b).count();
+
+    // reuse every group that ends within the unchanged prefix
+    let head = *starts
+        .iter()
+        .rev()
+        .find(|start| start.output_offset <= prefix_len)
+        .unwrap_or(&starts[0]);
+
+    // a group can be reused after the change if the data it decodes to, along with the window
+    // before it that its copy runs can reach into, is unchanged
+    let tails = starts.iter().filter(|start| {
+        let reach = start.output_offset.min(WINDOW_SIZE);
+        start.output_offset <= old.len()
+            && old.len() - start.output_offset + reach <= suffix_len
+            && start.output_offset + modified.len() >= head.output_offset + old.len()
+    });
+
+    let mut header = Yaz0Header::new(modified.len());
+    header.alignment = old_header.alignment;
+    let mut out = Vec::new();
+    header.write(&mut out)?;
+    out.extend_from_slice(&payload[..head.compressed_offset]);
+
+    for tail in tails {
+        let middle_end = tail.output_offset + modified.len() - old.len();
+        let (middle, _) = compress_segment(
+            &modified[..middle_end],
+            level,
+            &Checkpoint::at(head.output_offset, head.compressed_offset),
+            middle_end,
+        );
+
+        let mut ops = OpIter::new(&middle[..], middle_end - head.output_offset)
+            .collect::<Result<Vec<_>, _>>()?;
+        if pad_to_group(&mut ops, modified, head.output_offset) {
+            out.extend_from_slice(&encode_ops(&ops));
+            out.extend_from_slice(&payload[tail.compressed_offset..]);
+            return Ok(out);
+        }
+    }
+
+    // nothing after the change could be reused; compress the rest in full
+    let (rest, _) = compress_segment(
+        modified,
+        level,
+        &Checkpoint::at(head.output_offset, head.compressed_offset),
+        modified.len(),
+    );
+    out.extend_from_slice(&rest);
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Yaz0Writer;
+
+    fn compress(data: &[u8], level: CompressionLevel) -> Vec<u8> {
+        let mut out = Vec::new();
+        Yaz0Writer::new(&mut out).compress_and_write(data, level).unwrap();
+        out
+    }
+
+    fn decompress(data: &[u8]) -> Vec<u8> {
+        Yaz0Archive::new(Cursor::new(data)).unwrap().decompress().unwrap()
+    }
+
+    #[test]
+    fn reuses_unchanged_groups() {
+        let level = CompressionLevel::Naive { quality: 10 };
+        let data: &[u8] = &include_bytes!("../data/test")[..0x8000];
+        let original = compress(data, level);
+
+        // overwrite, insert, and delete a few bytes in the middle
+        let mut overwritten = data.to_vec();
+        overwritten[0x4000..0x4010].copy_from_slice(b"0123456789abcdef");
+        let mut inserted = data.to_vec();
+        inserted.splice(0x4000..0x4000, b"some new text".iter().copied());
+        let mut deleted = data.to_vec();
+        deleted.drain(0x4000..0x4100);
+
+        for modified in &[overwritten, inserted, deleted] {
+            let recompressed = recompress_delta(&original, modified, level).unwrap();
+            assert!(decompress(&recompressed) == *modified);
+
+            // both the start and end of the original payload were kept
+            assert!(recompressed[0x10..0x1000] == original[0x10..0x1000]);
+            assert!(recompressed[recompressed.len() - 0x1000..] == original[original.len() - 0x1000..]);
+        }
+    }
+
+    #[test]
+    fn edits_at_the_ends() {
+        let level = CompressionLevel::Lookahead { quality: 10 };
+        let data: &[u8] = &include_bytes!("../data/test")[..0x3000];
+        let original = compress(data, level);
+
+        let mut appended = data.to_vec();
+        appended.extend_from_slice(b"and a bit more");
+        let mut prepended = b"a bit before ".to_vec();
+        prepended.extend_from_slice(data);
+
+        for modified in &[appended, prepended, data.to_vec(), Vec::new()] {
+            let recompressed = recompress_delta(&original, modified, level).unwrap();
+            assert!(decompress(&recompressed) == *modified);
+        }
+    }
+}
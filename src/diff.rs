@@ -0,0 +1,272 @@
+//! Compact patches between two versions of a file's decompressed data, for distributing edits to
+//! Yaz0-compressed files (as romhacks do) without shipping the whole recompressed file.
+//!
+//! A [`Patch`] records only the bytes that changed. Applying it to the original Yaz0 file
+//! decompresses it, makes the edits, and recompresses the result with
+//! [`recompress_delta`](crate::delta::recompress_delta), so the compressed data before and after
+//! the edits is reused rather than compressed again.
+
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+
+use crate::delta::recompress_delta;
+use crate::deflate::CompressionLevel;
+use crate::Error;
+
+const MAGIC: &[u8; 4] = b"Y0PT";
+const HEADER_LEN: usize = 0x14;
+/// The encoded length of a hunk, not counting its inserted bytes. Changes closer together than
+/// this are cheaper to store as one hunk.
+const HUNK_HEADER_LEN: usize = 0xc;
+
+/// One edit made by a [`Patch`]: a stretch of the original data replaced with new bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// Where the edit starts in the original data.
+    pub offset: usize,
+    /// How many bytes of the original data are replaced.
+    pub removed: usize,
+    /// The bytes replacing them.
+    pub inserted: Vec<u8>,
+}
+
+/// The differences between two versions of some data, which can be saved with
+/// [`Patch::to_bytes`] and applied to the original version later.
+///
+/// A patch remembers a hash of the data it was made from, and refuses to apply to anything else.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    original_len: usize,
+    original_hash: u32,
+    modified_len: usize,
+    hunks: Vec<Hunk>,
+}
+
+/// 32-bit FNV-1a, identifying the data a patch applies to.
+fn fnv1a(data: &[u8]) -> u32 {
+    data.iter()
+        .fold(0x811c_9dc5, |hash: u32, &byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+}
+
+impl Patch {
+    /// Works out the patch turning `original` into `modified`.
+    ///
+    /// When the two are the same length, each changed stretch gets its own hunk; otherwise, a
+    /// single hunk covers everything between their common prefix and suffix.
+    pub fn diff(original: &[u8], modified: &[u8]) -> Patch {
+        let prefix = original.iter().zip(modified).take_while(|(a, b)| a == b).count();
+        let suffix = original[prefix..]
+            .iter()
+            .rev()
+            .zip(modified[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let (original_end, modified_end) = (original.len() - suffix, modified.len() - suffix);
+
+        let mut hunks = Vec::new();
+        if original.len() == modified.len() {
+            let mut pos = prefix;
+            while pos < original_end {
+                // take in unchanged gaps too short to be worth starting a new hunk after
+                let start = pos;
+                let mut last_change = pos;
+                while pos < original_end && pos - last_change <= HUNK_HEADER_LEN {
+                    if original[pos] != modified[pos] {
+                        last_change = pos;
+                    }
+                    pos += 1;
+                }
+
+                hunks.push(Hunk {
+                    offset: start,
+                    removed: last_change + 1 - start,
+                    inserted: modified[start..=last_change].to_vec(),
+                });
+
+                pos = last_change + 1;
+                while pos < original_end && original[pos] == modified[pos] {
+                    pos += 1;
+                }
+            }
+        } else {
+            hunks.push(Hunk {
+                offset: prefix,
+                removed: original_end - prefix,
+                inserted: modified[prefix..modified_end].to_vec(),
+            });
+        }
+
+        Patch {
+            original_len: original.len(),
+            original_hash: fnv1a(original),
+            modified_len: modified.len(),
+            hunks,
+        }
+    }
+
+    /// The edits making up the patch, in order.
+    pub fn hunks(&self) -> &[Hunk] {
+        &self.hunks
+    }
+
+    /// Whether the patch changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.hunks.is_empty()
+    }
+
+    /// Applies the patch to `original`, the decompressed data it was made from, returning the
+    /// modified data.
+    ///
+    /// Fails with [`Error::InvalidPatch`] if `original` isn't the data the patch was made from.
+    pub fn apply_to_data(&self, original: &[u8]) -> Result<Vec<u8>, Error> {
+        if original.len() != self.original_len {
+            return Err(Error::InvalidPatch { offset: 4, reason: "original data is the wrong size" });
+        }
+        if fnv1a(original) != self.original_hash {
+            return Err(Error::InvalidPatch { offset: 8, reason: "patch was made from different data" });
+        }
+
+        let mut modified = Vec::with_capacity(self.modified_len);
+        let mut pos = 0;
+        for hunk in &self.hunks {
+            modified.extend_from_slice(&original[pos..hunk.offset]);
+            modified.extend_from_slice(&hunk.inserted);
+            pos = hunk.offset + hunk.removed;
+        }
+        modified.extend_from_slice(&original[pos..]);
+
+        Ok(modified)
+    }
+
+    /// Applies the patch to the Yaz0 file `original`, whose decompressed data the patch was made
+    /// from, returning a new Yaz0 file. The compressed data before the first edit and after the
+    /// last is reused where it can be; the rest is compressed at `level`.
+    pub fn apply(&self, original: &[u8], level: CompressionLevel) -> Result<Vec<u8>, Error> {
+        let modified = self.apply_to_data(&crate::decompress(original)?)?;
+        recompress_delta(original, &modified, level)
+    }
+
+    /// Encodes the patch, for saving it to a file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let inserted: usize = self.hunks.iter().map(|hunk| hunk.inserted.len()).sum();
+        let mut out = Vec::with_capacity(HEADER_LEN + self.hunks.len() * HUNK_HEADER_LEN + inserted);
+
+        out.extend_from_slice(MAGIC);
+        for field in [self.original_len as u32, self.original_hash, self.modified_len as u32, self.hunks.len() as u32] {
+            out.write_u32::<BigEndian>(field).unwrap();
+        }
+        for hunk in &self.hunks {
+            for field in [hunk.offset, hunk.removed, hunk.inserted.len()] {
+                out.write_u32::<BigEndian>(field as u32).unwrap();
+            }
+            out.extend_from_slice(&hunk.inserted);
+        }
+
+        out
+    }
+
+    /// Decodes a patch saved with [`Patch::to_bytes`], checking that its hunks are in order and
+    /// fit within the original data.
+    pub fn from_bytes(data: &[u8]) -> Result<Patch, Error> {
+        let field = |offset: usize| {
+            data.get(offset..offset + 4)
+                .map(|bytes| BigEndian::read_u32(bytes) as usize)
+                .ok_or(Error::InvalidPatch { offset, reason: "patch is truncated" })
+        };
+
+        if !data.starts_with(MAGIC) {
+            return Err(Error::InvalidPatch { offset: 0, reason: "missing patch magic" });
+        }
+        let original_len = field(4)?;
+        let original_hash = field(8)? as u32;
+        let modified_len = field(0xc)?;
+        let count = field(0x10)?;
+
+        let mut hunks = Vec::new();
+        let mut offset = HEADER_LEN;
+        // how far through the original data, and the modified data, the hunks so far reach
+        let (mut original_pos, mut modified_pos) = (0, 0);
+        for _ in 0..count {
+            let (start, removed, len) = (field(offset)?, field(offset + 4)?, field(offset + 8)?);
+            let inserted = data
+                .get(offset + HUNK_HEADER_LEN..offset + HUNK_HEADER_LEN + len)
+                .ok_or(Error::InvalidPatch { offset, reason: "patch is truncated" })?;
+
+            if start < original_pos || start + removed > original_len {
+                return Err(Error::InvalidPatch { offset, reason: "hunk is out of order or out of bounds" });
+            }
+            modified_pos += start - original_pos + len;
+            original_pos = start + removed;
+
+            hunks.push(Hunk { offset: start, removed, inserted: inserted.to_vec() });
+            offset += HUNK_HEADER_LEN + len;
+        }
+
+        if modified_pos + (original_len - original_pos) != modified_len {
+            return Err(Error::InvalidPatch { offset: 0xc, reason: "hunks don't produce the patched size" });
+        }
+
+        Ok(Patch { original_len, original_hash, modified_len, hunks })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scattered_edits() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x8000];
+        let mut modified = data.to_vec();
+        modified[0x100] ^= 0xff;
+        modified[0x108] ^= 0xff;
+        for byte in &mut modified[0x4000..0x4004] {
+            *byte ^= 0xff;
+        }
+
+        let patch = Patch::diff(data, &modified);
+        let offsets: Vec<usize> = patch.hunks().iter().map(|hunk| hunk.offset).collect();
+        assert_eq!(offsets, [0x100, 0x4000]);
+        assert_eq!(patch.to_bytes().len(), HEADER_LEN + 2 * HUNK_HEADER_LEN + 9 + 4);
+
+        assert!(patch.apply_to_data(data).unwrap() == modified);
+        assert!(Patch::diff(data, data).is_empty());
+    }
+
+    #[test]
+    fn apply_to_yaz0() {
+        let level = CompressionLevel::HashChain { quality: 10 };
+        let data: &[u8] = &include_bytes!("../data/test")[..0x8000];
+        let original = crate::compress(data, level);
+
+        let mut inserted = data.to_vec();
+        inserted.splice(0x4000..0x4000, b"some new text".iter().copied());
+        let mut overwritten = data.to_vec();
+        overwritten[0x7ff0..].fill(0);
+
+        for modified in &[inserted, overwritten, Vec::new()] {
+            let patch = Patch::from_bytes(&Patch::diff(data, modified).to_bytes()).unwrap();
+            let patched = patch.apply(&original, level).unwrap();
+            assert!(crate::decompress(&patched).unwrap() == *modified);
+            if !modified.is_empty() {
+                assert!(patched[0x10..0x1000] == original[0x10..0x1000]);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x100];
+        let mut modified = data.to_vec();
+        modified.extend_from_slice(b"more");
+        let patch = Patch::diff(data, &modified);
+
+        let err = patch.apply_to_data(&modified[4..]).unwrap_err();
+        assert!(matches!(err, Error::InvalidPatch { offset: 8, .. }), "{:?}", err);
+        assert!(err.is_corruption());
+
+        let encoded = patch.to_bytes();
+        let err = Patch::from_bytes(&encoded[..encoded.len() - 1]).unwrap_err();
+        assert!(matches!(err, Error::InvalidPatch { offset: HEADER_LEN, .. }), "{:?}", err);
+        assert!(matches!(Patch::from_bytes(b"Yaz0"), Err(Error::InvalidPatch { offset: 0, .. })));
+    }
+}
@@ -1,6 +1,9 @@
+use std::fmt;
+use std::io;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// An error was encountered performing IO operations.
     #[error("backing i/o error")]
@@ -8,4 +11,345 @@ pub enum Error {
     /// The Yaz0 file header's magic was invalid.
     #[error("yaz0 header magic invalid")]
     InvalidMagic,
+    /// The compressed data was malformed.
+    #[error(
+        "corrupt yaz0 data at packet stream offset {offset:#x}: {reason}{}",
+        .context.as_ref().map_or(String::new(), |dump| format!("\n{}", dump))
+    )]
+    CorruptData {
+        /// Offset within the packet stream of the offending packet.
+        offset: usize,
+        reason: &'static str,
+        /// The bytes surrounding the offending packet, if the decoder was asked to
+        /// [annotate its errors](crate::Yaz0Archive::annotate_errors).
+        context: Option<Box<Hexdump>>,
+    },
+    /// A copy run reached back before the start of the output.
+    #[error(
+        "copy run at packet stream offset {offset:#x} reaches back {dist:#x} bytes, but only {produced:#x} have been produced{}",
+        .context.as_ref().map_or(String::new(), |dump| format!("\n{}", dump))
+    )]
+    RunOutOfBounds {
+        /// Offset within the packet stream of the offending packet.
+        offset: usize,
+        /// How far back the run reaches.
+        dist: usize,
+        /// How much output had been produced before the run.
+        produced: usize,
+        /// The bytes surrounding the offending packet, if the decoder was asked to
+        /// [annotate its errors](crate::Yaz0Archive::annotate_errors).
+        context: Option<Box<Hexdump>>,
+    },
+    /// The compressed data ran out before the expected amount of output was produced.
+    #[error("yaz0 data ended after producing {produced:#x} of {expected:#x} bytes")]
+    UnexpectedEof {
+        /// How much output the header said to expect.
+        expected: usize,
+        /// How much output was produced before the data ran out.
+        produced: usize,
+    },
+    /// The compressed data was cut off partway through a copy run.
+    #[error("yaz0 data cut off partway through the packet at packet stream offset {offset:#x}")]
+    TruncatedStream {
+        /// Offset within the packet stream of the cut-off packet.
+        offset: usize,
+        /// How much output had been produced before the packet.
+        produced: usize,
+    },
+    /// The compressed data decodes to more output than the header gives.
+    #[error(
+        "copy run at packet stream offset {offset:#x} runs past the {expected:#x} bytes given in the header, to {decoded:#x}"
+    )]
+    SizeMismatch {
+        /// Offset within the packet stream of the copy run that overran.
+        offset: usize,
+        /// How much output the header said to expect.
+        expected: usize,
+        /// How much output had been decoded by the end of the run.
+        decoded: usize,
+    },
+    /// A SARC archive was malformed.
+    #[error("invalid sarc archive at offset {offset:#x}: {reason}")]
+    InvalidSarc {
+        /// Offset within the archive of the offending structure.
+        offset: usize,
+        reason: &'static str,
+    },
+    /// A patch was malformed, or made from different data than it was applied to.
+    #[error("invalid patch at offset {offset:#x}: {reason}")]
+    InvalidPatch {
+        /// Offset within the encoded patch of the offending field.
+        offset: usize,
+        reason: &'static str,
+    },
+    /// A fixed-size destination was too small to hold the decompressed data.
+    #[error("destination holds {got:#x} bytes, but {needed:#x} are needed")]
+    DestinationTooSmall {
+        /// How many bytes the decompressed data needs.
+        needed: usize,
+        /// How many bytes the destination can hold.
+        got: usize,
+    },
+    /// Freshly compressed data didn't decompress back to the input it was compressed from.
+    #[error("compressed data doesn't round-trip: it first differs from the input at offset {offset:#x}")]
+    VerificationFailed {
+        /// Offset within the input of the first byte that didn't decompress correctly.
+        offset: usize,
+    },
+    /// The decompressed data didn't match the checksum stored with it.
+    #[error("decompressed data has crc32 {actual:#010x}, but {expected:#010x} was stored")]
+    ChecksumMismatch {
+        /// The checksum stored in the file.
+        expected: u32,
+        /// The checksum of the decompressed data.
+        actual: u32,
+    },
+    /// An operation given to [`encode_ops`](crate::deflate::encode_ops) can't be encoded.
+    #[error("operation {index} can't be encoded: {reason}")]
+    InvalidOp {
+        /// Index of the offending operation.
+        index: usize,
+        reason: &'static str,
+    },
+    /// A [`Yaz0Writer`](crate::Yaz0Writer) was set up with options that can't be used together.
+    #[error("conflicting writer options: {reason}")]
+    InvalidOptions { reason: &'static str },
+    /// The header asked for more decompressed data than the caller's limit allows.
+    #[error("yaz0 header asks for {requested:#x} bytes, over the limit of {limit:#x}")]
+    SizeLimitExceeded {
+        /// The decompressed size given in the header.
+        requested: usize,
+        /// The most the caller allowed.
+        limit: usize,
+    },
+}
+
+/// A few rows of the packet stream around the offending packet of a corrupt stream, displayed as
+/// a hexdump with the packet marked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hexdump {
+    /// Offset within the packet stream of the first byte.
+    start: usize,
+    bytes: Vec<u8>,
+    /// Offset within the packet stream of the offending packet.
+    mark: usize,
+}
+
+impl Hexdump {
+    /// How many bytes either side of the offending packet are kept.
+    pub(crate) const RADIUS: usize = 0x20;
+
+    /// Creates a hexdump of `bytes`, read from offset `start` of the packet stream, marking the
+    /// packet at offset `mark`.
+    pub(crate) fn new(start: usize, bytes: Vec<u8>, mark: usize) -> Hexdump {
+        Hexdump { start, bytes, mark }
+    }
+
+    /// Offset within the packet stream of the first byte of the dump.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The dumped bytes of the packet stream.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl fmt::Display for Hexdump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let first_row = self.start & !0xf;
+        let end = self.start + self.bytes.len();
+
+        for row in (first_row..end).step_by(0x10) {
+            write!(f, "  {:08x} ", row)?;
+            for pos in row..row + 0x10 {
+                match pos.checked_sub(self.start).and_then(|i| self.bytes.get(i)) {
+                    Some(byte) => write!(f, " {:02x}", byte)?,
+                    None => write!(f, "   ")?,
+                }
+            }
+            writeln!(f)?;
+
+            if (row..row + 0x10).contains(&self.mark) {
+                let column = 12 + (self.mark - row) * 3;
+                writeln!(f, "{:width$}^^ packet starts here", "", width = column)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Conventional process exit codes, from BSD's `sysexits.h`, for command-line tools to report
+/// failures with, so that scripts can tell them apart.
+pub mod exit_code {
+    /// The command was used incorrectly, e.g. with bad arguments.
+    pub const USAGE: i32 = 64;
+    /// The input data was malformed.
+    pub const DATA_ERROR: i32 = 65;
+    /// The tool itself went wrong, e.g. by asking the library for something impossible.
+    pub const SOFTWARE: i32 = 70;
+    /// An error occurred reading or writing a file.
+    pub const IO_ERROR: i32 = 74;
+}
+
+/// Broad categories of [`enum@Error`], for callers deciding whether to retry, skip, or abort.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The backing reader or writer failed; retrying may succeed.
+    Io,
+    /// The input isn't valid Yaz0 data; retrying won't help.
+    Corruption,
+    /// A caller-imposed resource limit was hit.
+    LimitExceeded,
+    /// The caller asked for something that can't be done, such as encoding an invalid operation;
+    /// it's a bug in the calling code, not a problem with the data.
+    InvalidInput,
+}
+
+impl ErrorKind {
+    /// Returns the conventional [`exit_code`] for a command-line tool failing with an error of
+    /// this kind. Exceeding a limit counts as a data error, since it's the input that's too big.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Io => exit_code::IO_ERROR,
+            ErrorKind::Corruption | ErrorKind::LimitExceeded => exit_code::DATA_ERROR,
+            ErrorKind::InvalidInput => exit_code::SOFTWARE,
+        }
+    }
+}
+
+impl Error {
+    /// Returns the broad category this error falls into.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Io(_) => ErrorKind::Io,
+            Error::InvalidMagic
+            | Error::CorruptData { .. }
+            | Error::RunOutOfBounds { .. }
+            | Error::UnexpectedEof { .. }
+            | Error::TruncatedStream { .. }
+            | Error::SizeMismatch { .. }
+            | Error::VerificationFailed { .. }
+            | Error::ChecksumMismatch { .. }
+            | Error::InvalidSarc { .. }
+            | Error::InvalidPatch { .. } => ErrorKind::Corruption,
+            Error::DestinationTooSmall { .. } | Error::SizeLimitExceeded { .. } => ErrorKind::LimitExceeded,
+            Error::InvalidOp { .. } | Error::InvalidOptions { .. } => ErrorKind::InvalidInput,
+        }
+    }
+
+    /// Returns the conventional [`exit_code`] for a command-line tool failing with this error.
+    pub fn exit_code(&self) -> i32 {
+        self.kind().exit_code()
+    }
+
+    /// Whether this error came from the backing reader or writer.
+    pub fn is_io(&self) -> bool {
+        self.kind() == ErrorKind::Io
+    }
+
+    /// Whether this error was caused by malformed input data.
+    pub fn is_corruption(&self) -> bool {
+        self.kind() == ErrorKind::Corruption
+    }
+
+    /// Whether this error was caused by exceeding a caller-imposed limit.
+    pub fn is_limit_exceeded(&self) -> bool {
+        self.kind() == ErrorKind::LimitExceeded
+    }
+
+    /// Whether this error was caused by the caller asking for something impossible.
+    pub fn is_invalid_input(&self) -> bool {
+        self.kind() == ErrorKind::InvalidInput
+    }
+
+    /// Returns the packet stream offset of the offending packet, and the slot for a hexdump
+    /// around it, for the errors that can carry one.
+    pub(crate) fn context_mut(&mut self) -> Option<(usize, &mut Option<Box<Hexdump>>)> {
+        match self {
+            Error::CorruptData { offset, context, .. } | Error::RunOutOfBounds { offset, context, .. } => {
+                Some((*offset, context))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    /// Surfaces a codec error through `std::io`, so adapters implementing `Read`/`Write` can
+    /// propagate it. Backing i/o errors are passed through untouched, and truncated data is
+    /// reported as `UnexpectedEof`; malformed or oversized data is reported as `InvalidData`, a
+    /// destination that's too small as `WriteZero`, and unencodable operations as `InvalidInput`.
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::Io(e) => e,
+            e @ (Error::UnexpectedEof { .. } | Error::TruncatedStream { .. }) => {
+                io::Error::new(io::ErrorKind::UnexpectedEof, e)
+            }
+            e @ Error::DestinationTooSmall { .. } => io::Error::new(io::ErrorKind::WriteZero, e),
+            e @ (Error::InvalidOp { .. } | Error::InvalidOptions { .. }) => {
+                io::Error::new(io::ErrorKind::InvalidInput, e)
+            }
+            e @ (Error::InvalidMagic
+            | Error::CorruptData { .. }
+            | Error::RunOutOfBounds { .. }
+            | Error::SizeMismatch { .. }
+            | Error::SizeLimitExceeded { .. }
+            | Error::VerificationFailed { .. }
+            | Error::ChecksumMismatch { .. }
+            | Error::InvalidSarc { .. }
+            | Error::InvalidPatch { .. }) => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn into_io_error() {
+        let eof: io::Error = Error::Io(io::ErrorKind::UnexpectedEof.into()).into();
+        assert_eq!(eof.kind(), io::ErrorKind::UnexpectedEof);
+
+        let magic: io::Error = Error::InvalidMagic.into();
+        assert_eq!(magic.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn hexdump() {
+        let dump = Hexdump::new(0xe, (0..0x14).collect(), 0x11);
+        assert_eq!(
+            dump.to_string(),
+            concat!(
+                "  00000000                                            00 01\n",
+                "  00000010  02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f 10 11\n",
+                "               ^^ packet starts here\n",
+                "  00000020  12 13                                          \n",
+            )
+        );
+    }
+
+    #[test]
+    fn classification() {
+        let io = Error::Io(io::ErrorKind::BrokenPipe.into());
+        assert!(io.is_io() && !io.is_corruption() && !io.is_limit_exceeded());
+
+        let magic = Error::InvalidMagic;
+        assert_eq!(magic.kind(), ErrorKind::Corruption);
+        assert!(magic.is_corruption() && !magic.is_io());
+
+        let op = Error::InvalidOp { index: 0, reason: "copies too little" };
+        assert!(op.is_invalid_input() && !op.is_corruption());
+        let invalid: io::Error = Error::InvalidOp { index: 0, reason: "" }.into();
+        assert_eq!(invalid.kind(), io::ErrorKind::InvalidInput);
+
+        assert_eq!(io.exit_code(), exit_code::IO_ERROR);
+        assert_eq!(magic.exit_code(), exit_code::DATA_ERROR);
+        assert_eq!(op.exit_code(), exit_code::SOFTWARE);
+    }
 }
@@ -0,0 +1,215 @@
+//! A C interface, for linking existing C and C++ tools against this implementation.
+//!
+//! Every function returns one of the `YAZ0_*` status codes below, and writes its results through
+//! out-pointers; see `include/yaz0.h` for the matching declarations.
+
+use std::io::Cursor;
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+
+use crate::{CompressionLevel, Error, Yaz0Archive};
+
+/// The call succeeded.
+pub const YAZ0_OK: i32 = 0;
+/// A required pointer was null.
+pub const YAZ0_ERR_NULL_POINTER: i32 = -1;
+/// The input doesn't start with the Yaz0 magic.
+pub const YAZ0_ERR_INVALID_MAGIC: i32 = -2;
+/// The compressed data is malformed.
+pub const YAZ0_ERR_CORRUPT_DATA: i32 = -3;
+/// The compressed data ends before all of the output has been produced.
+pub const YAZ0_ERR_UNEXPECTED_EOF: i32 = -4;
+/// The destination buffer is too small; the size needed is written to the length out-pointer.
+pub const YAZ0_ERR_BUFFER_TOO_SMALL: i32 = -5;
+/// Any other failure, including internal errors.
+pub const YAZ0_ERR_INTERNAL: i32 = -6;
+
+/// Maps a codec error to its status code.
+fn status(err: &Error) -> i32 {
+    match err {
+        Error::InvalidMagic => YAZ0_ERR_INVALID_MAGIC,
+        Error::CorruptData { .. }
+        | Error::RunOutOfBounds { .. }
+        | Error::SizeMismatch { .. }
+        | Error::InvalidSarc { .. }
+        | Error::InvalidPatch { .. }
+        | Error::ChecksumMismatch { .. } => YAZ0_ERR_CORRUPT_DATA,
+        Error::UnexpectedEof { .. } | Error::TruncatedStream { .. } => YAZ0_ERR_UNEXPECTED_EOF,
+        Error::DestinationTooSmall { .. } | Error::SizeLimitExceeded { .. } => YAZ0_ERR_BUFFER_TOO_SMALL,
+        // reading from a slice only fails if it's cut short
+        Error::Io(_) => YAZ0_ERR_UNEXPECTED_EOF,
+        Error::VerificationFailed { .. } | Error::InvalidOp { .. } | Error::InvalidOptions { .. } => {
+            YAZ0_ERR_INTERNAL
+        }
+    }
+}
+
+/// Runs `f`, turning any panic into [`YAZ0_ERR_INTERNAL`] rather than unwinding into C.
+fn guard<F>(f: F) -> i32
+where
+    F: FnOnce() -> i32,
+{
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(YAZ0_ERR_INTERNAL)
+}
+
+/// Builds a slice from a C pointer and length, allowing a null pointer for an empty slice.
+unsafe fn slice_from<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    match (ptr.is_null(), len) {
+        (true, 0) => Some(&[]),
+        (true, _) => None,
+        (false, _) => Some(slice::from_raw_parts(ptr, len)),
+    }
+}
+
+/// Returns an upper bound on the size of the compressed file for `len` bytes of input, for sizing
+/// the destination buffer passed to [`yaz0_compress`].
+#[no_mangle]
+pub extern "C" fn yaz0_max_compressed_size(len: usize) -> usize {
+    crate::max_compressed_size(len)
+}
+
+/// Reads the decompressed size from the header of the Yaz0 file in `src`, into `out_size`.
+///
+/// # Safety
+/// `src` must point to `src_len` readable bytes, and `out_size` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn yaz0_expected_size(src: *const u8, src_len: usize, out_size: *mut usize) -> i32 {
+    let src = match slice_from(src, src_len) {
+        Some(src) if !out_size.is_null() => src,
+        _ => return YAZ0_ERR_NULL_POINTER,
+    };
+
+    guard(|| match Yaz0Archive::new(Cursor::new(src)) {
+        Ok(archive) => {
+            *out_size = archive.expected_size();
+            YAZ0_OK
+        }
+        Err(err) => status(&err),
+    })
+}
+
+/// Decompresses the Yaz0 file in `src` into `dst`, writing the decompressed size to `out_len`.
+///
+/// If `dst` is too small, [`YAZ0_ERR_BUFFER_TOO_SMALL`] is returned, with the size needed in
+/// `out_len`.
+///
+/// # Safety
+/// `src` must point to `src_len` readable bytes, `dst` to `dst_len` writable bytes, and
+/// `out_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn yaz0_decompress(
+    src: *const u8,
+    src_len: usize,
+    dst: *mut u8,
+    dst_len: usize,
+    out_len: *mut usize,
+) -> i32 {
+    let src = match slice_from(src, src_len) {
+        Some(src) if !out_len.is_null() && (!dst.is_null() || dst_len == 0) => src,
+        _ => return YAZ0_ERR_NULL_POINTER,
+    };
+
+    guard(|| {
+        let mut archive = match Yaz0Archive::from_bytes(src) {
+            Ok(archive) => archive,
+            Err(err) => return status(&err),
+        };
+        *out_len = archive.expected_size();
+
+        let dst: &mut [u8] = match dst.is_null() {
+            true => &mut [],
+            false => slice::from_raw_parts_mut(dst, dst_len),
+        };
+        match archive.decompress_into(dst) {
+            Ok(()) => YAZ0_OK,
+            Err(err) => status(&err),
+        }
+    })
+}
+
+/// Compresses `src_len` bytes from `src` into a Yaz0 file at `quality` from 1 to 10 (higher is
+/// smaller but slower; out-of-range values are clamped), writing it to `dst` and its size to
+/// `out_len`.
+///
+/// If `dst` is too small, [`YAZ0_ERR_BUFFER_TOO_SMALL`] is returned, with the size needed in
+/// `out_len`; a buffer of [`yaz0_max_compressed_size`] bytes is always big enough.
+///
+/// # Safety
+/// `src` must point to `src_len` readable bytes, `dst` to `dst_len` writable bytes, and
+/// `out_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn yaz0_compress(
+    src: *const u8,
+    src_len: usize,
+    quality: u32,
+    dst: *mut u8,
+    dst_len: usize,
+    out_len: *mut usize,
+) -> i32 {
+    let src = match slice_from(src, src_len) {
+        Some(src) if !out_len.is_null() && (!dst.is_null() || dst_len == 0) => src,
+        _ => return YAZ0_ERR_NULL_POINTER,
+    };
+
+    guard(|| {
+        let quality = quality.clamp(1, 10) as usize;
+        let compressed = crate::compress(src, CompressionLevel::Lookahead { quality });
+
+        *out_len = compressed.len();
+        if compressed.len() > dst_len {
+            return YAZ0_ERR_BUFFER_TOO_SMALL;
+        }
+
+        dst.copy_from_nonoverlapping(compressed.as_ptr(), compressed.len());
+        YAZ0_OK
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn roundtrip() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x4000];
+
+        let mut compressed = vec![0u8; yaz0_max_compressed_size(data.len())];
+        let mut compressed_len = 0;
+        let status = unsafe {
+            yaz0_compress(data.as_ptr(), data.len(), 10, compressed.as_mut_ptr(), compressed.len(), &mut compressed_len)
+        };
+        assert_eq!(status, YAZ0_OK);
+        compressed.truncate(compressed_len);
+
+        let mut size = 0;
+        let status = unsafe { yaz0_expected_size(compressed.as_ptr(), compressed.len(), &mut size) };
+        assert_eq!((status, size), (YAZ0_OK, data.len()));
+
+        // ask for the size with an empty buffer first
+        let mut len = 0;
+        let status = unsafe { yaz0_decompress(compressed.as_ptr(), compressed.len(), ptr::null_mut(), 0, &mut len) };
+        assert_eq!((status, len), (YAZ0_ERR_BUFFER_TOO_SMALL, data.len()));
+
+        let mut out = vec![0u8; len];
+        let status = unsafe { yaz0_decompress(compressed.as_ptr(), compressed.len(), out.as_mut_ptr(), out.len(), &mut len) };
+        assert_eq!(status, YAZ0_OK);
+        assert!(out == data);
+    }
+
+    #[test]
+    fn errors() {
+        let mut len = 0;
+        let garbage = b"not yaz0 data!!!";
+        let status = unsafe { yaz0_expected_size(garbage.as_ptr(), garbage.len(), &mut len) };
+        assert_eq!(status, YAZ0_ERR_INVALID_MAGIC);
+
+        let status = unsafe { yaz0_expected_size(ptr::null(), 4, &mut len) };
+        assert_eq!(status, YAZ0_ERR_NULL_POINTER);
+
+        let truncated = &include_bytes!("../data/test.yaz0")[..0x100];
+        let mut out = vec![0u8; include_bytes!("../data/test").len()];
+        let status = unsafe { yaz0_decompress(truncated.as_ptr(), truncated.len(), out.as_mut_ptr(), out.len(), &mut len) };
+        assert_eq!(status, YAZ0_ERR_UNEXPECTED_EOF);
+    }
+}
@@ -0,0 +1,119 @@
+//! Identifying the format of the data inside a Yaz0 file from its first few bytes.
+
+use std::io::{Read, Seek};
+
+use crate::{Error, Yaz0Archive};
+
+/// A format commonly found compressed inside Yaz0 files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum InnerFormat {
+    /// `SARC` archive.
+    Sarc,
+    /// `U8` archive.
+    U8,
+    /// `RARC` archive.
+    Rarc,
+    /// `BFRES` model/resource archive.
+    Bfres,
+    /// `BYML` (binary YAML) document, in either byte order.
+    Byml,
+    /// `BNTX` texture container.
+    Bntx,
+    /// `MSBT` message table.
+    Msbt,
+    /// Another Yaz0 file; some tools compress files twice.
+    Yaz0,
+    /// None of the above.
+    Unknown,
+}
+
+/// Magic bytes identifying each format, at the start of the data.
+const MAGICS: &[(&[u8], InnerFormat)] = &[
+    (b"SARC", InnerFormat::Sarc),
+    (b"\x55\xaa\x38\x2d", InnerFormat::U8),
+    (b"RARC", InnerFormat::Rarc),
+    (b"FRES", InnerFormat::Bfres),
+    (b"BY", InnerFormat::Byml),
+    (b"YB", InnerFormat::Byml),
+    (b"BNTX", InnerFormat::Bntx),
+    (b"MsgStdBn", InnerFormat::Msbt),
+    (b"Yaz0", InnerFormat::Yaz0),
+];
+
+/// How many bytes of data are needed to identify any format.
+pub const SNIFF_LEN: usize = 8;
+
+impl InnerFormat {
+    /// Identifies the format of some data from its first few bytes (ideally at least
+    /// [`SNIFF_LEN`] of them), returning the format along with the magic bytes that identified it.
+    /// Unrecognized data has no magic bytes.
+    pub fn identify(head: &[u8]) -> (InnerFormat, &[u8]) {
+        MAGICS
+            .iter()
+            .find(|(magic, _)| head.starts_with(magic))
+            .map_or((InnerFormat::Unknown, &[][..]), |&(magic, format)| (format, &head[..magic.len()]))
+    }
+
+    /// A short, human-readable name for the format.
+    pub fn name(self) -> &'static str {
+        match self {
+            InnerFormat::Sarc => "SARC",
+            InnerFormat::U8 => "U8",
+            InnerFormat::Rarc => "RARC",
+            InnerFormat::Bfres => "BFRES",
+            InnerFormat::Byml => "BYML",
+            InnerFormat::Bntx => "BNTX",
+            InnerFormat::Msbt => "MSBT",
+            InnerFormat::Yaz0 => "Yaz0",
+            InnerFormat::Unknown => "unknown",
+        }
+    }
+}
+
+impl<R> Yaz0Archive<R>
+where
+    R: Read + Seek,
+{
+    /// Identifies the format of the compressed data, decompressing only as much of it as is
+    /// needed to do so. Returns the format along with its magic bytes (empty if unrecognized).
+    pub fn detect_inner_format(&mut self) -> Result<(InnerFormat, Vec<u8>), Error> {
+        let head = self.decompress_prefix(SNIFF_LEN)?;
+        let (format, magic) = InnerFormat::identify(&head);
+        Ok((format, magic.to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Yaz0Writer;
+    use std::io::Cursor;
+
+    #[test]
+    fn identify() {
+        assert_eq!(InnerFormat::identify(b"SARC\x00\x14\xfe\xff"), (InnerFormat::Sarc, &b"SARC"[..]));
+        assert_eq!(InnerFormat::identify(b"YB\x03\x00"), (InnerFormat::Byml, &b"YB"[..]));
+        assert_eq!(InnerFormat::identify(b"MsgStdBn\xff\xfe"), (InnerFormat::Msbt, &b"MsgStdBn"[..]));
+        assert_eq!(InnerFormat::identify(b"Msg"), (InnerFormat::Unknown, &b""[..]));
+        assert_eq!(InnerFormat::identify(b""), (InnerFormat::Unknown, &b""[..]));
+    }
+
+    #[test]
+    fn detect_inner_format() {
+        let mut data = b"RARC".to_vec();
+        data.extend_from_slice(&[0; 0x100]);
+
+        let mut deflated = Vec::new();
+        Yaz0Writer::new(&mut deflated)
+            .compress_and_write(&data, crate::CompressionLevel::Naive { quality: 10 })
+            .unwrap();
+
+        let mut f = Yaz0Archive::new(Cursor::new(&deflated)).unwrap();
+        assert_eq!(f.detect_inner_format().unwrap(), (InnerFormat::Rarc, b"RARC".to_vec()));
+
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let mut f = Yaz0Archive::new(Cursor::new(data)).unwrap();
+        assert_eq!(f.detect_inner_format().unwrap().0, InnerFormat::Unknown);
+    }
+}
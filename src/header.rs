@@ -1,49 +1,211 @@
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
 use crate::error::Error;
 use std::io::{Read, Seek, SeekFrom, Write};
 
+/// The byte order of a Yaz0 header's fields.
+///
+/// Nintendo's files are big-endian, but some tools (and some 3DS-era files) store the header
+/// little-endian. The packet stream is the same either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Endianness {
+    #[default]
+    Big,
+    Little,
+}
+
 /// The header on a Yaz0 file.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Yaz0Header {
     /// Expected size of the decompressed file
     pub expected_size: usize,
+    /// Alignment hint for the decompressed data; used by some games, zero otherwise.
+    pub alignment: u32,
+    /// Reserved; zero in files produced by Nintendo's tools.
+    pub reserved: u32,
+    /// The byte order the fields are stored in.
+    pub endianness: Endianness,
 }
 
 impl Yaz0Header {
+    /// Decompressed sizes above this are taken as a sign the header was read in the wrong byte
+    /// order, when detecting it.
+    const PLAUSIBLE_SIZE: u32 = 0x1000_0000;
+
     pub fn new(expected_size: usize) -> Yaz0Header {
-        Yaz0Header { expected_size }
+        Yaz0Header {
+            expected_size,
+            alignment: 0,
+            reserved: 0,
+            endianness: Endianness::Big,
+        }
     }
 
     /// Parses the header of a Yaz0 file, provided via the passed reader.
     /// Leaves the read head at the start of the data block.
+    ///
+    /// The byte order is detected from the decompressed size: headers are taken to be big-endian
+    /// unless that would make the size implausibly large (over 256 MiB) and little-endian wouldn't.
+    /// Little-endian sizes whose lowest byte is below `0x10` can't be told apart this way; use
+    /// [`Yaz0Header::parse_as`] when the byte order is known.
     pub fn parse<R>(reader: &mut R) -> Result<Yaz0Header, Error>
     where
-        R: Read + Seek,
+        R: Read,
+    {
+        Yaz0Header::parse_with(reader, None)
+    }
+
+    /// Parses the header of a Yaz0 file like [`Yaz0Header::parse`], but in the given byte order
+    /// rather than detecting it.
+    pub fn parse_as<R>(reader: &mut R, endianness: Endianness) -> Result<Yaz0Header, Error>
+    where
+        R: Read,
+    {
+        Yaz0Header::parse_with(reader, Some(endianness))
+    }
+
+    fn parse_with<R>(reader: &mut R, endianness: Option<Endianness>) -> Result<Yaz0Header, Error>
+    where
+        R: Read,
     {
-        let mut magic = [0u8; 4];
-        reader.read_exact(&mut magic)?;
-        if &magic != b"Yaz0" {
+        let mut bytes = [0u8; 0x10];
+        reader.read_exact(&mut bytes[..4])?;
+        if &bytes[..4] != b"Yaz0" {
             return Err(Error::InvalidMagic);
         }
+        reader.read_exact(&mut bytes[4..])?;
 
-        let expected_size = reader.read_u32::<BigEndian>()?;
+        let endianness = endianness.unwrap_or_else(|| {
+            let (big, little) = (BigEndian::read_u32(&bytes[4..]), LittleEndian::read_u32(&bytes[4..]));
+            if big > Self::PLAUSIBLE_SIZE && little <= Self::PLAUSIBLE_SIZE {
+                Endianness::Little
+            } else {
+                Endianness::Big
+            }
+        });
 
-        // consume 8 bytes
-        reader.seek(SeekFrom::Current(8))?;
+        let read_u32 = match endianness {
+            Endianness::Big => BigEndian::read_u32,
+            Endianness::Little => LittleEndian::read_u32,
+        };
 
-        Ok(Yaz0Header::new(expected_size as usize))
+        Ok(Yaz0Header {
+            expected_size: read_u32(&bytes[4..]) as usize,
+            alignment: read_u32(&bytes[8..]),
+            reserved: read_u32(&bytes[12..]),
+            endianness,
+        })
     }
 
-    /// Writes the header of a Yaz0 file to the passed writer.
+    /// Reinterprets the fields as if they'd been read in the byte order `endianness`, for
+    /// overriding a wrongly detected byte order after parsing.
+    pub(crate) fn reinterpret_as(&mut self, endianness: Endianness) {
+        if endianness != self.endianness {
+            self.expected_size = (self.expected_size as u32).swap_bytes() as usize;
+            self.alignment = self.alignment.swap_bytes();
+            self.reserved = self.reserved.swap_bytes();
+            self.endianness = endianness;
+        }
+    }
+
+    /// Writes the header of a Yaz0 file to the passed writer, in its byte order.
     /// Leaves the write head at the start of the data block.
     pub fn write<W>(&self, writer: &mut W) -> Result<(), Error>
     where
         W: Write,
     {
         writer.write_all(b"Yaz0")?;
-        writer.write_u32::<BigEndian>(self.expected_size as u32)?;
-        writer.write_all(&[0x0; 8])?;
+        for field in [self.expected_size as u32, self.alignment, self.reserved] {
+            match self.endianness {
+                Endianness::Big => writer.write_u32::<BigEndian>(field)?,
+                Endianness::Little => writer.write_u32::<LittleEndian>(field)?,
+            }
+        }
 
         Ok(())
     }
+
+    /// Patches the header of an existing Yaz0 file in place, leaving the payload untouched.
+    ///
+    /// The header at the stream's current position is parsed and passed to `patch` for editing,
+    /// then written back over the original, in the byte order it's left with. Leaves the stream
+    /// head at the start of the data block, and returns the updated header.
+    pub fn rewrite<S, F>(stream: &mut S, patch: F) -> Result<Yaz0Header, Error>
+    where
+        S: Read + Write + Seek,
+        F: FnOnce(&mut Yaz0Header),
+    {
+        let header_start = stream.stream_position()?;
+
+        let mut header = Yaz0Header::parse(stream)?;
+        patch(&mut header);
+
+        stream.seek(SeekFrom::Start(header_start))?;
+        header.write(stream)?;
+
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    #[test]
+    fn endianness() {
+        let mut header = Yaz0Header::new(0x4321);
+        header.reserved = 0x1337;
+        header.endianness = Endianness::Little;
+
+        let mut bytes = Vec::new();
+        header.write(&mut bytes).unwrap();
+        assert_eq!(&bytes[4..8], [0x21, 0x43, 0, 0]);
+
+        let parsed = Yaz0Header::parse(&mut &bytes[..]).unwrap();
+        assert_eq!((parsed.expected_size, parsed.reserved, parsed.endianness), (0x4321, 0x1337, Endianness::Little));
+
+        // too ambiguous to detect
+        let mut header = Yaz0Header::new(0x4000);
+        header.endianness = Endianness::Little;
+        let mut bytes = Vec::new();
+        header.write(&mut bytes).unwrap();
+
+        let mut parsed = Yaz0Header::parse(&mut &bytes[..]).unwrap();
+        assert_eq!((parsed.expected_size, parsed.endianness), (0x40_0000, Endianness::Big));
+        parsed.reinterpret_as(Endianness::Little);
+        assert_eq!(parsed.expected_size, 0x4000);
+        assert_eq!(Yaz0Header::parse_as(&mut &bytes[..], Endianness::Little).unwrap().expected_size, 0x4000);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn rewrite_in_place() {
+        let mut file = Cursor::new(vec![
+            // 'Yaz0'
+            0x59, 0x61, 0x7a, 0x30,
+            // 0 bytes, when deflated (bogus)
+            0x00, 0x00, 0x00, 0x00,
+            // alignment 0x80, reserved 0x1337
+            0x00, 0x00, 0x00, 0x80,
+            0x00, 0x00, 0x13, 0x37,
+            // payload
+            0xde, 0xad, 0xbe, 0xef,
+        ]);
+
+        let header = Yaz0Header::rewrite(&mut file, |header| header.expected_size = 0x12345).unwrap();
+
+        assert_eq!(header.expected_size, 0x12345);
+        assert_eq!(file.position(), 0x10);
+        assert_eq!(
+            file.into_inner(),
+            vec![
+                0x59, 0x61, 0x7a, 0x30,
+                0x00, 0x01, 0x23, 0x45,
+                0x00, 0x00, 0x00, 0x80,
+                0x00, 0x00, 0x13, 0x37,
+                0xde, 0xad, 0xbe, 0xef,
+            ]
+        );
+    }
 }
@@ -0,0 +1,158 @@
+//! Restart points within a packet stream, allowing decoding to start partway through.
+
+use std::cmp::Ordering;
+use std::io::Read;
+
+use crate::ops::{Op, OpIter, WINDOW_SIZE};
+use crate::Error;
+
+/// A point in the packet stream at which decoding can be restarted: a group boundary, along with
+/// the window of output preceding it that later copy runs may reference.
+#[derive(Debug, Clone)]
+pub(crate) struct RestartPoint {
+    /// Offset within the packet stream of the group starting here.
+    pub compressed_offset: usize,
+    /// Offset within the decompressed data of the output produced from here.
+    pub output_offset: usize,
+    /// Up to the last `0x1000` bytes of output before this point.
+    pub window: Vec<u8>,
+}
+
+impl RestartPoint {
+    /// Decodes the output from this point on into `dest`, filling it exactly, with the packet
+    /// stream read from `payload`, the whole of it. `expected_size` is the size of all of the
+    /// decompressed data, for reporting errors.
+    pub(crate) fn decode_into(
+        &self,
+        payload: &[u8],
+        dest: &mut [u8],
+        expected_size: usize,
+    ) -> Result<(), Error> {
+        // decode after the window, so that copy runs can reach back into it
+        let mut out = Vec::with_capacity(self.window.len() + dest.len());
+        out.extend_from_slice(&self.window);
+        let start = self.window.len();
+
+        let stream = payload.get(self.compressed_offset..).unwrap_or_default();
+        let mut ops = OpIter::new(stream, dest.len()).starting_at(self.compressed_offset, self.output_offset);
+        while let Some(op) = ops.next() {
+            match op? {
+                Op::Literal(byte) => out.push(byte),
+                Op::Copy { dist, len } => {
+                    if dist > out.len() {
+                        let (offset, produced) = (ops.op_offset(), self.output_offset + out.len() - start);
+                        return Err(Error::RunOutOfBounds { offset, dist, produced, context: None });
+                    }
+                    for _ in 0..len {
+                        out.push(out[out.len() - dist]);
+                    }
+                }
+            }
+        }
+
+        let expected = expected_size;
+        let decoded = self.output_offset + out.len() - start;
+        match (out.len() - start).cmp(&dest.len()) {
+            Ordering::Less => Err(Error::UnexpectedEof { expected, produced: decoded }),
+            Ordering::Greater => Err(Error::SizeMismatch { offset: ops.op_offset(), expected, decoded }),
+            Ordering::Equal => {
+                dest.copy_from_slice(&out[start..]);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// An index of restart points within a Yaz0 file's packet stream, built by scanning it once,
+/// which lets ranges of the decompressed data be decoded without starting from the beginning.
+///
+/// Each restart point keeps a `0x1000`-byte window of the output preceding it in memory.
+/// Build one with [`Yaz0Archive::build_index`](crate::Yaz0Archive::build_index).
+#[derive(Debug, Clone)]
+pub struct Yaz0Index {
+    points: Vec<RestartPoint>,
+}
+
+impl Yaz0Index {
+    /// How much decompressed data there is between restart points by default.
+    pub const DEFAULT_INTERVAL: usize = 0x10000;
+
+    /// Scans the packet stream read from `reader`, positioned at the start of the payload.
+    pub(crate) fn build<R>(reader: R, expected_size: usize, interval: usize) -> Result<Yaz0Index, Error>
+    where
+        R: Read,
+    {
+        let points = build_restart_points(reader, expected_size, interval)?;
+        Ok(Yaz0Index { points })
+    }
+
+    /// Returns the (packet stream offset, decompressed offset) pairs of the restart points, in
+    /// order. The first is always the start of the stream.
+    pub fn checkpoints(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.points
+            .iter()
+            .map(|point| (point.compressed_offset, point.output_offset))
+    }
+
+    /// Returns the last restart point at or before `offset` in the decompressed data.
+    pub(crate) fn restart_point(&self, offset: usize) -> &RestartPoint {
+        let i = self.points.partition_point(|point| point.output_offset <= offset);
+        &self.points[i - 1]
+    }
+}
+
+/// Scans the packet stream read from `reader` (positioned at the start of the payload), recording
+/// a restart point at the first group boundary every `interval` bytes of output.
+/// The first restart point is always the start of the stream.
+fn build_restart_points<R>(
+    reader: R,
+    expected_size: usize,
+    interval: usize,
+) -> Result<Vec<RestartPoint>, Error>
+where
+    R: Read,
+{
+    let mut points = vec![RestartPoint {
+        compressed_offset: 0,
+        output_offset: 0,
+        window: Vec::new(),
+    }];
+
+    let mut window = [0u8; WINDOW_SIZE];
+    let mut pos = 0;
+
+    let mut ops = OpIter::new(reader, expected_size);
+    loop {
+        let last = points.last().unwrap().output_offset;
+        if ops.at_group_start() && pos >= last + interval.max(1) && pos < expected_size {
+            points.push(RestartPoint {
+                compressed_offset: ops.offset(),
+                output_offset: pos,
+                window: (pos.saturating_sub(WINDOW_SIZE)..pos)
+                    .map(|i| window[i % WINDOW_SIZE])
+                    .collect(),
+            });
+        }
+
+        match ops.next() {
+            Some(Ok(Op::Literal(byte))) => {
+                window[pos % WINDOW_SIZE] = byte;
+                pos += 1;
+            }
+            Some(Ok(Op::Copy { dist, len })) => {
+                if dist > pos {
+                    let offset = ops.op_offset();
+                    return Err(Error::RunOutOfBounds { offset, dist, produced: pos, context: None });
+                }
+                for _ in 0..len {
+                    window[pos % WINDOW_SIZE] = window[(pos - dist) % WINDOW_SIZE];
+                    pos += 1;
+                }
+            }
+            Some(Err(e)) => return Err(e),
+            None => break,
+        }
+    }
+
+    Ok(points)
+}
@@ -1,124 +1,2170 @@
 use byteorder::ReadBytesExt;
-use std::io::{Read, Seek, SeekFrom};
+#[cfg(any(unix, windows))]
+use std::fs::File;
+use std::fmt;
+use std::io;
+#[cfg(feature = "digest")]
+use arrayvec::ArrayVec;
+#[cfg(feature = "digest")]
+use digest::{Digest, Output};
+#[cfg(any(unix, windows))]
+use std::io::BufReader;
+use std::io::{Chain, IoSliceMut, Read, Seek, SeekFrom, Take, Write};
+use std::sync::mpsc::Sender;
+use std::thread;
 
-use crate::header::Yaz0Header;
+use crate::checksum::{ChecksumLocation, Crc32};
+use crate::deflate::CompressionLevel;
+use crate::header::{Endianness, Yaz0Header};
+use crate::index::{RestartPoint, Yaz0Index};
+use crate::ops::{ByteIter, Op, OpIter, Yaz0OpIter, WINDOW_SIZE};
+#[cfg(any(unix, windows))]
+use crate::positioned::PositionedReader;
+use crate::sink::{Sink, VectoredSink, WriterSink};
+use crate::error::Hexdump;
 use crate::Error;
 
+/// Hashes bytes as they're written through to an inner [`Sink`].
+///
+/// Since the decompressor only ever writes sequentially, bytes are hashed in output order;
+/// they're batched up so the digest isn't fed a byte at a time.
+#[cfg(feature = "digest")]
+struct DigestSink<'a, S: ?Sized, D> {
+    inner: &'a mut S,
+    digest: D,
+    pending: ArrayVec<[u8; 64]>,
+}
+
+#[cfg(feature = "digest")]
+impl<'a, S, D> DigestSink<'a, S, D>
+where
+    S: Sink + ?Sized,
+    D: Digest,
+{
+    fn new(inner: &'a mut S) -> DigestSink<'a, S, D> {
+        DigestSink {
+            inner,
+            digest: D::new(),
+            pending: ArrayVec::new(),
+        }
+    }
+
+    /// Hashes any remaining batched bytes and returns the digest of everything written.
+    fn finalize(mut self) -> Output<D> {
+        self.digest.update(&self.pending);
+        self.digest.finalize()
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<S, D> Sink for DigestSink<'_, S, D>
+where
+    S: Sink + ?Sized,
+    D: Digest,
+{
+    fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    fn get(&self, pos: usize) -> u8 {
+        self.inner.get(pos)
+    }
+
+    fn set(&mut self, pos: usize, byte: u8) {
+        self.inner.set(pos, byte);
+
+        if self.pending.is_full() {
+            self.digest.update(&self.pending);
+            self.pending.clear();
+        }
+        self.pending.push(byte);
+    }
+}
+
+/// Progress report from the decompressor, passed to a progress callback or sent over a channel.
+#[derive(Debug)]
+pub struct InflateProgress {
+    /// How much of the packet stream has been decoded so far.
+    pub compressed_len: usize,
+    /// How much output has been produced so far.
+    pub output_len: usize,
+}
+
+/// How much output the decompressor produces between progress reports.
+const PROGRESS_INTERVAL: usize = 0x10000;
+
 /// Wraps a reader of Yaz0 data, providing decompression methods.
+///
+/// The reader doesn't need to be seekable (it can be a pipe or a network stream, say), as long as
+/// the payload is only read once. Seekable readers can be rewound to read it again, and unlock
+/// methods like [`Yaz0Archive::verify`] and [`Yaz0Archive::read_at`].
 #[derive(Debug)]
 pub struct Yaz0Archive<R>
 where
-    R: Read + Seek,
+    R: Read,
 {
     reader: R,
 
-    data_start: usize,
+    /// Where the payload starts in the reader, once it's known; it's only needed for seeking.
+    data_start: Option<u64>,
     header: Yaz0Header,
+
+    /// Whether any of the payload has been read since the reader was positioned at its start.
+    consumed: bool,
+    /// How much of the payload the decompressor has read, so that its start can be found again
+    /// from the reader's position afterwards.
+    payload_read: u64,
+    /// Seeks the reader. Only set once the archive has been used through a method needing
+    /// `R: Seek`, since that's the only way of knowing the reader can.
+    seek: Option<fn(&mut R, SeekFrom) -> io::Result<u64>>,
+    /// Lends out the rest of the reader's data, for readers that are already in memory, so the
+    /// decompressor can decode straight out of it. Only set along with `seek`, which moves the
+    /// reader past whatever was decoded.
+    remaining: Option<fn(&R) -> &[u8]>,
+    /// Payload bytes read from the reader ahead of the decompressor.
+    lookahead: ReadAhead,
+
+    annotate_errors: bool,
+
+    /// Restart points for [`Yaz0Archive::read_at`], built on first use.
+    index: Option<Yaz0Index>,
+
+    /// Whether [`Yaz0Archive::decompress_next`] has decompressed the current block, so that the
+    /// next call moves on to the block after it.
+    block_finished: bool,
+}
+
+/// How much of the payload is read from the reader at once.
+const READ_AHEAD_LEN: usize = 0x10000;
+
+/// Bytes read from the reader ahead of where the archive has got to, so that the decompressor
+/// isn't going back to the reader for every byte. They come before whatever the reader returns
+/// next, so they're either read first or seeked back over before the reader is used again.
+#[derive(Default)]
+struct ReadAhead {
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl fmt::Debug for ReadAhead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadAhead").field("pending", &self.pending()).finish()
+    }
+}
+
+impl ReadAhead {
+    /// How many bytes have been read ahead.
+    fn pending(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Returns the bytes read ahead, first reading up to `max` more from `reader` if there aren't
+    /// any.
+    fn fill<R>(&mut self, reader: &mut R, max: usize) -> io::Result<&[u8]>
+    where
+        R: Read + ?Sized,
+    {
+        if self.pending() == 0 {
+            self.buf.resize(max.clamp(1, READ_AHEAD_LEN), 0x00);
+            let len = loop {
+                match reader.read(&mut self.buf) {
+                    Ok(len) => break len,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        self.buf.clear();
+                        self.pos = 0;
+                        return Err(e);
+                    }
+                }
+            };
+            self.buf.truncate(len);
+            self.pos = 0;
+        }
+
+        Ok(&self.buf[self.pos..])
+    }
+
+    /// Seeks `reader` back over the bytes read ahead, and forgets them.
+    fn give_back<R>(&mut self, reader: &mut R, seek: fn(&mut R, SeekFrom) -> io::Result<u64>) -> io::Result<()> {
+        let pending = self.pending();
+        if pending > 0 {
+            seek(reader, SeekFrom::Current(-(pending as i64)))?;
+            self.buf.clear();
+            self.pos = 0;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the payload through the archive's read-ahead buffer, adding up how much has been read
+/// in `read`, so that the archive still knows where the payload started after handing its reader
+/// out.
+struct PayloadReader<'a, R> {
+    reader: &'a mut R,
+    lookahead: &'a mut ReadAhead,
+    read: &'a mut u64,
+    /// How far into the payload the decompressor is sure to read, for readers that can't be seeked
+    /// back over bytes read past the end of the payload; those aren't read ahead any further than
+    /// this. `None` if the reader can be.
+    limit: Option<u64>,
+}
+
+impl<R> Read for PayloadReader<'_, R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let ahead = self.lookahead.fill(self.reader, READ_AHEAD_LEN)?;
+        let n = ahead.len().min(buf.len());
+        buf[..n].copy_from_slice(&ahead[..n]);
+        self.lookahead.pos += n;
+        *self.read += n as u64;
+        Ok(n)
+    }
+}
+
+/// Describes an attempt to reread a payload from a reader that can't be rewound.
+fn unseekable() -> Error {
+    io::Error::other("the payload has already been read, and the reader can't be rewound").into()
 }
 
 impl<R> Yaz0Archive<R>
 where
-    R: Read + Seek,
+    R: Read,
 {
     /// Creates a new `Yaz0` from a reader.
     pub fn new(mut reader: R) -> Result<Yaz0Archive<R>, Error> {
         // Parses header and advances reader to start of data
         let header = Yaz0Header::parse(&mut reader)?;
 
-        let data_start = reader.seek(SeekFrom::Current(0))?;
-
         Ok(Yaz0Archive {
             reader,
             header,
-            data_start: data_start as usize,
+            data_start: None,
+            consumed: false,
+            payload_read: 0,
+            seek: None,
+            remaining: None,
+            lookahead: ReadAhead::default(),
+            annotate_errors: false,
+            index: None,
+            block_finished: false,
         })
     }
 
+    /// Overrides the decompressed size given in the header with `expected_size`, for files whose
+    /// header is known to be wrong. Decompression then stops after `expected_size` bytes, and
+    /// reports data running short of or past it as usual.
+    pub fn with_expected_size(mut self, expected_size: usize) -> Self {
+        self.header.expected_size = expected_size;
+        self
+    }
+
+    /// Reads the header in the byte order `endianness`, for files whose byte order can't be
+    /// detected (see [`Yaz0Header::parse`]).
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.header.reinterpret_as(endianness);
+        self
+    }
+
+    /// Returns the header, for callers decoding the payload themselves.
+    pub fn header(&self) -> &Yaz0Header {
+        &self.header
+    }
+
+    /// Returns the bytes read from the reader ahead of the decompressor, which come before
+    /// whatever the reader returns next.
+    ///
+    /// The decompressor reads the payload in blocks, so it can read past the payload's end into
+    /// whatever follows it. These bytes are given back to the reader once decompression finishes
+    /// if the archive knows the reader can seek (see [`Yaz0Archive::rewind`]); otherwise they're
+    /// kept here.
+    pub fn buffer(&self) -> &[u8] {
+        &self.lookahead.buf[self.lookahead.pos..]
+    }
+
+    /// Unwraps the archive, returning the reader, to carry on reading whatever follows the
+    /// payload. Anything in [`Yaz0Archive::buffer`] is lost, so take it first.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Returns the byte order the header was read in.
+    pub fn endianness(&self) -> Endianness {
+        self.header.endianness
+    }
+
     /// Get the expected size of inflated data from parsed `Yaz0Header`.
     pub fn expected_size(&self) -> usize {
         self.header.expected_size
     }
 
+    /// Returns the header's alignment field: a hint for how to align the decompressed data in
+    /// memory in some games, and zero otherwise.
+    pub fn alignment(&self) -> u32 {
+        self.header.alignment
+    }
+
+    /// Returns the header's reserved field, as is.
+    pub fn reserved(&self) -> u32 {
+        self.header.reserved
+    }
+
+    /// Returns the hash of the compression settings recorded in the header by
+    /// [`Yaz0Writer::record_settings`](crate::Yaz0Writer::record_settings), if there is one.
+    ///
+    /// Only the hashes of this crate's compression levels are reported; anything else in the
+    /// reserved field, like a checksum, isn't (see [`Yaz0Archive::reserved`] for that).
+    pub fn settings_hash(&self) -> Option<u32> {
+        let hash = self.header.reserved;
+        CompressionLevel::from_settings_hash(hash).map(|_| hash)
+    }
+
     /// Decompresses the Yaz0 file, producing a `Vec<u8>` of the decompressed data.
     pub fn decompress(&mut self) -> Result<Vec<u8>, Error> {
-        let mut dest: Vec<u8> = Vec::with_capacity(self.header.expected_size);
-        dest.resize(self.header.expected_size, 0x00);
+        let mut dest: Vec<u8> = vec![0x00; self.header.expected_size];
         self.decompress_into(&mut dest)?;
         Ok(dest)
     }
 
-    /// Decompresses the Yaz0 file into a destination buffer.
+    /// Decompresses the Yaz0 file like [`Yaz0Archive::decompress`], but only if the header asks
+    /// for at most `max_bytes` of decompressed data; otherwise [`Error::SizeLimitExceeded`] is
+    /// returned before anything is allocated or the payload is read. Use this for untrusted
+    /// files, whose headers can ask for up to 4 GiB.
+    pub fn decompress_with_limit(&mut self, max_bytes: usize) -> Result<Vec<u8>, Error> {
+        if self.header.expected_size > max_bytes {
+            return Err(Error::SizeLimitExceeded { requested: self.header.expected_size, limit: max_bytes });
+        }
+
+        self.decompress()
+    }
+
+    /// Decompresses the Yaz0 file, producing a `Vec<u8>` of the decompressed data.
+    /// Progress updates are streamed out of `progress_tx`.
+    pub fn decompress_with_progress(
+        &mut self,
+        progress_tx: Sender<InflateProgress>,
+    ) -> Result<Vec<u8>, Error> {
+        self.decompress_with_callback(|progress| {
+            // ignore errors if the rx is disconnected
+            let _ = progress_tx.send(progress);
+        })
+    }
+
+    /// Decompresses the Yaz0 file, producing a `Vec<u8>` of the decompressed data.
+    /// `progress` is called with progress updates as decompression goes, on the calling thread.
+    pub fn decompress_with_callback<F>(&mut self, mut progress: F) -> Result<Vec<u8>, Error>
+    where
+        F: FnMut(InflateProgress),
+    {
+        let mut dest: Vec<u8> = vec![0x00; self.header.expected_size];
+        self.inflate(&mut dest, &mut progress)?;
+        Ok(dest)
+    }
+
+    /// Decompresses the Yaz0 file into a destination buffer, or any other [`Sink`].
+    ///
+    /// If `dest` is a fixed-size buffer, it must have a length of at least [`Yaz0Archive::expected_size`],
+    /// or [`Error::DestinationTooSmall`] is returned. Growable sinks, like `Vec<u8>`, are extended as needed.
+    ///
+    /// Malformed data is always reported as an error, never a panic, so untrusted files can be
+    /// decompressed safely:
+    /// - a copy run reaching back before the start of the output is [`Error::RunOutOfBounds`];
+    /// - a copy run overshooting `expected_size` is [`Error::SizeMismatch`] (see
+    ///   [`Yaz0Archive::decompress_lenient`] to tolerate it);
+    /// - data running out before `expected_size` bytes have been produced is
+    ///   [`Error::UnexpectedEof`], or [`Error::TruncatedStream`] if it's cut off partway through
+    ///   a copy run.
+    pub fn decompress_into<S>(&mut self, dest: &mut S) -> Result<(), Error>
+    where
+        S: Sink + ?Sized,
+    {
+        self.inflate(dest, &mut |_| {})
+    }
+
+    /// Decompresses the Yaz0 file into several destination buffers, filling each in turn.
+    ///
+    /// The buffers' combined length must be at least [`Yaz0Archive::expected_size`], or
+    /// [`Error::DestinationTooSmall`] is returned.
+    pub fn decompress_into_vectored(&mut self, dest: &mut [IoSliceMut<'_>]) -> Result<(), Error> {
+        self.inflate(&mut VectoredSink::new(dest), &mut |_| {})
+    }
+
+    /// Decompresses the Yaz0 file, streaming the output into `writer` (a file, a memory-mapped
+    /// region, a socket...) rather than building it up in memory; only the window that copy runs
+    /// can reach back into is kept around. Returns the number of bytes written.
     ///
-    /// # Invariants
-    /// `dest` must have a length of at least the required size to decompress successfully (consider using [`Yaz0Archive::expected_size`] to determine this)
-    pub fn decompress_into(&mut self, dest: &mut [u8]) -> Result<(), Error> {
-        assert!(dest.len() >= self.expected_size());
+    /// Output is written in batches as it's produced, so if decompression fails partway, what
+    /// was decoded before the failure may already have been written.
+    pub fn decompress_to_writer<W>(&mut self, writer: &mut W) -> Result<u64, Error>
+    where
+        W: Write + ?Sized,
+    {
+        let mut sink = WriterSink::new(writer);
+        self.inflate(&mut sink, &mut |_| {})?;
+        let (_, written) = sink.finish()?;
+        Ok(written)
+    }
+
+    /// Decompresses the Yaz0 file, producing a `Vec<u8>` of the decompressed data along with
+    /// its digest under `D`, computed as the data is produced.
+    #[cfg(feature = "digest")]
+    pub fn decompress_with_digest<D>(&mut self) -> Result<(Vec<u8>, Output<D>), Error>
+    where
+        D: Digest,
+    {
+        let mut dest: Vec<u8> = vec![0x00; self.header.expected_size];
+        let digest = self.decompress_into_with_digest::<D, _>(&mut dest[..])?;
+        Ok((dest, digest))
+    }
 
-        let mut dest_pos: usize = 0;
+    /// Decompresses the Yaz0 file into a destination buffer or other [`Sink`], returning the
+    /// digest under `D` of the decompressed data, computed as the data is produced.
+    ///
+    /// As with [`Yaz0Archive::decompress_into`], fixed-size buffers must be at least [`Yaz0Archive::expected_size`] long.
+    #[cfg(feature = "digest")]
+    pub fn decompress_into_with_digest<D, S>(&mut self, dest: &mut S) -> Result<Output<D>, Error>
+    where
+        D: Digest,
+        S: Sink + ?Sized,
+    {
+        let mut sink = DigestSink::<_, D>::new(dest);
+        self.inflate(&mut sink, &mut |_| {})?;
+        Ok(sink.finalize())
+    }
 
-        let mut ops_left: u8 = 0;
-        let mut code_byte: u8 = 0;
+    /// Decompresses the Yaz0 file and checks it against the CRC-32 stored at `location` when it
+    /// was written (see [`Yaz0Writer::checksum`](crate::Yaz0Writer::checksum)), returning
+    /// [`Error::ChecksumMismatch`] if they differ. The decompressed data isn't kept.
+    ///
+    /// A trailer is read from just after the payload, so this leaves the reader after it.
+    pub fn verify_checksum(&mut self, location: ChecksumLocation) -> Result<(), Error> {
+        let mut crc = Crc32::new();
+        self.decompress_to_writer(&mut crc)?;
 
-        while dest_pos < self.header.expected_size {
-            if ops_left == 0 {
-                code_byte = self.reader.read_u8()?;
-                ops_left = 8;
+        let expected = match location {
+            ChecksumLocation::Reserved => self.header.reserved,
+            ChecksumLocation::Trailer => {
+                let mut trailer = [0u8; 4];
+                self.payload_reader().read_exact(&mut trailer)?;
+                match self.header.endianness {
+                    Endianness::Big => u32::from_be_bytes(trailer),
+                    Endianness::Little => u32::from_le_bytes(trailer),
+                }
             }
+        };
 
-            if code_byte & 0x80 != 0 {
-                dest[dest_pos] = self.reader.read_u8()?;
-                dest_pos += 1;
-            } else {
-                let byte1: u8 = self.reader.read_u8()?;
-                let byte2: u8 = self.reader.read_u8()?;
-
-                // Calculate where the copy should start
-                let dist = (((byte1 & 0xf) as usize) << 8) | (byte2 as usize);
-                let run_base = dest_pos - (dist + 1);
-
-                // Figure out how many bytes we have to copy
-                let copy_len: usize = match byte1 >> 4 {
-                    0 => self.reader.read_u8()? as usize + 0x12, // read the next input byte and add 0x12
-                                                                 // to get the length to copy
-                    n => n as usize + 2 // otherwise, just take the upper nybble of byte1 and add 2 to get the length
-                };
+        match crc.finish() {
+            actual if actual == expected => Ok(()),
+            actual => Err(Error::ChecksumMismatch { expected, actual }),
+        }
+    }
 
-                for i in 0..copy_len {
-                    dest[dest_pos] = dest[run_base + i];
-                    dest_pos += 1;
-                }
+    /// Returns an iterator over the operations in the compressed stream, parsed without
+    /// decompressing anything. See [`Yaz0OpIter`].
+    ///
+    /// Like decompressing, this reads the payload from its start, rewinding the reader if need be.
+    pub fn ops(&mut self) -> Result<Yaz0OpIter<impl Read + '_>, Error> {
+        self.start_payload()?;
+
+        let expected_size = self.header.expected_size;
+        Ok(Yaz0OpIter::from_payload(self.payload_reader(), expected_size))
+    }
+
+    /// Decompresses a Yaz0 file compressed with the preset dictionary `dict` (see
+    /// [`compress_with_dict`](crate::compress_with_dict)), starting with it in the decoder's
+    /// window. Only the last `0x1000` bytes of `dict` are used.
+    pub fn decompress_with_dict(&mut self, dict: &[u8]) -> Result<Vec<u8>, Error> {
+        let dict = &dict[dict.len().saturating_sub(WINDOW_SIZE)..];
+
+        let mut dest = dict.to_vec();
+        dest.resize(dict.len() + self.header.expected_size, 0x00);
+        self.inflate_from(&mut dest, dict.len(), &mut |_| {})?;
+
+        Ok(dest.split_off(dict.len()))
+    }
+
+    /// Decompresses the next of several Yaz0 files stored back to back in the reader, as some
+    /// container formats do. Returns `None` once the reader runs out.
+    ///
+    /// The first call decompresses the file this archive was created from, leaving the reader at
+    /// the end of its payload; each later call skips any zero padding after the previous file,
+    /// then parses the next file's header and decompresses it. The archive's header accessors
+    /// describe the most recent file. See also [`Yaz0BlockIter`].
+    pub fn decompress_next(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        if self.block_finished {
+            match Self::parse_next_header(&mut self.payload_reader())? {
+                Some(header) => self.header = header,
+                None => return Ok(None),
             }
 
-            // use next operation bit from the code byte
-            code_byte <<= 1;
-            ops_left -= 1;
+            self.data_start = None;
+            self.consumed = false;
+            self.payload_read = 0;
+            self.index = None;
+        }
+
+        let data = self.decompress()?;
+        self.block_finished = true;
+
+        Ok(Some(data))
+    }
+
+    /// Skips any zero padding after a block, then parses the header of the block after it.
+    /// Returns `None` if the reader runs out first.
+    fn parse_next_header(reader: &mut impl Read) -> Result<Option<Yaz0Header>, Error> {
+        let first = loop {
+            match reader.read_u8() {
+                Ok(0) => continue,
+                Ok(byte) => break byte,
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        Yaz0Header::parse(&mut [first].chain(reader)).map(Some)
+    }
+
+    /// Decompresses a Yaz0 file whose header can't be trusted to hold the decompressed size
+    /// (e.g. it's been zeroed or damaged), by decoding until the compressed payload runs out.
+    ///
+    /// The archive's `expected_size` is updated to the recovered size.
+    /// Note that any padding after the payload will be decoded as if it were packet data.
+    pub fn decompress_recovering_size(&mut self) -> Result<Vec<u8>, Error> {
+        let mut dest = Vec::new();
+        let result = self.inflate_until(&mut dest, 0, None, &mut None, &mut |_| {});
+        let size = self.annotate(result)?;
+        self.header.expected_size = size;
+
+        Ok(dest)
+    }
+
+    /// Reads on through the payload, starting with anything already read ahead.
+    fn payload_reader(&mut self) -> PayloadReader<'_, R> {
+        PayloadReader {
+            reader: &mut self.reader,
+            lookahead: &mut self.lookahead,
+            read: &mut self.payload_read,
+            limit: None,
+        }
+    }
+
+    /// Returns where the payload starts in the reader, working it out from the reader's position
+    /// if it isn't known yet. Fails if the reader isn't known to be seekable.
+    ///
+    /// Since this is called before any seek, it also moves the reader back over anything read ahead.
+    fn payload_start(&mut self) -> Result<u64, Error> {
+        let seek = self.seek.ok_or_else(unseekable)?;
+        self.lookahead.give_back(&mut self.reader, seek)?;
+        if let Some(start) = self.data_start {
+            return Ok(start);
+        }
+
+        let here = seek(&mut self.reader, SeekFrom::Current(0))?;
+        let start = if self.consumed { here - self.payload_read } else { here };
+        self.data_start = Some(start);
+
+        Ok(start)
+    }
+
+    /// Gets ready for the decompressor to read the payload from its start, rewinding the reader
+    /// if some of it has been read already.
+    fn start_payload(&mut self) -> Result<(), Error> {
+        if self.consumed {
+            let start = self.payload_start()?;
+            let seek = self.seek.ok_or_else(unseekable)?;
+            seek(&mut self.reader, SeekFrom::Start(start))?;
         }
 
+        self.consumed = true;
+        self.payload_read = 0;
         Ok(())
     }
+
+    /// Attaches a hexdump of the surrounding packet stream to a [`Error::CorruptData`] or
+    /// [`Error::RunOutOfBounds`], if requested with [`Yaz0Archive::annotate_errors`].
+    fn annotate<T>(&mut self, result: Result<T, Error>) -> Result<T, Error> {
+        match result {
+            Err(mut err) if self.annotate_errors => {
+                if let Some((offset, _)) = err.context_mut() {
+                    let dump = self.hexdump_around(offset).ok().map(Box::new);
+                    if let Some((_, context)) = err.context_mut() {
+                        *context = dump;
+                    }
+                }
+                Err(err)
+            }
+            result => result,
+        }
+    }
+
+    /// Rereads the bytes of the packet stream around `offset`.
+    fn hexdump_around(&mut self, offset: usize) -> Result<Hexdump, Error> {
+        let start = offset.saturating_sub(Hexdump::RADIUS);
+        let data_start = self.payload_start()?;
+        let seek = self.seek.ok_or_else(unseekable)?;
+        seek(&mut self.reader, SeekFrom::Start(data_start + start as u64))?;
+        self.consumed = true;
+
+        let mut bytes = Vec::new();
+        (&mut self.reader)
+            .take((offset - start + Hexdump::RADIUS) as u64)
+            .read_to_end(&mut bytes)?;
+
+        Ok(Hexdump::new(start, bytes, offset))
+    }
+
+    /// Runs the decompressor, writing its output into `dest` and passing progress updates to
+    /// `progress`.
+    fn inflate<S>(
+        &mut self,
+        dest: &mut S,
+        progress: &mut dyn FnMut(InflateProgress),
+    ) -> Result<(), Error>
+    where
+        S: Sink + ?Sized,
+    {
+        self.inflate_from(dest, 0, progress)
+    }
+
+    /// Runs the decompressor like [`Yaz0Archive::inflate`], but with the first `start` bytes of
+    /// `dest` holding a preset dictionary, and the output written after it.
+    fn inflate_from<S>(
+        &mut self,
+        dest: &mut S,
+        start: usize,
+        progress: &mut dyn FnMut(InflateProgress),
+    ) -> Result<(), Error>
+    where
+        S: Sink + ?Sized,
+    {
+        let expected = self.header.expected_size;
+        match self.inflate_overrunning(dest, start, progress)? {
+            (decoded, Some(offset)) => Err(Error::SizeMismatch { offset, expected, decoded }),
+            (_, None) => Ok(()),
+        }
+    }
+
+    /// Runs the decompressor like [`Yaz0Archive::inflate_from`], but lets the last copy run
+    /// overrun the end of the output, cutting it short. Returns how much output the stream decoded
+    /// to, and the offset of the copy run that overran, if one did.
+    pub(crate) fn inflate_overrunning<S>(
+        &mut self,
+        dest: &mut S,
+        start: usize,
+        progress: &mut dyn FnMut(InflateProgress),
+    ) -> Result<(usize, Option<usize>), Error>
+    where
+        S: Sink + ?Sized,
+    {
+        let expected = self.header.expected_size;
+        if dest.capacity() < start + expected {
+            return Err(Error::DestinationTooSmall { needed: start + expected, got: dest.capacity() });
+        }
+
+        let mut overrun = None;
+        let result = self.inflate_until(dest, start, Some(expected), &mut overrun, progress);
+        let produced = self.annotate(result)?;
+
+        if produced < expected {
+            return Err(Error::UnexpectedEof { expected, produced });
+        }
+
+        Ok(match overrun {
+            Some((offset, excess)) => (produced + excess, Some(offset)),
+            None => (produced, None),
+        })
+    }
+
+    /// Runs the decompressor until `end` bytes have been produced, or until the compressed stream
+    /// runs out at an operation boundary. Returns the number of bytes produced.
+    ///
+    /// Output is written to `dest` after its first `start` bytes, which copy runs may reach back
+    /// into as a preset dictionary. If the last copy run overshoots `end`, it's cut short, and its
+    /// offset and the number of bytes cut are stored in `overrun`.
+    fn inflate_until<S>(
+        &mut self,
+        dest: &mut S,
+        start: usize,
+        end: Option<usize>,
+        overrun: &mut Option<(usize, usize)>,
+        progress: &mut dyn FnMut(InflateProgress),
+    ) -> Result<usize, Error>
+    where
+        S: Sink + ?Sized,
+    {
+        self.start_payload()?;
+
+        let remaining = match self.remaining {
+            Some(remaining) => remaining,
+            None => {
+                let seek = self.seek;
+                let mut src = self.payload_reader();
+                // without a way of giving back bytes read past the payload, don't read them, unless
+                // the payload runs to the end of the reader anyway
+                if seek.is_none() && end.is_some() {
+                    src.limit = Some(0);
+                }
+
+                let result = inflate_packets(&mut src, dest, start, end, overrun, progress);
+                // leave the reader at the end of the payload, if it can be moved back there
+                if let Some(seek) = seek {
+                    self.lookahead.give_back(&mut self.reader, seek)?;
+                }
+                return result;
+            }
+        };
+
+        // decode straight out of the reader's buffer, then move it past what was decoded
+        let mut payload = remaining(&self.reader);
+        let available = payload.len();
+        let result = inflate_packets(&mut payload, dest, start, end, overrun, progress);
+        let decoded = available - payload.len();
+
+        self.payload_read += decoded as u64;
+        if let Some(seek) = self.seek {
+            seek(&mut self.reader, SeekFrom::Current(decoded as i64))?;
+        }
+        result
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Cursor;
-    use pretty_assertions::assert_eq;
+/// A source of packet stream bytes for [`inflate_packets`].
+trait PacketSource {
+    /// Reads the next byte of the packet stream, or `None` if it's run out.
+    fn next_byte(&mut self) -> Result<Option<u8>, Error>;
 
-    /// Deflate a test .szs file encoded by yaz0enc, and compare to the decompressed file produced by yaz0dec.
-    #[test]
-    fn test_deflate_bianco() {
-        let data: &[u8] = include_bytes!("../data/test.yaz0");
-        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+    /// Notes that at least `n` more bytes of the packet stream will be read, so that a source
+    /// reading ahead knows it can safely read that far.
+    fn needs_at_least(&mut self, _n: usize) {}
+}
 
-        let reader = Cursor::new(data);
+impl<R> PacketSource for PayloadReader<'_, R>
+where
+    R: Read,
+{
+    #[inline]
+    fn next_byte(&mut self) -> Result<Option<u8>, Error> {
+        let byte = match self.lookahead.buf.as_slice().get(self.lookahead.pos) {
+            Some(&byte) => byte,
+            None => {
+                let max = match self.limit {
+                    Some(limit) => limit.saturating_sub(*self.read).min(READ_AHEAD_LEN as u64) as usize,
+                    None => READ_AHEAD_LEN,
+                };
+                match self.lookahead.fill(self.reader, max)?.first() {
+                    Some(&byte) => byte,
+                    None => return Ok(None),
+                }
+            }
+        };
 
-        let mut f = Yaz0Archive::new(reader).unwrap();
+        self.lookahead.pos += 1;
+        *self.read += 1;
+        Ok(Some(byte))
+    }
 
-        let deflated = f.decompress().unwrap();
+    fn needs_at_least(&mut self, n: usize) {
+        if let Some(limit) = &mut self.limit {
+            *limit = *self.read + n as u64;
+        }
+    }
+}
 
-        println!("{} :: {}", deflated.len(), reference_decompressed.len());
+impl PacketSource for &[u8] {
+    #[inline]
+    fn next_byte(&mut self) -> Result<Option<u8>, Error> {
+        match self.split_first() {
+            Some((&byte, rest)) => {
+                *self = rest;
+                Ok(Some(byte))
+            }
+            None => Ok(None),
+        }
+    }
+}
 
-        assert!(deflated == reference_decompressed, "deflated bianco0 did not match reference deflation!");
+/// Decodes the packet stream from `src`, as described for [`Yaz0Archive::inflate_until`].
+fn inflate_packets<P, S>(
+    src: &mut P,
+    dest: &mut S,
+    start: usize,
+    end: Option<usize>,
+    overrun: &mut Option<(usize, usize)>,
+    progress: &mut dyn FnMut(InflateProgress),
+) -> Result<usize, Error>
+where
+    P: PacketSource,
+    S: Sink + ?Sized,
+{
+    let end = end.map(|end| start + end);
+    let mut dest_pos: usize = start;
+    // bytes of the packet stream consumed so far, for reporting where corruption was found
+    let mut offset: usize = 0;
+
+    let mut ops_left: u8 = 0;
+    let mut code_byte: u8 = 0;
+    let mut next_report: usize = 0;
+
+    while end.map_or(true, |end| dest_pos < end) {
+        if ops_left == 0 {
+            if dest_pos >= next_report {
+                progress(InflateProgress { compressed_len: offset, output_len: dest_pos - start });
+                next_report = dest_pos + PROGRESS_INTERVAL;
+            }
+
+            // no operation makes more than 0x111 bytes of output, from three bytes of input
+            if let Some(end) = end {
+                let left = end - dest_pos;
+                src.needs_at_least(left.div_ceil(0x111 / 3) + left.div_ceil(0x111).div_ceil(8));
+            }
+
+            // running out of input at the start of a chunk or an operation may just mean we're done
+            code_byte = match src.next_byte()? {
+                Some(byte) => byte,
+                None => break,
+            };
+            offset += 1;
+            ops_left = 8;
+        }
+
+        let op_offset = offset;
+        let first_byte = match src.next_byte()? {
+            Some(byte) => byte,
+            None => break,
+        };
+        offset += 1;
+
+        // running out partway through a copy operation means the stream was cut off
+        let truncated = || Error::TruncatedStream { offset: op_offset, produced: dest_pos - start };
+
+        if code_byte & 0x80 != 0 {
+            dest.set(dest_pos, first_byte);
+            dest_pos += 1;
+        } else {
+            let byte1: u8 = first_byte;
+            let byte2: u8 = src.next_byte()?.ok_or_else(truncated)?;
+            offset += 1;
+
+            // Calculate where the copy should start
+            let dist = (((byte1 & 0xf) as usize) << 8) | (byte2 as usize);
+            let run_base = match dest_pos.checked_sub(dist + 1) {
+                Some(base) => base,
+                None => {
+                    return Err(Error::RunOutOfBounds {
+                        offset: op_offset,
+                        dist: dist + 1,
+                        produced: dest_pos,
+                        context: None,
+                    });
+                }
+            };
+
+            // Figure out how many bytes we have to copy
+            let copy_len: usize = match byte1 >> 4 {
+                0 => {
+                    // read the next input byte and add 0x12 to get the length to copy
+                    offset += 1;
+                    src.next_byte()?.ok_or_else(truncated)? as usize + 0x12
+                }
+                n => n as usize + 2 // otherwise, just take the upper nybble of byte1 and add 2 to get the length
+            };
+
+            // the last run may overshoot the end of the output; ignore anything past it
+            let copy_len = match end {
+                Some(end) if copy_len > end - dest_pos => {
+                    *overrun = Some((op_offset, copy_len - (end - dest_pos)));
+                    end - dest_pos
+                }
+                _ => copy_len,
+            };
+
+            for i in 0..copy_len {
+                dest.set(dest_pos, dest.get(run_base + i));
+                dest_pos += 1;
+            }
+        }
+
+        // use next operation bit from the code byte
+        code_byte <<= 1;
+        ops_left -= 1;
+    }
+
+    progress(InflateProgress { compressed_len: offset, output_len: dest_pos - start });
+
+    Ok(dest_pos - start)
+}
+
+impl<R> Yaz0Archive<R>
+where
+    R: Read + Seek,
+{
+    /// Creates a new `Yaz0` from a reader, where the compressed payload starts `payload_offset`
+    /// bytes after the start of the header rather than directly after it.
+    ///
+    /// This is needed for files with padding inserted after the header, such as those
+    /// written with [`Yaz0Writer::payload_alignment`](crate::Yaz0Writer::payload_alignment).
+    /// `payload_offset` should be at least `0x10`, the length of the header itself.
+    pub fn with_payload_offset(mut reader: R, payload_offset: usize) -> Result<Yaz0Archive<R>, Error> {
+        let header_start = reader.stream_position()?;
+        let header = Yaz0Header::parse(&mut reader)?;
+
+        let data_start = reader.seek(SeekFrom::Start(header_start + payload_offset as u64))?;
+
+        Ok(Yaz0Archive {
+            reader,
+            header,
+            data_start: Some(data_start),
+            consumed: false,
+            payload_read: 0,
+            seek: Some(R::seek),
+            remaining: None,
+            lookahead: ReadAhead::default(),
+            annotate_errors: false,
+            index: None,
+            block_finished: false,
+        })
+    }
+
+    /// Creates a new `Yaz0` from a reader positioned at the start of a bare packet stream, with
+    /// no header, that decompresses to `expected_size` bytes.
+    ///
+    /// Some container formats embed Yaz0 payloads this way, storing the size elsewhere. If the
+    /// size isn't known, pass `0` and use [`Yaz0Archive::decompress_recovering_size`].
+    pub fn from_raw(mut reader: R, expected_size: usize) -> Result<Yaz0Archive<R>, Error> {
+        let data_start = reader.stream_position()?;
+
+        Ok(Yaz0Archive {
+            reader,
+            header: Yaz0Header::new(expected_size),
+            data_start: Some(data_start),
+            consumed: false,
+            payload_read: 0,
+            seek: Some(R::seek),
+            remaining: None,
+            lookahead: ReadAhead::default(),
+            annotate_errors: false,
+            index: None,
+            block_finished: false,
+        })
+    }
+
+    /// Attach a hexdump of the bytes surrounding the offending packet to any
+    /// [`Error::CorruptData`] reported, to make corruption easier to diagnose.
+    /// This is off by default, since it means rereading part of the input when an error occurs.
+    pub fn annotate_errors(mut self) -> Self {
+        self.seek = Some(R::seek);
+        self.annotate_errors = true;
+        self
+    }
+
+    /// Seeks back to the start of the compressed payload, so that it can be decompressed again.
+    ///
+    /// Methods that need a seekable reader do this for themselves, and once one has been called,
+    /// other methods do too.
+    pub fn rewind(&mut self) -> Result<(), Error> {
+        self.seek_payload(0)?;
+        self.consumed = false;
+        Ok(())
+    }
+
+    /// Returns where the compressed payload starts in the reader: just after the header, unless
+    /// the archive was created with [`Yaz0Archive::with_payload_offset`].
+    pub fn data_start(&mut self) -> Result<u64, Error> {
+        self.seek = Some(R::seek);
+        self.payload_start()
+    }
+
+    /// Seeks to `offset` bytes into the payload, noting that the reader can seek.
+    /// Returns the new position in the reader.
+    fn seek_payload(&mut self, offset: usize) -> Result<u64, Error> {
+        self.seek = Some(R::seek);
+        let start = self.payload_start()?;
+        let pos = self.reader.seek(SeekFrom::Start(start + offset as u64))?;
+        self.consumed = true;
+        Ok(pos)
+    }
+
+    /// Returns a reader over the raw compressed payload, from the end of the header (or the
+    /// configured payload offset) to the end of the underlying reader.
+    pub fn raw_compressed_reader(&mut self) -> Result<Take<&mut R>, Error> {
+        let start = self.seek_payload(0)?;
+        let end = self.reader.seek(SeekFrom::End(0))?;
+        self.reader.seek(SeekFrom::Start(start))?;
+
+        Ok((&mut self.reader).take(end.saturating_sub(start)))
+    }
+
+    /// Scans the compressed data once to build an index of restart points every `interval` bytes
+    /// of decompressed data, which [`Yaz0Archive::read_at`] then uses. Smaller intervals make
+    /// small reads cheaper, at the cost of keeping a `0x1000`-byte window in memory for each
+    /// restart point.
+    pub fn build_index(&mut self, interval: usize) -> Result<&Yaz0Index, Error> {
+        self.seek_payload(0)?;
+
+        let result = Yaz0Index::build(&mut self.reader, self.header.expected_size, interval);
+        let index = self.annotate(result)?;
+
+        Ok(self.index.insert(index))
+    }
+
+    /// Decompresses the Yaz0 file like [`Yaz0Archive::decompress`], but splits the work across
+    /// `threads` threads (or as many as there are CPUs, if `None`), each decoding its own stretch
+    /// of the data from a restart point in the index built by [`Yaz0Archive::build_index`].
+    ///
+    /// Building the index takes about as long as decompressing, so this only pays off once one has
+    /// been built for other reasons, or when a file is decompressed more than once; if there's no
+    /// index yet, the file is just decompressed on the calling thread. The whole compressed payload
+    /// is read into memory first.
+    pub fn decompress_parallel(&mut self, threads: Option<usize>) -> Result<Vec<u8>, Error> {
+        let index = match self.index.take() {
+            Some(index) => index,
+            None => {
+                self.seek_payload(0)?;
+                return self.decompress();
+            }
+        };
+
+        let mut payload = Vec::new();
+        let read = self.raw_compressed_reader().and_then(|mut reader| Ok(reader.read_to_end(&mut payload)?));
+        let expected_size = self.header.expected_size;
+        let result = read.and_then(|_| decode_regions(&index, &payload, expected_size, threads));
+        self.index = Some(index);
+
+        self.annotate(result)
+    }
+
+    /// Decompresses `len` bytes of data, starting at offset `offset` in the decompressed data,
+    /// without decompressing everything before it. Ranges extending past the end of the data are
+    /// cut short.
+    ///
+    /// Decoding starts from the nearest restart point before `offset`, in the index built by
+    /// [`Yaz0Archive::build_index`]; if there isn't one yet, it's built first with
+    /// [`Yaz0Index::DEFAULT_INTERVAL`].
+    pub fn read_at(&mut self, offset: usize, len: usize) -> Result<Vec<u8>, Error> {
+        if self.index.is_none() {
+            self.build_index(Yaz0Index::DEFAULT_INTERVAL)?;
+        }
+
+        let expected_size = self.header.expected_size;
+        let offset = offset.min(expected_size);
+        let len = len.min(expected_size - offset);
+
+        let compressed_offset = self.index.as_ref().unwrap().restart_point(offset).compressed_offset;
+        self.seek_payload(compressed_offset)?;
+
+        let point = self.index.as_ref().unwrap().restart_point(offset);
+
+        let result = ByteIter::<_>::resume(&mut self.reader, expected_size, point)
+            .skip(offset - point.output_offset)
+            .take(len)
+            .collect();
+        self.annotate(result)
+    }
+
+    /// Decompresses only the first `n` bytes of the Yaz0 file (or all of it, if it's shorter),
+    /// stopping as soon as they've been produced. Useful for sniffing the format of the
+    /// compressed data from its first few bytes, without inflating the whole file.
+    ///
+    /// Copy runs in the decoded part are checked as they're decoded: one reaching back before the
+    /// start of the output is reported as [`Error::RunOutOfBounds`]. Data running out before the
+    /// `n` bytes have been produced is [`Error::UnexpectedEof`], or [`Error::TruncatedStream`] if
+    /// it's cut off partway through a copy run.
+    pub fn decompress_prefix(&mut self, n: usize) -> Result<Vec<u8>, Error> {
+        self.seek_payload(0)?;
+
+        let n = n.min(self.header.expected_size);
+        let result = ByteIter::<_>::new(&mut self.reader, self.header.expected_size)
+            .take(n)
+            .collect();
+        self.annotate(result)
+    }
+
+    /// Checks that the compressed data is well-formed, without decompressing it.
+    ///
+    /// Every copy run is checked against the amount of output produced so far, to ensure it
+    /// doesn't reach back before the start of the output or run past its end, using only a
+    /// counter rather than an output buffer.
+    pub fn verify(&mut self) -> Result<(), Error> {
+        let result = self.verify_ops();
+        self.annotate(result)
+    }
+
+    fn verify_ops(&mut self) -> Result<(), Error> {
+        self.seek_payload(0)?;
+
+        let expected_size = self.header.expected_size;
+        let mut ops = OpIter::new(&mut self.reader, expected_size);
+        let mut dest_pos = 0;
+
+        while let Some(op) = ops.next() {
+            let op = op?;
+            let offset = ops.op_offset();
+
+            if let Op::Copy { dist, len } = op {
+                if dist > dest_pos {
+                    return Err(Error::RunOutOfBounds { offset, dist, produced: dest_pos, context: None });
+                }
+                if dest_pos + len > expected_size {
+                    return Err(Error::SizeMismatch { offset, expected: expected_size, decoded: dest_pos + len });
+                }
+            }
+
+            dest_pos += op.output_len();
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Yaz0Archive<io::Cursor<&'a [u8]>> {
+    /// Creates a new `Yaz0` reading straight out of the Yaz0 file `data`, such as a memory-mapped
+    /// file. Decompression then decodes from the slice directly, which is quicker than going
+    /// through a reader a byte at a time.
+    pub fn from_bytes(data: &'a [u8]) -> Result<Yaz0Archive<io::Cursor<&'a [u8]>>, Error> {
+        let mut archive = Yaz0Archive::new(io::Cursor::new(data))?;
+        archive.seek = Some(io::Cursor::seek);
+        archive.remaining = Some(|cursor| {
+            let data: &[u8] = cursor.get_ref();
+            data.get(cursor.position() as usize..).unwrap_or_default()
+        });
+        Ok(archive)
+    }
+}
+
+#[cfg(any(unix, windows))]
+impl<'a> Yaz0Archive<BufReader<PositionedReader<'a>>> {
+    /// Creates a new `Yaz0` reading from a shared `&File` with (buffered) positioned IO, so that
+    /// many archives can read from (and decompress out of) the same file handle at once.
+    pub fn from_file(file: &'a File) -> Result<Yaz0Archive<BufReader<PositionedReader<'a>>>, Error> {
+        Yaz0Archive::new(BufReader::new(PositionedReader::new(file)))
+    }
+}
+
+/// Decodes the packet stream `payload` into `expected_size` bytes of output, on `threads` threads
+/// (or as many as there are CPUs), each starting from a restart point in `index`.
+fn decode_regions(
+    index: &Yaz0Index,
+    payload: &[u8],
+    expected_size: usize,
+    threads: Option<usize>,
+) -> Result<Vec<u8>, Error> {
+    let threads = threads
+        .or_else(|| thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+        .max(1);
+
+    // pick the restart points nearest to splitting the output evenly
+    let mut starts: Vec<&RestartPoint> = (0..threads)
+        .map(|i| index.restart_point(i * expected_size / threads))
+        .collect();
+    starts.dedup_by_key(|point| point.output_offset);
+
+    let mut out = vec![0x00; expected_size];
+    thread::scope(|scope| {
+        let mut rest = &mut out[..];
+        let mut workers = Vec::new();
+        for (i, point) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).map_or(expected_size, |next| next.output_offset);
+            let (region, tail) = rest.split_at_mut(end - point.output_offset);
+            rest = tail;
+            workers.push(scope.spawn(move || point.decode_into(payload, region, expected_size)));
+        }
+
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().expect("decompression thread panicked"))
+    })?;
+
+    Ok(out)
+}
+
+/// An iterator over several Yaz0 files stored back to back in a reader, possibly separated by zero
+/// padding, yielding each one decompressed. Iteration stops after the first error.
+#[derive(Debug)]
+pub struct Yaz0BlockIter<R>
+where
+    R: Read,
+{
+    /// Positioned as if it had just finished the block before the first one.
+    archive: Yaz0Archive<R>,
+    failed: bool,
+}
+
+impl<R> Yaz0BlockIter<R>
+where
+    R: Read,
+{
+    /// Creates an iterator over the Yaz0 files in `reader`, starting from its current position.
+    pub fn new(reader: R) -> Yaz0BlockIter<R> {
+        let archive = Yaz0Archive {
+            reader,
+            header: Yaz0Header::new(0),
+            data_start: None,
+            consumed: false,
+            payload_read: 0,
+            seek: None,
+            remaining: None,
+            lookahead: ReadAhead::default(),
+            annotate_errors: false,
+            index: None,
+            block_finished: true,
+        };
+
+        Yaz0BlockIter { archive, failed: false }
+    }
+}
+
+impl<R> Iterator for Yaz0BlockIter<R>
+where
+    R: Read,
+{
+    type Item = Result<Vec<u8>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let result = self.archive.decompress_next().transpose();
+        self.failed = matches!(result, Some(Err(_)));
+        result
+    }
+}
+
+/// Reads data that may or may not be a Yaz0 file, so that loaders can handle compressed and
+/// plain files the same way: [`MaybeYaz0::decompress`] decompresses Yaz0 files, and returns
+/// anything else (anything not starting with the Yaz0 magic) as is.
+#[derive(Debug)]
+pub struct MaybeYaz0<R>
+where
+    R: Read,
+{
+    inner: MaybeInner<R>,
+}
+
+#[derive(Debug)]
+enum MaybeInner<R>
+where
+    R: Read,
+{
+    Compressed(Yaz0Archive<Chain<io::Cursor<Vec<u8>>, R>>),
+    Plain(Chain<io::Cursor<Vec<u8>>, R>),
+}
+
+impl<R> MaybeYaz0<R>
+where
+    R: Read,
+{
+    /// Peeks at the start of `reader` to see whether it holds a Yaz0 file; if it does, its header
+    /// is parsed.
+    pub fn new(mut reader: R) -> Result<MaybeYaz0<R>, Error> {
+        let mut magic = Vec::with_capacity(4);
+        (&mut reader).take(4).read_to_end(&mut magic)?;
+
+        let compressed = magic == b"Yaz0";
+        let reader = io::Cursor::new(magic).chain(reader);
+        let inner = match compressed {
+            true => MaybeInner::Compressed(Yaz0Archive::new(reader)?),
+            false => MaybeInner::Plain(reader),
+        };
+
+        Ok(MaybeYaz0 { inner })
+    }
+
+    /// Whether the data is a Yaz0 file.
+    pub fn is_compressed(&self) -> bool {
+        matches!(self.inner, MaybeInner::Compressed(_))
+    }
+
+    /// Decompresses the data if it's a Yaz0 file, or reads it as is otherwise.
+    pub fn decompress(self) -> Result<Vec<u8>, Error> {
+        match self.inner {
+            MaybeInner::Compressed(mut archive) => archive.decompress(),
+            MaybeInner::Plain(mut reader) => {
+                let mut data = Vec::new();
+                reader.read_to_end(&mut data)?;
+                Ok(data)
+            }
+        }
+    }
+}
+
+/// Repairs the header of a Yaz0 file whose decompressed size field is zeroed or otherwise wrong,
+/// by decoding the payload until it runs out and writing the recovered size back into the header.
+/// The payload itself is left untouched.
+///
+/// The stream should be positioned at the start of the header. Returns the recovered size.
+pub fn repair_expected_size<S>(stream: &mut S) -> Result<usize, Error>
+where
+    S: Read + Write + Seek,
+{
+    let header_start = stream.stream_position()?;
+
+    let size = Yaz0Archive::new(&mut *stream)?
+        .decompress_recovering_size()?
+        .len();
+
+    stream.seek(SeekFrom::Start(header_start))?;
+    Yaz0Header::rewrite(stream, |header| header.expected_size = size)?;
+
+    Ok(size)
+}
+
+/// What a [`Decompressor`] needs to make further progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// All of the input passed in has been used; call again with more.
+    NeedsInput,
+    /// The output buffer is full; call again with more room.
+    NeedsOutput,
+    /// The whole file has been decompressed. Any input after the end of the payload is left
+    /// unconsumed.
+    Done,
+}
+
+/// Decompresses a Yaz0 file passed to it a piece at a time, into output buffers supplied a piece
+/// at a time, without doing any I/O itself; for event-driven servers, or I/O models that don't fit
+/// [`Read`].
+///
+/// Only the last window of output is kept, so copy runs can reach back past the start of the
+/// current output buffer. Errors are reported as by [`Yaz0Archive::decompress_into`], except that
+/// the data running out is only noticed by [`Decompressor::finish`].
+#[derive(Clone)]
+pub struct Decompressor {
+    /// The header's bytes, until all of them have arrived.
+    header_bytes: [u8; 0x10],
+    header_len: usize,
+    header: Option<Yaz0Header>,
+
+    /// The last window of output, for copy runs to read from.
+    window: Box<[u8; WINDOW_SIZE]>,
+    /// Output produced so far.
+    produced: usize,
+
+    /// Bytes of the packet stream consumed so far.
+    offset: usize,
+    code_byte: u8,
+    ops_left: u8,
+    /// The bytes read so far of a copy run's packet, when it's split between calls.
+    packet: [u8; 3],
+    packet_len: usize,
+    /// Offset of the last operation's packet within the packet stream.
+    op_offset: usize,
+    /// The distance of the copy run being written, and how many bytes of it are left.
+    dist: usize,
+    copy_left: usize,
+}
+
+impl Default for Decompressor {
+    fn default() -> Decompressor {
+        Decompressor::new()
+    }
+}
+
+impl fmt::Debug for Decompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Decompressor")
+            .field("header", &self.header)
+            .field("produced", &self.produced)
+            .field("offset", &self.offset)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Decompressor {
+    pub fn new() -> Decompressor {
+        Decompressor {
+            header_bytes: [0; 0x10],
+            header_len: 0,
+            header: None,
+            window: Box::new([0; WINDOW_SIZE]),
+            produced: 0,
+            offset: 0,
+            code_byte: 0,
+            ops_left: 0,
+            packet: [0; 3],
+            packet_len: 0,
+            op_offset: 0,
+            dist: 0,
+            copy_left: 0,
+        }
+    }
+
+    /// The file's header, once enough input has been passed in to parse it.
+    pub fn header(&self) -> Option<&Yaz0Header> {
+        self.header.as_ref()
+    }
+
+    /// How much input has been consumed so far, header included.
+    pub fn total_in(&self) -> usize {
+        self.header_len + self.offset
+    }
+
+    /// How much output has been produced so far.
+    pub fn total_out(&self) -> usize {
+        self.produced
+    }
+
+    /// Whether the whole file has been decompressed.
+    pub fn is_done(&self) -> bool {
+        self.header.is_some_and(|header| self.produced == header.expected_size)
+    }
+
+    /// Decompresses as much of `input` into `output` as it can, returning how many bytes of
+    /// `input` were consumed, how many bytes of `output` were written, and what's needed next.
+    ///
+    /// Input that's consumed is remembered, so the next call should pass in only what follows it.
+    pub fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<(usize, usize, Status), Error> {
+        let mut consumed = 0;
+        let mut written = 0;
+
+        let expected_size = match self.header {
+            Some(header) => header.expected_size,
+            None => {
+                let len = (self.header_bytes.len() - self.header_len).min(input.len());
+                self.header_bytes[self.header_len..self.header_len + len].copy_from_slice(&input[..len]);
+                self.header_len += len;
+                consumed += len;
+
+                if self.header_len < self.header_bytes.len() {
+                    return Ok((consumed, 0, Status::NeedsInput));
+                }
+                let header = Yaz0Header::parse(&mut &self.header_bytes[..])?;
+                self.header = Some(header);
+                header.expected_size
+            }
+        };
+
+        loop {
+            // finish off the copy run in progress
+            while self.copy_left > 0 {
+                if written == output.len() {
+                    return Ok((consumed, written, Status::NeedsOutput));
+                }
+                let byte = self.window[(self.produced - self.dist) % WINDOW_SIZE];
+                self.push(byte, output, &mut written);
+                self.copy_left -= 1;
+            }
+
+            if self.produced == expected_size {
+                return Ok((consumed, written, Status::Done));
+            }
+
+            if self.ops_left == 0 {
+                let Some(&byte) = input.get(consumed) else {
+                    return Ok((consumed, written, Status::NeedsInput));
+                };
+                consumed += 1;
+                self.offset += 1;
+                self.code_byte = byte;
+                self.ops_left = 8;
+            }
+
+            if self.code_byte & 0x80 != 0 {
+                if written == output.len() {
+                    return Ok((consumed, written, Status::NeedsOutput));
+                }
+                let Some(&byte) = input.get(consumed) else {
+                    return Ok((consumed, written, Status::NeedsInput));
+                };
+                consumed += 1;
+                self.op_offset = self.offset;
+                self.offset += 1;
+                self.push(byte, output, &mut written);
+            } else {
+                if self.packet_len == 0 {
+                    self.op_offset = self.offset;
+                }
+                // a zero length nibble means the length is in a third byte
+                while self.packet_len < 2 || (self.packet_len == 2 && self.packet[0] >> 4 == 0) {
+                    let Some(&byte) = input.get(consumed) else {
+                        return Ok((consumed, written, Status::NeedsInput));
+                    };
+                    consumed += 1;
+                    self.offset += 1;
+                    self.packet[self.packet_len] = byte;
+                    self.packet_len += 1;
+                }
+
+                let [byte1, byte2, byte3] = self.packet;
+                let dist = ((((byte1 & 0xf) as usize) << 8) | (byte2 as usize)) + 1;
+                let len = match byte1 >> 4 {
+                    0 => byte3 as usize + 0x12,
+                    n => n as usize + 2,
+                };
+                self.packet_len = 0;
+
+                if dist > self.produced {
+                    return Err(Error::RunOutOfBounds {
+                        offset: self.op_offset,
+                        dist,
+                        produced: self.produced,
+                        context: None,
+                    });
+                }
+                if self.produced + len > expected_size {
+                    return Err(Error::SizeMismatch {
+                        offset: self.op_offset,
+                        expected: expected_size,
+                        decoded: self.produced + len,
+                    });
+                }
+                self.dist = dist;
+                self.copy_left = len;
+            }
+
+            self.code_byte <<= 1;
+            self.ops_left -= 1;
+        }
+    }
+
+    /// Checks that the whole file has been decompressed, once the input has run out.
+    ///
+    /// Fails with [`Error::UnexpectedEof`] if it hasn't, or [`Error::TruncatedStream`] if the
+    /// input stopped partway through a copy run's packet; an input too short to hold a header
+    /// fails with an I/O error of kind [`UnexpectedEof`](io::ErrorKind::UnexpectedEof).
+    pub fn finish(&self) -> Result<(), Error> {
+        let header = self.header.ok_or_else(|| Error::Io(io::ErrorKind::UnexpectedEof.into()))?;
+
+        if self.produced == header.expected_size {
+            Ok(())
+        } else if self.packet_len > 0 {
+            Err(Error::TruncatedStream { offset: self.op_offset, produced: self.produced })
+        } else {
+            Err(Error::UnexpectedEof { expected: header.expected_size, produced: self.produced })
+        }
+    }
+
+    fn push(&mut self, byte: u8, output: &mut [u8], written: &mut usize) {
+        output[*written] = byte;
+        *written += 1;
+        self.window[self.produced % WINDOW_SIZE] = byte;
+        self.produced += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use pretty_assertions::assert_eq;
+
+    /// Deflate a test .szs file encoded by yaz0enc, and compare to the decompressed file produced by yaz0dec.
+    #[test]
+    fn test_deflate_bianco() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let reader = Cursor::new(data);
+
+        let mut f = Yaz0Archive::new(reader).unwrap();
+
+        let deflated = f.decompress().unwrap();
+
+        println!("{} :: {}", deflated.len(), reference_decompressed.len());
+
+        assert!(deflated == reference_decompressed, "deflated bianco0 did not match reference deflation!");
+    }
+
+    #[test]
+    fn test_ops() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+        let mut archive = Yaz0Archive::new(Cursor::new(data)).unwrap();
+
+        // replaying the operations reproduces the file
+        let mut out = Vec::new();
+        let mut ops = archive.ops().unwrap();
+        while let Some(op) = ops.next() {
+            assert_eq!(ops.output_offset(), out.len());
+            match op.unwrap() {
+                Op::Literal(byte) => out.push(byte),
+                Op::Copy { dist, len } => {
+                    for _ in 0..len {
+                        out.push(out[out.len() - dist]);
+                    }
+                }
+            }
+        }
+        assert_eq!(ops.compressed_offset(), data.len() - 0x10);
+        assert!(out == reference_decompressed);
+        drop(ops);
+
+        // the payload can be read again afterwards
+        archive.rewind().unwrap();
+        assert!(archive.decompress().unwrap() == reference_decompressed);
+        let truncated = Yaz0OpIter::from_payload(&data[0x10..0x100], reference_decompressed.len());
+        assert!(truncated.last().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_maybe_yaz0() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let compressed = MaybeYaz0::new(data).unwrap();
+        assert!(compressed.is_compressed());
+        assert!(compressed.decompress().unwrap() == reference_decompressed);
+
+        for plain in [&b"SARC\x00\x14\xfe\xff"[..], b"Ya", b""] {
+            let maybe = MaybeYaz0::new(plain).unwrap();
+            assert!(!maybe.is_compressed());
+            assert_eq!(maybe.decompress().unwrap(), plain);
+        }
+    }
+
+    /// Stream the test file into a fixed-size buffer, as when writing into a memory map.
+    #[test]
+    fn test_decompress_to_writer() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let mut out = vec![0; reference_decompressed.len()];
+        let written = Yaz0Archive::new(Cursor::new(data))
+            .unwrap()
+            .decompress_to_writer(&mut Cursor::new(&mut out[..]))
+            .unwrap();
+        assert_eq!(written, reference_decompressed.len() as u64);
+        assert!(out == reference_decompressed);
+
+        // the output doesn't fit, so the writer gives out
+        let mut short = [0; 0x100];
+        let err = Yaz0Archive::new(Cursor::new(data))
+            .unwrap()
+            .decompress_to_writer(&mut &mut short[..])
+            .unwrap_err();
+        assert!(matches!(err, Error::Io(ref e) if e.kind() == io::ErrorKind::WriteZero), "{:?}", err);
+    }
+
+    /// Decompress the test file into several unevenly sized buffers (including an empty one),
+    /// so that copy runs straddle buffer boundaries.
+    #[test]
+    fn test_decompress_vectored() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let mut f = Yaz0Archive::new(Cursor::new(data)).unwrap();
+
+        let mut out = vec![0u8; f.expected_size()];
+        {
+            let (a, rest) = out.split_at_mut(7);
+            let (b, rest) = rest.split_at_mut(0);
+            let (c, d) = rest.split_at_mut(0x1000);
+            let mut bufs = [
+                IoSliceMut::new(a),
+                IoSliceMut::new(b),
+                IoSliceMut::new(c),
+                IoSliceMut::new(d),
+            ];
+            f.decompress_into_vectored(&mut bufs).unwrap();
+        }
+
+        assert!(out == reference_decompressed, "vectored decompression did not match reference!");
+    }
+
+    /// Check that the digest computed while decompressing matches hashing the output afterwards.
+    #[cfg(feature = "digest")]
+    #[test]
+    fn test_decompress_with_digest() {
+        use sha2::{Digest, Sha256};
+
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let mut f = Yaz0Archive::new(Cursor::new(data)).unwrap();
+        let (inflated, digest) = f.decompress_with_digest::<Sha256>().unwrap();
+
+        assert!(inflated == reference_decompressed);
+        assert_eq!(digest, Sha256::digest(reference_decompressed));
+    }
+
+    /// Check that a payload padded away from the header can be read when its offset is given.
+    #[test]
+    fn test_payload_offset() {
+        use crate::{CompressionLevel, Yaz0Writer};
+
+        let data: Vec<u8> = (0..0x400).map(|i| (i % 13) as u8).collect();
+
+        let mut deflated = Vec::new();
+        Yaz0Writer::new(&mut deflated)
+            .payload_alignment(0x80)
+            .compress_and_write(&data, CompressionLevel::Lookahead { quality: 10 })
+            .unwrap();
+
+        let mut f = Yaz0Archive::with_payload_offset(Cursor::new(&deflated), 0x80).unwrap();
+        assert_eq!(f.decompress().unwrap(), data);
+    }
+
+    /// Check that the raw payload reader yields exactly the bytes after the header, and that
+    /// using it doesn't disturb later decompression.
+    #[test]
+    fn test_raw_compressed_reader() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let mut f = Yaz0Archive::new(Cursor::new(data)).unwrap();
+
+        let mut raw = Vec::new();
+        f.raw_compressed_reader().unwrap().read_to_end(&mut raw).unwrap();
+        assert!(raw == data[0x10..], "raw payload did not match the file contents after the header!");
+
+        let deflated = f.decompress().unwrap();
+        assert!(deflated == reference_decompressed);
+    }
+
+    #[test]
+    fn test_decompress_prefix() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let mut f = Yaz0Archive::new(Cursor::new(data)).unwrap();
+        assert!(f.decompress_prefix(0x40).unwrap() == reference_decompressed[..0x40]);
+        assert!(f.decompress_prefix(0x12345).unwrap() == reference_decompressed[..0x12345]);
+        assert!(f.decompress_prefix(usize::MAX).unwrap() == reference_decompressed);
+
+        // the prefix stops before any of the file's trailing data is needed
+        let truncated = &data[..0x40];
+        let mut f = Yaz0Archive::new(Cursor::new(truncated)).unwrap();
+        assert!(f.decompress_prefix(0x10).unwrap() == reference_decompressed[..0x10]);
+    }
+
+    #[test]
+    fn test_from_raw() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let mut f = Yaz0Archive::from_raw(Cursor::new(&data[0x10..]), reference_decompressed.len()).unwrap();
+        assert!(f.decompress().unwrap() == reference_decompressed);
+    }
+
+    #[test]
+    fn test_unseekable() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        // a slice reads, but doesn't seek, so the payload can only be read once
+        let mut f = Yaz0Archive::new(data).unwrap();
+        assert!(f.decompress().unwrap() == reference_decompressed);
+        assert!(matches!(f.decompress(), Err(Error::Io(_))));
+
+        // a seekable reader is rewound, once a seeking method has been called
+        let mut f = Yaz0Archive::new(Cursor::new(data)).unwrap();
+        assert!(f.decompress().unwrap() == reference_decompressed);
+        f.rewind().unwrap();
+        assert!(f.decompress().unwrap() == reference_decompressed);
+        assert!(f.decompress_prefix(0x10).unwrap() == reference_decompressed[..0x10]);
+        assert!(f.decompress().unwrap() == reference_decompressed);
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        /// Counts the reads made of a seekable reader.
+        struct Counted<'a> {
+            inner: Cursor<&'a [u8]>,
+            reads: usize,
+        }
+
+        impl Read for Counted<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.reads += 1;
+                self.inner.read(buf)
+            }
+        }
+
+        impl Seek for Counted<'_> {
+            fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+                self.inner.seek(pos)
+            }
+        }
+
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        // the payload is read in chunks, rather than a byte at a time, though not knowing the reader
+        // can seek, they're kept short enough that it isn't read past
+        let mut f = Yaz0Archive::new(Counted { inner: Cursor::new(data), reads: 0 }).unwrap();
+        assert!(f.decompress().unwrap() == reference_decompressed);
+        assert!(f.reader.reads < 0x800, "{} reads", f.reader.reads);
+        assert_eq!(f.reader.inner.position(), data.len() as u64);
+
+        // once it's known to, the chunks are large, and bytes read past the end of the payload
+        // are given back
+        f.rewind().unwrap();
+        assert_eq!(f.reader.inner.position(), 0x10);
+        f.reader.reads = 0;
+        assert!(f.decompress().unwrap() == reference_decompressed);
+        assert!(f.reader.reads < 0x10, "{} reads", f.reader.reads);
+        assert_eq!(f.reader.inner.position(), data.len() as u64);
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+        let mut input = data.to_vec();
+        input.extend_from_slice(b"NEXTFILE");
+
+        // reading ahead stops at the end of the payload, whether or not the reader can seek
+        let mut cursor = Cursor::new(&input[..]);
+        assert!(Yaz0Archive::new(&mut cursor).unwrap().decompress().unwrap() == reference_decompressed);
+        let mut rest = Vec::new();
+        cursor.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"NEXTFILE");
+
+        let mut reader = &input[..];
+        assert!(Yaz0Archive::new(&mut reader).unwrap().decompress().unwrap() == reference_decompressed);
+        assert_eq!(reader, b"NEXTFILE");
+    }
+
+    #[test]
+    fn test_decompress_with_limit() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+        let len = reference_decompressed.len();
+
+        let mut f = Yaz0Archive::new(data).unwrap();
+        let err = f.decompress_with_limit(len - 1).unwrap_err();
+        assert!(matches!(err, Error::SizeLimitExceeded { requested, limit } if requested == len && limit == len - 1));
+        assert!(err.is_limit_exceeded());
+        // nothing was read, so the payload's still there to decompress
+        assert!(f.decompress_with_limit(len).unwrap() == reference_decompressed);
+
+        // a header claiming 4 GiB is refused without allocating
+        let mut huge = data.to_vec();
+        huge[4..8].copy_from_slice(&[0xff; 4]);
+        let err = Yaz0Archive::new(&huge[..]).unwrap().decompress_with_limit(1 << 20).unwrap_err();
+        assert!(matches!(err, Error::SizeLimitExceeded { requested: 0xffff_ffff, .. }), "{:?}", err);
+
+        // unless the header's size is overridden with the right one
+        let mut f = Yaz0Archive::new(&huge[..]).unwrap().with_expected_size(len);
+        assert!(f.decompress_with_limit(len).unwrap() == reference_decompressed);
+    }
+
+    #[test]
+    fn test_concatenated() {
+        use crate::{compress, CompressionLevel};
+
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+        let level = CompressionLevel::HashChain { quality: 10 };
+        let parts = [&reference_decompressed[..0x800], &[], &reference_decompressed[0x800..0x1800]];
+
+        let mut data = Vec::new();
+        for part in &parts {
+            data.extend_from_slice(&compress(part, level));
+            data.extend_from_slice(&[0; 0xd]);
+        }
+
+        let blocks: Vec<Vec<u8>> = Yaz0BlockIter::new(&data[..]).collect::<Result<_, _>>().unwrap();
+        assert!(blocks == parts);
+
+        let mut f = Yaz0Archive::new(&data[..]).unwrap();
+        assert!(f.decompress_next().unwrap().unwrap() == parts[0]);
+        assert!(f.decompress_next().unwrap().unwrap() == parts[1]);
+        assert_eq!(f.expected_size(), 0);
+        assert!(f.decompress_next().unwrap().unwrap() == parts[2]);
+        assert!(f.decompress_next().unwrap().is_none());
+
+        // garbage after the first file ends iteration
+        let mut data = compress(parts[0], level);
+        data.extend_from_slice(b"junk");
+        let mut blocks = Yaz0BlockIter::new(&data[..]);
+        assert!(blocks.next().unwrap().unwrap() == parts[0]);
+        assert!(matches!(blocks.next(), Some(Err(Error::InvalidMagic))));
+        assert!(blocks.next().is_none());
+    }
+
+    #[test]
+    fn test_read_at() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let mut f = Yaz0Archive::new(Cursor::new(data)).unwrap();
+        assert!(f.read_at(0x12345, 0x100).unwrap() == reference_decompressed[0x12345..0x12445]);
+
+        let index = f.build_index(0x4000).unwrap();
+        assert!(index.checkpoints().count() > reference_decompressed.len() / 0x8000);
+        let outputs: Vec<usize> = index.checkpoints().map(|(_, output)| output).collect();
+        assert!(outputs.windows(2).all(|pair| pair[1] - pair[0] >= 0x4000));
+
+        for &(offset, len) in &[(0, 0x10), (0x3fff, 0x20), (0x8100, 0x4000), (0x200000, 4)] {
+            let end = (offset + len).min(reference_decompressed.len());
+            let start = offset.min(end);
+            assert!(f.read_at(offset, len).unwrap() == reference_decompressed[start..end]);
+        }
+    }
+
+    #[test]
+    fn test_decompress_parallel() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        // without an index, it's decompressed serially
+        let mut f = Yaz0Archive::new(Cursor::new(data)).unwrap();
+        assert!(f.decompress_parallel(Some(4)).unwrap() == reference_decompressed);
+
+        f.build_index(0x8000).unwrap();
+        for threads in [1, 3, 0x1000] {
+            assert!(f.decompress_parallel(Some(threads)).unwrap() == reference_decompressed);
+        }
+        assert!(f.read_at(0x100, 0x10).unwrap() == reference_decompressed[0x100..0x110]);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_from_bytes() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let mut f = Yaz0Archive::from_bytes(data).unwrap();
+        assert!(f.decompress().unwrap() == reference_decompressed);
+        // the reader is left past the payload, so it can be found and read again
+        assert!(f.decompress().unwrap() == reference_decompressed);
+        f.verify().unwrap();
+
+        let mut data = b"Yaz0\x00\x00\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        data.extend_from_slice(&[
+            0xc0, /* | id:  */ 0, 1,
+                  /*   run: */ 0x10, 0x02,
+        ]);
+        let err = Yaz0Archive::from_bytes(&data).unwrap().annotate_errors().decompress().unwrap_err();
+        assert!(matches!(err, Error::RunOutOfBounds { offset: 3, dist: 3, produced: 2, context: Some(_) }), "{:?}", err);
+
+        data.truncate(data.len() - 1);
+        let err = Yaz0Archive::from_bytes(&data).unwrap().decompress().unwrap_err();
+        assert!(matches!(err, Error::TruncatedStream { offset: 3, produced: 2 }), "{:?}", err);
+    }
+
+    #[test]
+    fn test_decompressor() {
+        use rand::{Rng, SeedableRng};
+        use rand::rngs::StdRng;
+
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+        let mut compressed = include_bytes!("../data/test.yaz0").to_vec();
+        compressed.extend_from_slice(b"trailing");
+
+        // feed input and take output in uneven pieces, often smaller than a single copy run
+        let mut rng = StdRng::seed_from_u64(562);
+        let mut decompressor = Decompressor::new();
+        let (mut input, mut out) = (&compressed[..], Vec::new());
+        loop {
+            let mut buf = vec![0; rng.gen_range(0, 0x200)];
+            let len = rng.gen_range(0, 0x100).min(input.len());
+            let (consumed, produced, status) = decompressor.decompress(&input[..len], &mut buf).unwrap();
+            input = &input[consumed..];
+            out.extend_from_slice(&buf[..produced]);
+
+            match status {
+                Status::NeedsInput => assert_eq!(consumed, len),
+                Status::NeedsOutput => assert_eq!(produced, buf.len()),
+                Status::Done => break,
+            }
+        }
+        assert!(out == reference_decompressed);
+        assert_eq!(input, b"trailing");
+        assert_eq!(decompressor.header().unwrap().expected_size, out.len());
+        decompressor.finish().unwrap();
+
+        let mut data = b"Yaz0\x00\x00\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        data.extend_from_slice(&[
+            0xc0, /* | id:  */ 0, 1,
+                  /*   run: */ 0x10, 0x02,
+        ]);
+        let err = Decompressor::new().decompress(&data, &mut [0; 8]).unwrap_err();
+        assert!(matches!(err, Error::RunOutOfBounds { offset: 3, dist: 3, produced: 2, context: None }), "{:?}", err);
+
+        let mut decompressor = Decompressor::new();
+        let (consumed, produced, status) = decompressor.decompress(&data[..data.len() - 1], &mut [0; 8]).unwrap();
+        assert_eq!((consumed, produced, status), (data.len() - 1, 2, Status::NeedsInput));
+        let err = decompressor.finish().unwrap_err();
+        assert!(matches!(err, Error::TruncatedStream { offset: 3, produced: 2 }), "{:?}", err);
+
+        assert!(matches!(Decompressor::new().decompress(b"Yaz1\0\0\0\0\0\0\0\0\0\0\0\0", &mut []), Err(Error::InvalidMagic)));
+    }
+
+    #[test]
+    fn test_into_inner() {
+        let mut data = include_bytes!("../data/test.yaz0").to_vec();
+        let payload_end = data.len() as u64;
+        data.extend_from_slice(b"trailer");
+
+        // decompressing from memory leaves the reader just past the payload
+        let mut f = Yaz0Archive::from_bytes(&data).unwrap();
+        assert_eq!(f.header().expected_size, f.expected_size());
+        f.decompress().unwrap();
+        assert_eq!(f.data_start().unwrap(), 0x10);
+        assert!(f.buffer().is_empty());
+        let mut reader = f.into_inner();
+        assert_eq!(reader.position(), payload_end);
+        let mut trailer = Vec::new();
+        reader.read_to_end(&mut trailer).unwrap();
+        assert_eq!(trailer, b"trailer");
+
+        // otherwise, what was read past it is in the buffer
+        let mut f = Yaz0Archive::new(Cursor::new(&data[..])).unwrap();
+        f.decompress().unwrap();
+        let mut trailer = f.buffer().to_vec();
+        f.into_inner().read_to_end(&mut trailer).unwrap();
+        assert_eq!(trailer, b"trailer");
+    }
+
+    #[test]
+    fn test_verify() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        Yaz0Archive::new(Cursor::new(data)).unwrap().verify().unwrap();
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_verify_corrupt() {
+        let mut data = b"Yaz0\x00\x00\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        data.extend_from_slice(&[
+            0xc0, /* | id:  */ 0, 1,
+                  /*   run: */ 0x10, 0x02,
+        ]);
+
+        let err = Yaz0Archive::new(Cursor::new(&data)).unwrap().verify().unwrap_err();
+        assert!(matches!(err, Error::RunOutOfBounds { offset: 3, dist: 3, produced: 2, context: None }), "{:?}", err);
+
+        // with annotations turned on, the whole (short) stream is dumped
+        let err = Yaz0Archive::new(Cursor::new(&data)).unwrap().annotate_errors().verify().unwrap_err();
+        match err {
+            Error::RunOutOfBounds { context: Some(ref dump), .. } => assert_eq!(dump.bytes(), &data[0x10..]),
+            _ => panic!("{:?}", err),
+        }
+        assert!(err.to_string().contains("^^ packet starts here"), "{}", err);
+
+        // a long enough distance, but the run is too long for the header's size
+        data[0x13] = 0x70;
+        data[0x14] = 0x01;
+        let err = Yaz0Archive::new(Cursor::new(&data)).unwrap().verify().unwrap_err();
+        assert!(matches!(err, Error::SizeMismatch { offset: 3, expected: 8, decoded: 11 }), "{:?}", err);
+
+        // and truncated data
+        data[0x13] = 0x10;
+        data.truncate(0x14);
+        let err = Yaz0Archive::new(Cursor::new(&data)).unwrap().verify().unwrap_err();
+        assert!(matches!(err, Error::TruncatedStream { .. }), "{:?}", err);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_decompress_corrupt() {
+        let mut data = b"Yaz0\x00\x00\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        data.extend_from_slice(&[
+            0xc0, /* | id:  */ 0, 1,
+                  /*   run: */ 0x10, 0x02,
+        ]);
+
+        // the run reaches back three bytes, with only two produced so far
+        let err = Yaz0Archive::new(Cursor::new(&data)).unwrap().decompress().unwrap_err();
+        assert!(matches!(err, Error::RunOutOfBounds { offset: 3, dist: 3, produced: 2, context: None }), "{:?}", err);
+        assert!(err.is_corruption());
+
+        let err = Yaz0Archive::new(Cursor::new(&data)).unwrap().annotate_errors().decompress().unwrap_err();
+        assert!(matches!(err, Error::RunOutOfBounds { context: Some(_), .. }), "{:?}", err);
+
+        // without a header size to stop at, too
+        let err = Yaz0Archive::from_raw(Cursor::new(&data[0x10..]), 0)
+            .unwrap()
+            .decompress_recovering_size()
+            .unwrap_err();
+        assert!(matches!(err, Error::RunOutOfBounds { offset: 3, .. }), "{:?}", err);
+
+        // a run overshooting the header's size is rejected, unless decompressing leniently
+        data[0x13] = 0x70;
+        data[0x14] = 0x01;
+        let mut dest = [0u8; 8];
+        let err = Yaz0Archive::new(Cursor::new(&data)).unwrap().decompress_into(&mut dest[..]).unwrap_err();
+        assert!(matches!(err, Error::SizeMismatch { offset: 3, expected: 8, decoded: 11 }), "{:?}", err);
+        let (inflated, _) = Yaz0Archive::new(Cursor::new(&data)).unwrap().decompress_lenient().unwrap();
+        assert_eq!(inflated, [0, 1, 0, 1, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn test_decompress_short() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let mut dest = vec![0u8; reference_decompressed.len() - 1];
+        let err = Yaz0Archive::new(Cursor::new(data)).unwrap().decompress_into(&mut dest[..]).unwrap_err();
+        assert!(matches!(err, Error::DestinationTooSmall { got, .. } if got == dest.len()), "{:?}", err);
+        assert!(err.is_limit_exceeded());
+
+        // cut off at an operation boundary
+        for &len in &[0x11, 0x12] {
+            let err = Yaz0Archive::new(Cursor::new(&data[..len])).unwrap().decompress().unwrap_err();
+            match err {
+                Error::UnexpectedEof { expected, produced } => {
+                    assert_eq!(expected, reference_decompressed.len());
+                    assert!(produced < expected);
+                }
+                _ => panic!("{:?}", err),
+            }
+            assert!(err.is_corruption());
+        }
+
+        // and partway through a copy run
+        let err = Yaz0Archive::new(Cursor::new(&data[..0x1000])).unwrap().decompress().unwrap_err();
+        assert!(matches!(err, Error::TruncatedStream { .. }), "{:?}", err);
+        assert!(err.is_corruption());
+    }
+
+    #[test]
+    fn test_decompress_progress() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let mut reports = Vec::new();
+        let inflated = Yaz0Archive::new(Cursor::new(data))
+            .unwrap()
+            .decompress_with_callback(|progress| reports.push(progress))
+            .unwrap();
+        assert!(inflated == reference_decompressed);
+
+        let last = reports.last().unwrap();
+        assert_eq!(last.output_len, reference_decompressed.len());
+        assert_eq!(last.compressed_len, data.len() - 0x10);
+        assert!(reports.len() > reference_decompressed.len() / PROGRESS_INTERVAL);
+        assert!(reports.windows(2).all(|pair| pair[0].output_len <= pair[1].output_len));
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        Yaz0Archive::new(Cursor::new(data)).unwrap().decompress_with_progress(tx).unwrap();
+        assert_eq!(rx.iter().count(), reports.len());
+    }
+
+    #[test]
+    fn test_decompress_garbage() {
+        use rand::{self, Rng};
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..0x100 {
+            let len = rng.gen_range(0, 0x40);
+            let mut data = b"Yaz0\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+            data.extend((0..len).map(|_| rng.gen::<u8>()));
+
+            // garbage should be rejected (or, by chance, decode), but never panic
+            let _ = Yaz0Archive::new(Cursor::new(&data)).unwrap().decompress();
+        }
+    }
+
+    /// Zero out the test file's size field, and check that it can be recovered.
+    #[test]
+    fn test_repair_expected_size() {
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let mut data = include_bytes!("../data/test.yaz0").to_vec();
+        data[4..8].copy_from_slice(&[0; 4]);
+
+        let mut file = Cursor::new(data);
+        let size = repair_expected_size(&mut file).unwrap();
+        assert_eq!(size, reference_decompressed.len());
+
+        file.set_position(0);
+        let mut f = Yaz0Archive::new(file).unwrap();
+        assert_eq!(f.expected_size(), reference_decompressed.len());
+        assert!(f.decompress().unwrap() == reference_decompressed);
     }
 
     /// Test loading a small constructed Yaz0 file containing random data.
@@ -1,13 +1,187 @@
-#[cfg(test)]
-#[macro_use]
-extern crate pretty_assertions;
-
 mod error;
+mod index;
+mod ops;
+pub mod analysis;
+pub mod anomaly;
+pub mod any;
+#[cfg(feature = "async")]
+pub mod async_io;
+#[cfg(feature = "digest")]
+pub mod cache;
+pub mod capabilities;
+pub mod checksum;
 pub mod deflate;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod delta;
+pub mod diff;
+pub mod format;
 pub mod header;
 pub mod inflate;
+pub mod mio0;
+pub mod pipeline;
+#[cfg(test)]
+mod roundtrip;
+#[cfg(any(unix, windows))]
+pub mod positioned;
+pub mod sarc;
+#[cfg(any(unix, windows))]
+pub mod shared;
+pub mod sink;
+pub mod stats;
+#[cfg(feature = "flate2")]
+pub mod transcode;
+#[cfg(feature = "test-vectors")]
+pub mod vectors;
+pub mod volume;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod windowed;
+pub mod yay0;
+
+pub use crate::analysis::{compare_streams, diff_streams, recompress_matching, Comparison};
+pub use crate::any::{sniff, FormatInfo};
+pub use crate::capabilities::capabilities;
+pub use crate::checksum::ChecksumLocation;
+pub use crate::diff::Patch;
+pub use crate::deflate::{max_compressed_size, CompressionLevel, CompressionOptions, Compressor, Yaz0Writer};
+pub use crate::error::{exit_code, Error, ErrorKind, Hexdump};
+pub use crate::header::{Endianness, Yaz0Header};
+pub use crate::index::Yaz0Index;
+pub use crate::inflate::{repair_expected_size, Decompressor, MaybeYaz0, Yaz0Archive, Yaz0BlockIter};
+pub use crate::mio0::{Mio0Archive, Mio0Writer};
+pub use crate::ops::{Op, Yaz0OpIter};
+pub use crate::sink::Sink;
+pub use crate::yay0::{Yay0Archive, Yay0Writer};
+
+use std::io::{Cursor, Read, Write};
+
+/// Compresses `data` at [CompressionLevel] `level` into a Yaz0 file, header included.
+///
+/// For more control over the output, or to write it straight to a file, use [`Yaz0Writer`].
+pub fn compress(data: &[u8], level: CompressionLevel) -> Vec<u8> {
+    let mut compressed = Vec::with_capacity(max_compressed_size(data.len()));
+    Yaz0Writer::new(&mut compressed)
+        .compress_and_write(data, level)
+        .expect("writing to a Vec can't fail");
+
+    compressed
+}
+
+/// Whether `data` starts with the Yaz0 magic. To find out more from the header, or to recognize
+/// other formats too, use [`sniff`].
+pub fn is_yaz0(data: &[u8]) -> bool {
+    data.starts_with(b"Yaz0")
+}
+
+/// Decompresses the Yaz0 file `data`.
+///
+/// To decompress from a file or into an existing buffer, use [`Yaz0Archive`].
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    Yaz0Archive::from_bytes(data)?.decompress()
+}
+
+/// Decompresses the Yaz0 file read from `reader`, streaming the output into `writer`; only the
+/// window that copy runs can reach back into is held in memory. Returns the number of
+/// decompressed bytes written.
+pub fn copy_decompress<R, W>(reader: R, writer: &mut W) -> Result<u64, Error>
+where
+    R: Read,
+    W: Write + ?Sized,
+{
+    Yaz0Archive::new(reader)?.decompress_to_writer(writer)
+}
+
+/// Compresses everything read from `reader` at [CompressionLevel] `level` into a Yaz0 file,
+/// streaming it into `writer`. Returns the number of bytes read.
+///
+/// The header records the decompressed size, so the input has to be read in full before anything
+/// is written; the output is then written a chunk at a time as it's compressed.
+pub fn copy_compress<R, W>(mut reader: R, writer: &mut W, level: CompressionLevel) -> Result<u64, Error>
+where
+    R: Read,
+    W: Write,
+{
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    Yaz0Writer::new(writer).compress_and_write(&data, level)?;
+    Ok(data.len() as u64)
+}
+
+/// Compresses `data` at [CompressionLevel] `level` into a Yaz0 file, with the compressor's window
+/// seeded with the preset dictionary `dict`, so runs can copy from it. Sets of small, similar
+/// files compress much better against a dictionary of what they have in common.
+///
+/// Only the last `0x1000` bytes of `dict` can be reached. The file has to be decompressed with the
+/// same dictionary, with [`decompress_with_dict`]; decompressing it without one fails with
+/// [`Error::RunOutOfBounds`].
+pub fn compress_with_dict(data: &[u8], dict: &[u8], level: CompressionLevel) -> Vec<u8> {
+    let payload = deflate::compress_payload_with_dict(data, dict, level);
+
+    let mut compressed = Vec::with_capacity(0x10 + payload.len());
+    Yaz0Header::new(data.len())
+        .write(&mut compressed)
+        .expect("writing to a Vec can't fail");
+    compressed.extend_from_slice(&payload);
+
+    compressed
+}
+
+/// Decompresses the Yaz0 file `data`, compressed against the preset dictionary `dict` with
+/// [`compress_with_dict`].
+pub fn decompress_with_dict(data: &[u8], dict: &[u8]) -> Result<Vec<u8>, Error> {
+    Yaz0Archive::new(Cursor::new(data))?.decompress_with_dict(dict)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn one_shot() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x8000];
+
+        let compressed = compress(data, CompressionLevel::HashChain { quality: 10 });
+        assert!(compressed.len() < data.len());
+        assert!(decompress(&compressed).unwrap() == data);
+
+        assert!(matches!(decompress(b"not yaz0 data"), Err(Error::InvalidMagic)));
+    }
+
+    #[test]
+    fn copy() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x8000];
+        let level = CompressionLevel::HashChain { quality: 10 };
+
+        let mut compressed = Vec::new();
+        assert_eq!(copy_compress(data, &mut compressed, level).unwrap(), data.len() as u64);
+        assert!(compressed == compress(data, level));
+
+        let mut decompressed = Vec::new();
+        assert_eq!(copy_decompress(&compressed[..], &mut decompressed).unwrap(), data.len() as u64);
+        assert!(decompressed == data);
+
+        let err = copy_decompress(&compressed[..0x100], &mut std::io::sink()).unwrap_err();
+        assert!(matches!(err, Error::UnexpectedEof { .. } | Error::TruncatedStream { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn dictionary() {
+        let dict: &[u8] = &include_bytes!("../data/test")[..0x3000];
+        let mut data = dict[0x2400..0x2800].to_vec();
+        data[0x100] ^= 0xff;
+
+        for &level in &[CompressionLevel::Lookahead { quality: 10 }, CompressionLevel::HashChain { quality: 10 }] {
+            let compressed = compress_with_dict(&data, dict, level);
+            assert!(compressed.len() < compress(&data, level).len() / 4);
+            assert_eq!(decompress_with_dict(&compressed, dict).unwrap(), data);
+
+            assert!(matches!(decompress(&compressed), Err(Error::RunOutOfBounds { .. })));
+        }
 
-pub use crate::deflate::{CompressionLevel, Yaz0Writer};
-pub use crate::error::Error;
-pub use crate::header::Yaz0Header;
-pub use crate::inflate::Yaz0Archive;
+        // an empty dictionary is no dictionary at all
+        let compressed = compress_with_dict(&data, &[], CompressionLevel::Lookahead { quality: 10 });
+        assert_eq!(compressed, compress(&data, CompressionLevel::Lookahead { quality: 10 }));
+        assert_eq!(decompress_with_dict(&compressed, &[]).unwrap(), data);
+    }
+}
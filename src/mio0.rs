@@ -0,0 +1,333 @@
+//! The MIO0 format, used by N64 titles.
+//!
+//! MIO0 is laid out like [Yay0](crate::yay0): a stream of mask bits (set for a literal), a stream
+//! of 16-bit links describing copy runs, and a stream of literal bytes, with the header recording
+//! where the latter two start. The only difference is that there are no long copy runs; a link's
+//! upper nybble is always the run length minus 3, so runs are between 3 and 18 bytes long.
+
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::deflate::{compress_ops, CompressionLevel};
+use crate::yay0::Stream;
+use crate::{Error, Op, Sink};
+
+/// The longest copy run MIO0 can encode.
+const MAX_RUN: usize = 0x12;
+
+/// The header on a MIO0 file.
+#[derive(Debug)]
+pub struct Mio0Header {
+    /// Expected size of the decompressed file
+    pub expected_size: usize,
+    /// Offset of the link stream from the start of the header.
+    pub link_offset: u32,
+    /// Offset of the literal byte stream from the start of the header.
+    pub literal_offset: u32,
+}
+
+impl Mio0Header {
+    /// Parses the header of a MIO0 file, provided via the passed reader.
+    /// Leaves the read head at the start of the mask stream.
+    pub fn parse<R>(reader: &mut R) -> Result<Mio0Header, Error>
+    where
+        R: Read,
+    {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != b"MIO0" {
+            return Err(Error::InvalidMagic);
+        }
+
+        Ok(Mio0Header {
+            expected_size: reader.read_u32::<BigEndian>()? as usize,
+            link_offset: reader.read_u32::<BigEndian>()?,
+            literal_offset: reader.read_u32::<BigEndian>()?,
+        })
+    }
+
+    /// Writes the header of a MIO0 file to the passed writer.
+    /// Leaves the write head at the start of the mask stream.
+    pub fn write<W>(&self, writer: &mut W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        writer.write_all(b"MIO0")?;
+        writer.write_u32::<BigEndian>(self.expected_size as u32)?;
+        writer.write_u32::<BigEndian>(self.link_offset)?;
+        writer.write_u32::<BigEndian>(self.literal_offset)?;
+
+        Ok(())
+    }
+}
+
+/// Wraps a reader of MIO0 data, providing decompression methods.
+#[derive(Debug)]
+pub struct Mio0Archive<R>
+where
+    R: Read + Seek,
+{
+    reader: R,
+
+    /// Where the header starts in the reader; the header's offsets are relative to it.
+    start: u64,
+    header: Mio0Header,
+}
+
+impl<R> Mio0Archive<R>
+where
+    R: Read + Seek,
+{
+    /// Creates a new `Mio0Archive` from a reader positioned at the start of the header.
+    pub fn new(mut reader: R) -> Result<Mio0Archive<R>, Error> {
+        let start = reader.stream_position()?;
+        let header = Mio0Header::parse(&mut reader)?;
+
+        Ok(Mio0Archive {
+            reader,
+            start,
+            header,
+        })
+    }
+
+    /// Get the expected size of inflated data from the parsed `Mio0Header`.
+    pub fn expected_size(&self) -> usize {
+        self.header.expected_size
+    }
+
+    /// Decompresses the MIO0 file, producing a [Vec] of the decompressed data.
+    pub fn decompress(&mut self) -> Result<Vec<u8>, Error> {
+        let mut dest = vec![0; self.expected_size()];
+        self.decompress_into(&mut dest[..])?;
+
+        Ok(dest)
+    }
+
+    /// Decompresses the MIO0 file into a destination [`Sink`].
+    ///
+    /// If the destination sink can't hold [`Mio0Archive::expected_size`] bytes,
+    /// [`Error::DestinationTooSmall`] is returned.
+    pub fn decompress_into<S>(&mut self, dest: &mut S) -> Result<(), Error>
+    where
+        S: Sink + ?Sized,
+    {
+        if dest.capacity() < self.expected_size() {
+            return Err(Error::DestinationTooSmall {
+                needed: self.expected_size(),
+                got: dest.capacity(),
+            });
+        }
+
+        let mut file = Vec::new();
+        self.reader.seek(SeekFrom::Start(self.start))?;
+        self.reader.read_to_end(&mut file)?;
+
+        inflate(&file, &self.header, dest)
+    }
+}
+
+/// Decodes the MIO0 file `file`, with the already-parsed `header`, into `dest`.
+fn inflate<S>(file: &[u8], header: &Mio0Header, dest: &mut S) -> Result<(), Error>
+where
+    S: Sink + ?Sized,
+{
+    let mut masks = Stream { file, pos: 0x10 };
+    let mut links = Stream { file, pos: header.link_offset as usize };
+    let mut literals = Stream { file, pos: header.literal_offset as usize };
+
+    let end = header.expected_size;
+    let mut dest_pos = 0;
+
+    let mut mask: u8 = 0;
+    let mut ops_left = 0;
+
+    while dest_pos < end {
+        if ops_left == 0 {
+            mask = masks.read(1, "mask stream ends early")?[0];
+            ops_left = 8;
+        }
+
+        if mask & 0x80 != 0 {
+            dest.set(dest_pos, literals.read(1, "literal stream ends early")?[0]);
+            dest_pos += 1;
+        } else {
+            let link_offset = links.pos;
+            let link = BigEndian::read_u16(links.read(2, "link stream ends early")?) as usize;
+
+            let dist = (link & 0xfff) + 1;
+            let copy_len = (link >> 12) + 3;
+
+            let run_base = match dest_pos.checked_sub(dist) {
+                Some(base) => base,
+                None => {
+                    return Err(Error::CorruptData {
+                        offset: link_offset,
+                        reason: "copy run reaches back before the start of the output",
+                        context: None,
+                    })
+                }
+            };
+
+            // the last run may overshoot the end of the output; ignore anything past it
+            for i in 0..copy_len.min(end - dest_pos) {
+                dest.set(dest_pos, dest.get(run_base + i));
+                dest_pos += 1;
+            }
+        }
+
+        mask <<= 1;
+        ops_left -= 1;
+    }
+
+    Ok(())
+}
+
+/// Splits copy runs in `ops` longer than MIO0 can encode into several shorter runs from the same
+/// distance, each at least 3 bytes long.
+fn split_long_runs(ops: Vec<Op>) -> Vec<Op> {
+    let mut split = Vec::with_capacity(ops.len());
+
+    for op in ops {
+        match op {
+            Op::Copy { dist, mut len } => {
+                while len > MAX_RUN {
+                    let take = if len - MAX_RUN >= 3 { MAX_RUN } else { len - 3 };
+                    split.push(Op::Copy { dist, len: take });
+                    len -= take;
+                }
+                split.push(Op::Copy { dist, len });
+            }
+            literal => split.push(literal),
+        }
+    }
+
+    split
+}
+
+/// Compresses data into the MIO0 format, using the same compression levels as
+/// [`Yaz0Writer`](crate::Yaz0Writer).
+pub struct Mio0Writer<'a, W: 'a>
+where
+    W: Write,
+{
+    writer: &'a mut W,
+}
+
+impl<'a, W> Mio0Writer<'a, W>
+where
+    W: Write,
+{
+    /// Creates a new `Mio0Writer` writing to `writer`.
+    pub fn new(writer: &'a mut W) -> Mio0Writer<'a, W> {
+        Mio0Writer { writer }
+    }
+
+    /// Compresses `data` with [CompressionLevel] `level`, writing the MIO0 file (header
+    /// included) to the destination.
+    pub fn compress_and_write(self, data: &[u8], level: CompressionLevel) -> Result<(), Error> {
+        let mut masks: Vec<u8> = Vec::new();
+        let mut links: Vec<u16> = Vec::new();
+        let mut literals: Vec<u8> = Vec::new();
+
+        for (i, op) in split_long_runs(compress_ops(data, level)).into_iter().enumerate() {
+            if i % 8 == 0 {
+                masks.push(0);
+            }
+
+            match op {
+                Op::Literal(byte) => {
+                    *masks.last_mut().unwrap() |= 0x80 >> (i % 8);
+                    literals.push(byte);
+                }
+                Op::Copy { dist, len } => {
+                    links.push((((len - 3) << 12) | (dist - 1)) as u16);
+                }
+            }
+        }
+
+        // N64 decoders read the mask stream a word at a time
+        masks.resize(masks.len().next_multiple_of(4), 0);
+
+        let link_offset = 0x10 + masks.len();
+        let header = Mio0Header {
+            expected_size: data.len(),
+            link_offset: link_offset as u32,
+            literal_offset: (link_offset + links.len() * 2) as u32,
+        };
+        header.write(self.writer)?;
+
+        self.writer.write_all(&masks)?;
+        for link in links {
+            self.writer.write_u16::<BigEndian>(link)?;
+        }
+        self.writer.write_all(&literals)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    #[test]
+    #[rustfmt::skip]
+    fn inflate_known() {
+        let data: &[u8] = &[
+            b'M', b'I', b'O', b'0', 0, 0, 0, 0x0a, 0, 0, 0, 0x14, 0, 0, 0, 0x16,
+            // masks: 4 literals, run
+            0xf0, 0x00, 0x00, 0x00,
+            // links: dist 4, len 6
+            0x30, 0x03,
+            // literals
+            0, 1, 2, 3,
+        ];
+
+        let out = Mio0Archive::new(Cursor::new(data)).unwrap().decompress().unwrap();
+        assert_eq!(&out[..], &[0, 1, 2, 3, 0, 1, 2, 3, 0, 1][..]);
+    }
+
+    #[test]
+    fn split_runs() {
+        let ops = split_long_runs(vec![Op::Copy { dist: 1, len: 0x14 }, Op::Copy { dist: 2, len: 0x30 }]);
+        assert!(ops.iter().all(|op| (3..=MAX_RUN).contains(&op.output_len())), "{:?}", ops);
+        assert_eq!(ops[..2].iter().map(Op::output_len).sum::<usize>(), 0x14);
+        assert_eq!(ops.iter().map(Op::output_len).sum::<usize>(), 0x44);
+    }
+
+    #[test]
+    fn inverts() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x20000];
+
+        for &level in &[
+            CompressionLevel::Naive { quality: 10 },
+            CompressionLevel::HashChain { quality: 7 },
+        ] {
+            let mut compressed = Vec::new();
+            Mio0Writer::new(&mut compressed).compress_and_write(data, level).unwrap();
+
+            let out = Mio0Archive::new(Cursor::new(&compressed)).unwrap().decompress().unwrap();
+            assert_eq!(&out[..], data);
+        }
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut compressed = Vec::new();
+        Mio0Writer::new(&mut compressed)
+            .compress_and_write(b"abcabcabcabc", CompressionLevel::Naive { quality: 10 })
+            .unwrap();
+
+        // point the first link back before the start of the output
+        let link_offset = BigEndian::read_u32(&compressed[8..]) as usize;
+        compressed[link_offset] |= 0x0f;
+        let err = Mio0Archive::new(Cursor::new(&compressed)).unwrap().decompress().unwrap_err();
+        assert!(matches!(err, Error::CorruptData { .. }), "{:?}", err);
+
+        compressed.truncate(0x12);
+        let err = Mio0Archive::new(Cursor::new(&compressed)).unwrap().decompress().unwrap_err();
+        assert!(matches!(err, Error::CorruptData { .. }), "{:?}", err);
+    }
+}
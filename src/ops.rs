@@ -0,0 +1,427 @@
+//! Parsing the compressed packet stream into individual operations, without decompressing it.
+
+use byteorder::ReadBytesExt;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use std::io::{self, Read};
+
+use crate::index::RestartPoint;
+use crate::Error;
+
+/// A single operation in a Yaz0 packet stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Op {
+    /// Output this byte as-is.
+    Literal(u8),
+    /// Copy `len` bytes, starting `dist` bytes back from the end of the output so far.
+    /// `dist` is at least 1, and can be less than `len`, in which case the copy overlaps itself.
+    Copy { dist: usize, len: usize },
+}
+
+impl Op {
+    /// The number of bytes of output produced by this operation.
+    pub(crate) fn output_len(&self) -> usize {
+        match *self {
+            Op::Literal(_) => 1,
+            Op::Copy { len, .. } => len,
+        }
+    }
+}
+
+/// Iterates over the operations in a packet stream, read from `reader`.
+///
+/// Iteration stops once operations producing `output_len` bytes have been read.
+pub(crate) struct OpIter<R> {
+    reader: R,
+
+    /// Output bytes left to account for.
+    remaining: usize,
+    /// Output accounted for before the reader's start, and in total once it's done, for errors.
+    output_start: usize,
+    output_end: usize,
+
+    code_byte: u8,
+    ops_left: u8,
+    /// The current group's code byte, as read.
+    group_code: u8,
+
+    /// Bytes of the packet stream consumed so far.
+    offset: usize,
+    /// Offset of the last operation's packet within the packet stream.
+    op_offset: usize,
+}
+
+impl<R> OpIter<R>
+where
+    R: Read,
+{
+    /// Creates an iterator over the operations in the packet stream read from `reader`, which
+    /// should be positioned at the start of the payload.
+    pub(crate) fn new(reader: R, output_len: usize) -> OpIter<R> {
+        OpIter {
+            reader,
+            remaining: output_len,
+            output_start: 0,
+            output_end: output_len,
+            code_byte: 0,
+            ops_left: 0,
+            group_code: 0,
+            offset: 0,
+            op_offset: 0,
+        }
+    }
+
+    /// Treats the reader as starting `offset` bytes into the packet stream, with `output_offset`
+    /// bytes of output already produced, when resuming iteration partway through.
+    pub(crate) fn starting_at(mut self, offset: usize, output_offset: usize) -> OpIter<R> {
+        self.offset = offset;
+        self.output_start = output_offset;
+        self.output_end += output_offset;
+        self
+    }
+
+    /// Bytes of the packet stream consumed so far.
+    pub(crate) fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Whether the next operation starts a new group, and so will be preceded by a code byte.
+    pub(crate) fn at_group_start(&self) -> bool {
+        self.ops_left == 0
+    }
+
+    /// Offset of the last operation's packet within the packet stream.
+    pub(crate) fn op_offset(&self) -> usize {
+        self.op_offset
+    }
+
+    /// The code byte of the group the last operation was in.
+    pub(crate) fn group_code(&self) -> u8 {
+        self.group_code
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let byte = self.reader.read_u8()?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    /// Output accounted for by the operations read so far.
+    fn produced(&self) -> usize {
+        self.output_end - self.remaining
+    }
+
+    /// Reports the data running out at the start of a group or an operation: the stream just
+    /// ended early, as [`Yaz0Archive::decompress`](crate::Yaz0Archive::decompress) reports it.
+    fn ended(&self, err: io::Error) -> Error {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => Error::UnexpectedEof { expected: self.output_end, produced: self.produced() },
+            _ => err.into(),
+        }
+    }
+
+    /// Reports the data running out partway through a copy run's packet, which was cut off.
+    fn truncated(&self, err: io::Error) -> Error {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => Error::TruncatedStream { offset: self.op_offset, produced: self.produced() },
+            _ => err.into(),
+        }
+    }
+
+    fn read_op(&mut self) -> Result<Op, Error> {
+        if self.ops_left == 0 {
+            self.code_byte = self.read_byte().map_err(|e| self.ended(e))?;
+            self.group_code = self.code_byte;
+            self.ops_left = 8;
+        }
+
+        self.op_offset = self.offset;
+        let op = if self.code_byte & 0x80 != 0 {
+            Op::Literal(self.read_byte().map_err(|e| self.ended(e))?)
+        } else {
+            let byte1 = self.read_byte().map_err(|e| self.ended(e))?;
+            let byte2 = self.read_byte().map_err(|e| self.truncated(e))?;
+
+            let dist = ((((byte1 & 0xf) as usize) << 8) | (byte2 as usize)) + 1;
+            let len = match byte1 >> 4 {
+                0 => self.read_byte().map_err(|e| self.truncated(e))? as usize + 0x12,
+                n => n as usize + 2,
+            };
+
+            Op::Copy { dist, len }
+        };
+
+        // use next operation bit from the code byte
+        self.code_byte <<= 1;
+        self.ops_left -= 1;
+
+        Ok(op)
+    }
+}
+
+impl<R> Iterator for OpIter<R>
+where
+    R: Read,
+{
+    type Item = Result<Op, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        match self.read_op() {
+            Ok(op) => {
+                self.remaining = self.remaining.saturating_sub(op.output_len());
+                Some(Ok(op))
+            }
+            Err(e) => {
+                self.remaining = 0;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Iterates over the operations in a Yaz0 packet stream, parsing them without decompressing
+/// anything, for tools that want to look at how a file was compressed rather than what it holds.
+///
+/// Get one for a Yaz0 file with [`Yaz0Archive::ops`](crate::Yaz0Archive::ops), or for a bare
+/// packet stream with [`Yaz0OpIter::from_payload`]. Iteration stops once the operations account
+/// for the decompressed size; a stream that's cut short yields an error and then stops.
+///
+/// Copy runs aren't checked against the output produced so far, since none is kept; use
+/// [`Yaz0Archive::verify`](crate::Yaz0Archive::verify) for that.
+pub struct Yaz0OpIter<R> {
+    ops: OpIter<R>,
+    /// Output produced by the operations yielded so far.
+    produced: usize,
+    /// Output produced before the last operation yielded.
+    output_offset: usize,
+}
+
+impl<R> Yaz0OpIter<R>
+where
+    R: Read,
+{
+    /// Creates an iterator over the packet stream read from `reader`, stopping once the
+    /// operations account for `output_len` bytes of output.
+    pub fn from_payload(reader: R, output_len: usize) -> Yaz0OpIter<R> {
+        Yaz0OpIter {
+            ops: OpIter::new(reader, output_len),
+            produced: 0,
+            output_offset: 0,
+        }
+    }
+
+    /// Offset of the last operation yielded within the packet stream, after the header.
+    pub fn op_offset(&self) -> usize {
+        self.ops.op_offset()
+    }
+
+    /// Offset in the decompressed data of the output of the last operation yielded.
+    pub fn output_offset(&self) -> usize {
+        self.output_offset
+    }
+
+    /// Bytes of the packet stream read so far.
+    pub fn compressed_offset(&self) -> usize {
+        self.ops.offset()
+    }
+}
+
+impl<R> Iterator for Yaz0OpIter<R>
+where
+    R: Read,
+{
+    type Item = Result<Op, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let op = self.ops.next()?;
+        if let Ok(op) = op {
+            self.output_offset = self.produced;
+            self.produced += op.output_len();
+        }
+        Some(op)
+    }
+}
+
+/// Encodes `ops` into a packet stream, grouping them eight to a code byte.
+/// Copy runs must have a `dist` of at most `0x1000`, and a `len` between 3 and `0x111`.
+pub(crate) fn encode_ops(ops: &[Op]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+
+    for group in ops.chunks(8) {
+        let code_pos = encoded.len();
+        encoded.push(0);
+
+        for (i, op) in group.iter().enumerate() {
+            match *op {
+                Op::Literal(byte) => {
+                    encoded[code_pos] |= 0x80 >> i;
+                    encoded.push(byte);
+                }
+                Op::Copy { dist, len } => {
+                    let dist = dist - 1;
+                    if len >= 0x12 {
+                        encoded.push((dist >> 8) as u8);
+                        encoded.push(dist as u8);
+                        encoded.push((len - 0x12) as u8);
+                    } else {
+                        encoded.push((((len - 2) << 4) | (dist >> 8)) as u8);
+                        encoded.push(dist as u8);
+                    }
+                }
+            }
+        }
+    }
+
+    encoded
+}
+
+/// How far back copy runs can reach.
+pub(crate) const WINDOW_SIZE: usize = 0x1000;
+
+/// Decodes a packet stream a byte at a time, keeping only the last `N` bytes of output in memory
+/// for copy runs to reference. Copy runs reaching back further than that are treated as corrupt.
+pub(crate) struct ByteIter<R, const N: usize = WINDOW_SIZE> {
+    ops: OpIter<R>,
+    window: [u8; N],
+    /// Bytes produced so far.
+    pos: usize,
+    /// Bytes to produce in total.
+    len: usize,
+    /// The distance and remaining length of a copy run in progress.
+    copy: Option<(usize, usize)>,
+}
+
+impl<R, const N: usize> ByteIter<R, N>
+where
+    R: Read,
+{
+    /// Creates an iterator over the `output_len` bytes decoded from the packet stream read from
+    /// `reader`, which should be positioned at the start of the payload.
+    pub(crate) fn new(reader: R, output_len: usize) -> ByteIter<R, N> {
+        ByteIter {
+            ops: OpIter::new(reader, output_len),
+            window: [0; N],
+            pos: 0,
+            len: output_len,
+            copy: None,
+        }
+    }
+
+    /// Creates an iterator resuming decoding from a restart point, with `reader` positioned at the
+    /// restart point's offset in the packet stream. Iteration continues until `output_len` bytes
+    /// of output (counting from the very start) have been produced.
+    pub(crate) fn resume(reader: R, output_len: usize, point: &RestartPoint) -> ByteIter<R, N> {
+        let pos = point.output_offset;
+
+        let mut window = [0; N];
+        let history = &point.window[point.window.len().saturating_sub(N)..];
+        for (i, &byte) in history.iter().enumerate() {
+            window[(pos - history.len() + i) % N] = byte;
+        }
+
+        ByteIter {
+            ops: OpIter::new(reader, output_len - pos).starting_at(point.compressed_offset, pos),
+            window,
+            pos,
+            len: output_len,
+            copy: None,
+        }
+    }
+
+    fn emit(&mut self, byte: u8) -> u8 {
+        self.window[self.pos % N] = byte;
+        self.pos += 1;
+        byte
+    }
+}
+
+impl<R, const N: usize> Iterator for ByteIter<R, N>
+where
+    R: Read,
+{
+    type Item = Result<u8, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the last run may overshoot the end of the output; ignore anything past it
+        if self.pos == self.len {
+            return None;
+        }
+
+        if self.copy.is_none() {
+            match self.ops.next()? {
+                Ok(Op::Literal(byte)) => return Some(Ok(self.emit(byte))),
+                Ok(Op::Copy { dist, .. }) if dist > self.pos => {
+                    let offset = self.ops.op_offset();
+                    return Some(Err(Error::RunOutOfBounds { offset, dist, produced: self.pos, context: None }));
+                }
+                Ok(Op::Copy { dist, .. }) if dist > N => {
+                    let offset = self.ops.op_offset();
+                    let reason = "copy run reaches back further than the decoder's window";
+                    return Some(Err(Error::CorruptData { offset, reason, context: None }));
+                }
+                Ok(Op::Copy { dist, len }) => self.copy = Some((dist, len)),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        let (dist, len) = self.copy.take()?;
+        if len > 1 {
+            self.copy = Some((dist, len - 1));
+        }
+
+        let byte = self.window[(self.pos - dist) % N];
+        Some(Ok(self.emit(byte)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    #[rustfmt::skip]
+    fn parse_ops() {
+        let stream: &[u8] = &[
+            0xf6, /* | id:  */ 0, 1, 2, 0xa,
+                  /*   run: */ 0x10, 0x03,
+                  /*   id:  */ 3, 0xb,
+                  /*   run: */ 0x00, 0x04, 0x01,
+            0xf0, /* | id:  */ 4, 5, 6, 7,
+        ];
+
+        let ops: Vec<Op> = OpIter::new(stream, 0x20).collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(
+            ops,
+            vec![
+                Op::Literal(0), Op::Literal(1), Op::Literal(2), Op::Literal(0xa),
+                Op::Copy { dist: 4, len: 3 },
+                Op::Literal(3), Op::Literal(0xb),
+                Op::Copy { dist: 5, len: 0x13 },
+                Op::Literal(4), Op::Literal(5), Op::Literal(6), Op::Literal(7),
+            ]
+        );
+
+        assert_eq!(encode_ops(&ops), stream);
+    }
+
+    #[test]
+    fn cut_short() {
+        // running out between operations, as decompressing reports it
+        let stream: &[u8] = &[0xc0, 1, 2];
+        let err = OpIter::new(stream, 0x10).find_map(Result::err).unwrap();
+        assert!(matches!(err, Error::UnexpectedEof { expected: 0x10, produced: 2 }), "{:?}", err);
+
+        // and partway through a copy run's packet, counting output from where iteration resumed
+        let stream: &[u8] = &[0xc0, 1, 2, 0x10];
+        let err = OpIter::new(stream, 0x10).starting_at(0x20, 0x40).find_map(Result::err).unwrap();
+        assert!(matches!(err, Error::TruncatedStream { offset: 0x23, produced: 0x42 }), "{:?}", err);
+    }
+}
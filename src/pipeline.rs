@@ -0,0 +1,115 @@
+//! Batch file-to-file operations, overlapping IO with (de)compression.
+//!
+//! Each batch runs as a three-stage pipeline: while one file is being (de)compressed, the next is
+//! read in on one thread and the previous one is written out on another. At most one file is
+//! buffered between each pair of stages, bounding memory use.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::deflate::{CompressionLevel, Yaz0Writer};
+use crate::inflate::Yaz0Archive;
+use crate::Error;
+
+/// A single file-to-file operation: the input path, and where to write the output.
+pub type Job = (PathBuf, PathBuf);
+
+/// Runs `process` over the contents of each job's input file, writing the result to its output
+/// file. Returns a result for each job, in order.
+fn run<F>(jobs: Vec<Job>, process: F) -> Vec<Result<(), Error>>
+where
+    F: Fn(Vec<u8>) -> Result<Vec<u8>, Error>,
+{
+    let (read_tx, read_rx) = mpsc::sync_channel::<(PathBuf, Result<Vec<u8>, Error>)>(1);
+    let (write_tx, write_rx) = mpsc::sync_channel::<Result<(PathBuf, Vec<u8>), Error>>(1);
+
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            for (in_path, out_path) in jobs {
+                let data = fs::read(in_path).map_err(Error::from);
+                if read_tx.send((out_path, data)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let writer = scope.spawn(move || {
+            write_rx
+                .into_iter()
+                .map(|job| {
+                    let (out_path, data) = job?;
+                    fs::write(out_path, data)?;
+                    Ok(())
+                })
+                .collect()
+        });
+
+        for (out_path, data) in read_rx {
+            let result = data.and_then(&process).map(|output| (out_path, output));
+            if write_tx.send(result).is_err() {
+                break;
+            }
+        }
+        drop(write_tx);
+
+        writer.join().expect("pipeline writer thread panicked")
+    })
+}
+
+/// Compresses each job's input file at [CompressionLevel] `level`, writing the Yaz0 file to its
+/// output path. Returns a result for each job, in order.
+pub fn compress_files(jobs: Vec<Job>, level: CompressionLevel) -> Vec<Result<(), Error>> {
+    run(jobs, |data| {
+        let mut compressed = Vec::new();
+        Yaz0Writer::new(&mut compressed).compress_and_write(&data, level)?;
+        Ok(compressed)
+    })
+}
+
+/// Decompresses each job's input Yaz0 file, writing the decompressed data to its output path.
+/// Returns a result for each job, in order.
+pub fn decompress_files(jobs: Vec<Job>) -> Vec<Result<(), Error>> {
+    run(jobs, |data| Yaz0Archive::new(Cursor::new(data))?.decompress())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn roundtrip_batch() {
+        let dir = std::env::temp_dir().join(format!("yaz0-pipeline-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let inputs: Vec<Vec<u8>> = (1..5)
+            .map(|n| (0..0x100 * n).map(|i| (i % (n + 3)) as u8).collect())
+            .collect();
+        for (n, input) in inputs.iter().enumerate() {
+            fs::write(dir.join(format!("{}", n)), input).unwrap();
+        }
+
+        let path = |n: usize, ext: &str| dir.join(format!("{}{}", n, ext));
+
+        let mut jobs: Vec<Job> = (0..inputs.len()).map(|n| (path(n, ""), path(n, ".szs"))).collect();
+        jobs.push((path(99, ""), path(99, ".szs")));
+
+        let results = compress_files(jobs, CompressionLevel::Lookahead { quality: 10 });
+        assert_eq!(results.len(), inputs.len() + 1);
+        assert!(results[..inputs.len()].iter().all(|r| r.is_ok()));
+        assert!(results[inputs.len()].as_ref().unwrap_err().is_io());
+
+        let jobs = (0..inputs.len()).map(|n| (path(n, ".szs"), path(n, ".out"))).collect();
+        let results = decompress_files(jobs);
+        assert!(results.iter().all(|r| r.is_ok()));
+
+        for (n, input) in inputs.iter().enumerate() {
+            assert_eq!(&fs::read(path(n, ".out")).unwrap(), input);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
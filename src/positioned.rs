@@ -0,0 +1,90 @@
+//! Reading files with positioned IO, so that many readers can share one file handle.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// A reader over a shared `&File`, which tracks its own position and reads with positioned IO
+/// (`pread` on Unix, `ReadFile` with an offset on Windows) rather than moving the file's cursor.
+///
+/// Any number of these can read from the same file at once, without locking or reopening it.
+#[derive(Debug, Clone)]
+pub struct PositionedReader<'a> {
+    file: &'a File,
+    pos: u64,
+}
+
+impl<'a> PositionedReader<'a> {
+    /// Creates a reader starting at the beginning of `file`.
+    pub fn new(file: &'a File) -> PositionedReader<'a> {
+        PositionedReader { file, pos: 0 }
+    }
+
+    #[cfg(unix)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        use std::os::unix::fs::FileExt;
+        self.file.read_at(buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        use std::os::windows::fs::FileExt;
+        self.file.seek_read(buf, offset)
+    }
+}
+
+impl Read for PositionedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.read_at(buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for PositionedReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.file.metadata()?.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Yaz0Archive;
+    use std::fs;
+    use std::thread;
+
+    #[test]
+    fn shared_handle() {
+        let path = std::env::temp_dir().join(format!("yaz0-positioned-{}", std::process::id()));
+        fs::write(&path, &include_bytes!("../data/test.yaz0")[..]).unwrap();
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let file = File::open(&path).unwrap();
+        thread::scope(|scope| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| Yaz0Archive::from_file(&file).unwrap().decompress().unwrap()))
+                .collect();
+
+            for thread in threads {
+                assert!(thread.join().unwrap() == reference_decompressed);
+            }
+        });
+
+        fs::remove_file(&path).unwrap();
+    }
+}
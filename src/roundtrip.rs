@@ -0,0 +1,80 @@
+//! Property tests, checking that everything the compressor produces decompresses back to its
+//! input, over generated inputs built to exercise the match finders: long runs of one byte,
+//! repeated blocks, noise, and data repeating with a period around the window size.
+
+use std::time::Duration;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use crate::deflate::{CompressionLevel, CompressionOptions};
+use crate::{decompress, Yaz0Writer};
+
+/// Any compression level, at any quality.
+fn level() -> impl Strategy<Value = CompressionLevel> {
+    prop_oneof![
+        (1..=10usize).prop_map(|quality| CompressionLevel::Naive { quality }),
+        (1..=10usize).prop_map(|quality| CompressionLevel::Lookahead { quality }),
+        (1..=10usize).prop_map(|quality| CompressionLevel::HashChain { quality }),
+        (1..=2usize).prop_map(|depth| CompressionLevel::Lazy { depth }),
+        (0.0..1.2f64).prop_map(|target_ratio| CompressionLevel::Auto { target_ratio, time_budget: Duration::MAX }),
+        Just(CompressionLevel::MatchNintendo),
+        Just(CompressionLevel::Store),
+    ]
+}
+
+/// Limits on runs, including ones outside what the format allows, which get clamped.
+fn options() -> impl Strategy<Value = CompressionOptions> {
+    (1..=0x1100usize, 0..=0x120usize, 0..=0x20usize)
+        .prop_map(|(window, max_run, min_run)| CompressionOptions { window, max_run, min_run })
+}
+
+/// A stretch of input, of one of the shapes the match finders treat differently.
+fn segment() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        // a run of one byte, often longer than the longest copy run
+        (any::<u8>(), 1..0x300usize).prop_map(|(byte, len)| vec![byte; len]),
+        // a short block repeated back to back
+        (vec(any::<u8>(), 1..0x20), 1..0x20usize).prop_map(|(block, times)| block.repeat(times)),
+        // noise, which mostly has to be stored as literals
+        vec(any::<u8>(), 0..0x100),
+        // bytes drawn from a tiny alphabet, full of short matches
+        vec(0..3u8, 0..0x200),
+    ]
+}
+
+/// Data repeating with a period around the window size, so that runs only just reach (or just
+/// miss) the previous repeat.
+fn periodic() -> impl Strategy<Value = Vec<u8>> {
+    (vec(any::<u8>(), 0xfe0..0x1020), 0x10..0x200usize).prop_map(|(period, tail)| {
+        let mut data = period.repeat(2);
+        data.truncate(period.len() + tail);
+        data
+    })
+}
+
+/// Compresses `data` at `level` within `options`, and decompresses it again.
+fn roundtrip(data: &[u8], level: CompressionLevel, options: CompressionOptions) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    Yaz0Writer::new(&mut compressed)
+        .options(options)
+        .compress_and_write(data, level)
+        .unwrap();
+
+    decompress(&compressed).unwrap()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn structured(segments in vec(segment(), 0..8), level in level(), options in options()) {
+        let data = segments.concat();
+        prop_assert!(roundtrip(&data, level, options) == data);
+    }
+
+    #[test]
+    fn window_sized_period(data in periodic(), level in level(), options in options()) {
+        prop_assert!(roundtrip(&data, level, options) == data);
+    }
+}
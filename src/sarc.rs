@@ -0,0 +1,201 @@
+//! Reading `SARC` archives, the format found inside most `.szs` files once they're decompressed.
+//!
+//! Only what's needed to list and extract files is parsed: the archive header, the file table
+//! (`SFAT`), and the name table (`SFNT`). Archives in either byte order are supported.
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::Error;
+
+/// A file stored in a SARC archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SarcEntry<'a> {
+    /// The file's path within the archive, if the archive records names.
+    pub name: Option<&'a str>,
+    /// The hash of the file's path, which the file table is sorted by.
+    pub name_hash: u32,
+    /// The file's contents.
+    pub data: &'a [u8],
+}
+
+impl SarcEntry<'_> {
+    /// The file's path, or for unnamed files, a placeholder made from its name hash.
+    pub fn display_name(&self) -> String {
+        match self.name {
+            Some(name) => name.to_owned(),
+            None => format!("{:08x}.bin", self.name_hash),
+        }
+    }
+}
+
+const SFAT_HEADER_LEN: usize = 0xc;
+const SFAT_NODE_LEN: usize = 0x10;
+const SFNT_HEADER_LEN: usize = 0x8;
+
+/// Reads fields in the archive's byte order, reporting anything out of bounds as malformed.
+struct Fields<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Fields<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], Error> {
+        self.data
+            .get(offset..offset.saturating_add(len))
+            .ok_or(Error::InvalidSarc { offset, reason: "archive is truncated" })
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, Error> {
+        let bytes = self.bytes(offset, 2)?;
+        if self.little_endian {
+            Ok(LittleEndian::read_u16(bytes))
+        } else {
+            Ok(BigEndian::read_u16(bytes))
+        }
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, Error> {
+        let bytes = self.bytes(offset, 4)?;
+        if self.little_endian {
+            Ok(LittleEndian::read_u32(bytes))
+        } else {
+            Ok(BigEndian::read_u32(bytes))
+        }
+    }
+
+    fn expect_magic(&self, offset: usize, magic: &[u8], reason: &'static str) -> Result<(), Error> {
+        if self.bytes(offset, magic.len())? == magic {
+            Ok(())
+        } else {
+            Err(Error::InvalidSarc { offset, reason })
+        }
+    }
+}
+
+/// Parses the SARC archive `data`, returning its files in the order they're stored.
+pub fn entries(data: &[u8]) -> Result<Vec<SarcEntry<'_>>, Error> {
+    let mut fields = Fields { data, little_endian: false };
+    fields.expect_magic(0, b"SARC", "missing SARC magic")?;
+
+    fields.little_endian = match fields.bytes(6, 2)? {
+        [0xfe, 0xff] => false,
+        [0xff, 0xfe] => true,
+        _ => return Err(Error::InvalidSarc { offset: 6, reason: "invalid byte order mark" }),
+    };
+
+    let sfat = fields.u16(4)? as usize;
+    let data_start = fields.u32(0xc)? as usize;
+
+    fields.expect_magic(sfat, b"SFAT", "missing SFAT magic")?;
+    let node_count = fields.u16(sfat + 6)? as usize;
+    let nodes = sfat + SFAT_HEADER_LEN;
+
+    let sfnt = nodes + node_count * SFAT_NODE_LEN;
+    fields.expect_magic(sfnt, b"SFNT", "missing SFNT magic")?;
+    let names = sfnt + SFNT_HEADER_LEN;
+
+    (0..node_count)
+        .map(|i| {
+            let node = nodes + i * SFAT_NODE_LEN;
+            let name_hash = fields.u32(node)?;
+            let attributes = fields.u32(node + 4)?;
+            let start = fields.u32(node + 8)? as usize;
+            let end = fields.u32(node + 0xc)? as usize;
+
+            // the top byte flags whether there's a name; the low half is its offset in words
+            let name = match attributes >> 24 {
+                0 => None,
+                _ => Some(read_name(data, names + (attributes & 0xffff) as usize * 4, node)?),
+            };
+
+            if end < start {
+                return Err(Error::InvalidSarc { offset: node, reason: "file ends before it starts" });
+            }
+            let data = fields
+                .bytes(data_start + start, end - start)
+                .map_err(|_| Error::InvalidSarc { offset: node, reason: "file extends past the end of the archive" })?;
+
+            Ok(SarcEntry { name, name_hash, data })
+        })
+        .collect()
+}
+
+/// Reads the NUL-terminated name at `offset`, for the file table node at `node`.
+fn read_name(data: &[u8], offset: usize, node: usize) -> Result<&str, Error> {
+    let invalid = |reason| Error::InvalidSarc { offset: node, reason };
+
+    let rest = data.get(offset..).ok_or_else(|| invalid("name is past the end of the archive"))?;
+    let len = rest
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| invalid("name is unterminated"))?;
+
+    std::str::from_utf8(&rest[..len]).map_err(|_| invalid("name isn't valid UTF-8"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use byteorder::WriteBytesExt;
+
+    const SARC_HEADER_LEN: usize = 0x14;
+
+    /// Builds a little-endian SARC archive holding `files`, with unnamed files for `None` names.
+    fn build(files: &[(Option<&str>, &[u8])]) -> Vec<u8> {
+        let mut names = Vec::new();
+        let mut nodes = Vec::new();
+        let mut contents = Vec::new();
+
+        for (i, (name, data)) in files.iter().enumerate() {
+            nodes.write_u32::<LittleEndian>(0x1000 + i as u32).unwrap();
+            match name {
+                Some(name) => {
+                    nodes.write_u32::<LittleEndian>(0x0100_0000 | (names.len() / 4) as u32).unwrap();
+                    names.extend_from_slice(name.as_bytes());
+                    names.push(0);
+                    names.resize((names.len() + 3) & !3, 0);
+                }
+                None => nodes.write_u32::<LittleEndian>(0).unwrap(),
+            }
+            nodes.write_u32::<LittleEndian>(contents.len() as u32).unwrap();
+            contents.extend_from_slice(data);
+            nodes.write_u32::<LittleEndian>(contents.len() as u32).unwrap();
+        }
+
+        let data_start = SARC_HEADER_LEN + SFAT_HEADER_LEN + nodes.len() + SFNT_HEADER_LEN + names.len();
+
+        let mut sarc = b"SARC\x14\x00\xff\xfe".to_vec();
+        sarc.write_u32::<LittleEndian>((data_start + contents.len()) as u32).unwrap();
+        sarc.write_u32::<LittleEndian>(data_start as u32).unwrap();
+        sarc.extend_from_slice(b"\x00\x01\x00\x00");
+        sarc.extend_from_slice(b"SFAT\x0c\x00");
+        sarc.write_u16::<LittleEndian>(files.len() as u16).unwrap();
+        sarc.write_u32::<LittleEndian>(0x65).unwrap();
+        sarc.extend_from_slice(&nodes);
+        sarc.extend_from_slice(b"SFNT\x08\x00\x00\x00");
+        sarc.extend_from_slice(&names);
+        sarc.extend_from_slice(&contents);
+        sarc
+    }
+
+    #[test]
+    fn parse() {
+        let sarc = build(&[(Some("Model/a.bfres"), b"hello"), (None, b""), (Some("b.txt"), b"world!")]);
+
+        let files = entries(&sarc).unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!((files[0].name, files[0].data), (Some("Model/a.bfres"), &b"hello"[..]));
+        assert_eq!(files[1].display_name(), "00001001.bin");
+        assert_eq!((files[2].name, files[2].data), (Some("b.txt"), &b"world!"[..]));
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(matches!(entries(b"RARC"), Err(Error::InvalidSarc { offset: 0, .. })));
+
+        let sarc = build(&[(Some("a"), b"hello")]);
+        let err = entries(&sarc[..sarc.len() - 1]).unwrap_err();
+        assert!(matches!(err, Error::InvalidSarc { offset: 0x20, .. }), "{:?}", err);
+        assert!(err.is_corruption());
+    }
+}
@@ -0,0 +1,120 @@
+//! A random-access archive that can be read from many threads at once.
+
+use std::fs::File;
+use std::io::{BufReader, Seek, SeekFrom};
+
+use crate::header::Yaz0Header;
+use crate::index::Yaz0Index;
+use crate::ops::ByteIter;
+use crate::positioned::PositionedReader;
+use crate::Error;
+
+/// A Yaz0 file that ranges of decompressed data can be pulled out of concurrently, from any
+/// number of threads, all sharing one file handle.
+///
+/// On creation, the file is scanned once to build an index of restart points, so that ranges can
+/// be decoded without starting from the beginning of the file each time. Reads use positioned IO,
+/// so they never contend over the file's cursor.
+#[derive(Debug)]
+pub struct SharedArchive {
+    file: File,
+
+    data_start: u64,
+    header: Yaz0Header,
+    index: Yaz0Index,
+}
+
+impl SharedArchive {
+    /// How much decompressed data there is between restart points by default.
+    pub const DEFAULT_INTERVAL: usize = Yaz0Index::DEFAULT_INTERVAL;
+
+    /// Opens the Yaz0 file `file`, indexing it with restart points every
+    /// [`SharedArchive::DEFAULT_INTERVAL`] bytes of decompressed data.
+    pub fn new(file: File) -> Result<SharedArchive, Error> {
+        SharedArchive::with_interval(file, SharedArchive::DEFAULT_INTERVAL)
+    }
+
+    /// Opens the Yaz0 file `file`, indexing it with restart points every `interval` bytes of
+    /// decompressed data. Smaller intervals make small reads cheaper, at the cost of keeping a
+    /// `0x1000`-byte window in memory for each restart point.
+    pub fn with_interval(file: File, interval: usize) -> Result<SharedArchive, Error> {
+        let mut reader = BufReader::new(PositionedReader::new(&file));
+        let header = Yaz0Header::parse(&mut reader)?;
+        let data_start = reader.stream_position()?;
+
+        let index = Yaz0Index::build(reader, header.expected_size, interval)?;
+
+        Ok(SharedArchive {
+            file,
+            data_start,
+            header,
+            index,
+        })
+    }
+
+    /// Get the expected size of inflated data from the parsed header.
+    pub fn expected_size(&self) -> usize {
+        self.header.expected_size
+    }
+
+    /// Decompresses `len` bytes of data, starting at offset `offset` in the decompressed data.
+    /// Ranges extending past the end of the data are cut short.
+    pub fn decompress_range(&self, offset: usize, len: usize) -> Result<Vec<u8>, Error> {
+        let offset = offset.min(self.expected_size());
+        let len = len.min(self.expected_size() - offset);
+
+        // start from the last restart point before the range
+        let point = self.index.restart_point(offset);
+
+        let mut reader = BufReader::new(PositionedReader::new(&self.file));
+        reader.seek(SeekFrom::Start(
+            self.data_start + point.compressed_offset as u64,
+        ))?;
+
+        ByteIter::<_>::resume(reader, self.expected_size(), point)
+            .skip(offset - point.output_offset)
+            .take(len)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::thread;
+
+    #[test]
+    fn concurrent_ranges() {
+        let path = std::env::temp_dir().join(format!("yaz0-shared-{}", std::process::id()));
+        fs::write(&path, &include_bytes!("../data/test.yaz0")[..]).unwrap();
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let archive = SharedArchive::with_interval(File::open(&path).unwrap(), 0x8000).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let ranges = [
+            (0, 0x10),
+            (0x7ffe, 0x20),
+            (0x12345, 0x4000),
+            (0x126600, 0x100),
+            (0x200000, 4),
+        ];
+        thread::scope(|scope| {
+            for &(offset, len) in &ranges {
+                let archive = &archive;
+                scope.spawn(move || {
+                    let range = archive.decompress_range(offset, len).unwrap();
+                    let end = (offset + len).min(reference_decompressed.len());
+                    let start = offset.min(end);
+                    assert!(
+                        range == reference_decompressed[start..end],
+                        "range {:#x}+{:#x} did not match",
+                        offset,
+                        len
+                    );
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,248 @@
+#[cfg(feature = "bytes")]
+use bytes::{BufMut, BytesMut};
+#[cfg(feature = "smallvec")]
+use smallvec::SmallVec;
+use std::io::{self, IoSliceMut, Write};
+
+use crate::ops::WINDOW_SIZE;
+
+/// A random-access destination for decompressed data.
+///
+/// Copy runs read back bytes that were previously written, so sinks need to support both.
+/// Implementations are provided for fixed-size slices, which must be large enough to hold the
+/// decompressed data, and for growable containers, which are extended as data is produced.
+pub trait Sink {
+    /// Total number of bytes this sink can hold.
+    fn capacity(&self) -> usize;
+    /// Reads back the byte previously written at `pos`.
+    fn get(&self, pos: usize) -> u8;
+    /// Writes `byte` at `pos`.
+    fn set(&mut self, pos: usize, byte: u8);
+}
+
+impl Sink for [u8] {
+    fn capacity(&self) -> usize {
+        self.len()
+    }
+
+    fn get(&self, pos: usize) -> u8 {
+        self[pos]
+    }
+
+    fn set(&mut self, pos: usize, byte: u8) {
+        self[pos] = byte;
+    }
+}
+
+/// Grows as bytes are written past its end, for when the output size isn't known up front.
+impl Sink for Vec<u8> {
+    fn capacity(&self) -> usize {
+        usize::MAX
+    }
+
+    fn get(&self, pos: usize) -> u8 {
+        self[pos]
+    }
+
+    fn set(&mut self, pos: usize, byte: u8) {
+        if pos == self.len() {
+            self.push(byte);
+        } else {
+            self[pos] = byte;
+        }
+    }
+}
+
+#[cfg(feature = "smallvec")]
+impl<A> Sink for SmallVec<A>
+where
+    A: smallvec::Array<Item = u8>,
+{
+    fn capacity(&self) -> usize {
+        usize::MAX
+    }
+
+    fn get(&self, pos: usize) -> u8 {
+        self[pos]
+    }
+
+    fn set(&mut self, pos: usize, byte: u8) {
+        if pos == self.len() {
+            self.push(byte);
+        } else {
+            self[pos] = byte;
+        }
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Sink for BytesMut {
+    fn capacity(&self) -> usize {
+        usize::MAX
+    }
+
+    fn get(&self, pos: usize) -> u8 {
+        self[pos]
+    }
+
+    fn set(&mut self, pos: usize, byte: u8) {
+        if pos == self.len() {
+            self.put_u8(byte);
+        } else {
+            self[pos] = byte;
+        }
+    }
+}
+
+/// Presents a list of discontiguous buffers as one logical destination.
+pub(crate) struct VectoredSink<'s, 'b> {
+    bufs: &'s mut [IoSliceMut<'b>],
+    /// Logical offset of the start of each buffer in `bufs`.
+    starts: Vec<usize>,
+}
+
+impl<'s, 'b> VectoredSink<'s, 'b> {
+    pub(crate) fn new(bufs: &'s mut [IoSliceMut<'b>]) -> VectoredSink<'s, 'b> {
+        let starts = bufs
+            .iter()
+            .scan(0, |offset, buf| {
+                let start = *offset;
+                *offset += buf.len();
+                Some(start)
+            })
+            .collect();
+
+        VectoredSink { bufs, starts }
+    }
+
+    /// Finds the buffer containing logical position `pos`.
+    /// Taking the *last* buffer starting at or before `pos` skips over any empty ones.
+    fn locate(&self, pos: usize) -> (usize, usize) {
+        let i = self.starts.partition_point(|&start| start <= pos) - 1;
+        (i, pos - self.starts[i])
+    }
+}
+
+impl Sink for VectoredSink<'_, '_> {
+    fn capacity(&self) -> usize {
+        self.bufs.iter().map(|buf| buf.len()).sum()
+    }
+
+    fn get(&self, pos: usize) -> u8 {
+        let (i, offset) = self.locate(pos);
+        self.bufs[i][offset]
+    }
+
+    fn set(&mut self, pos: usize, byte: u8) {
+        let (i, offset) = self.locate(pos);
+        self.bufs[i][offset] = byte;
+    }
+}
+
+/// Streams output to a writer, keeping only the window that copy runs can reference in memory.
+///
+/// Output is batched up before being written. Since [`Sink::set`] can't fail, the first write
+/// error is stashed and reported by [`WriterSink::finish`]; nothing more is written after it.
+pub(crate) struct WriterSink<W> {
+    writer: W,
+    window: Box<[u8; WINDOW_SIZE]>,
+    pending: Vec<u8>,
+    written: u64,
+    error: Option<io::Error>,
+}
+
+impl<W> WriterSink<W>
+where
+    W: Write,
+{
+    /// How much output is batched up before being written.
+    const BATCH_SIZE: usize = 0x10000;
+
+    pub(crate) fn new(writer: W) -> WriterSink<W> {
+        WriterSink {
+            writer,
+            window: Box::new([0; WINDOW_SIZE]),
+            pending: Vec::with_capacity(Self::BATCH_SIZE),
+            written: 0,
+            error: None,
+        }
+    }
+
+    fn write_pending(&mut self) {
+        if self.error.is_none() {
+            match self.writer.write_all(&self.pending) {
+                Ok(()) => self.written += self.pending.len() as u64,
+                Err(e) => self.error = Some(e),
+            }
+        }
+        self.pending.clear();
+    }
+
+    /// Writes out any batched output, returning the writer and the number of bytes written.
+    pub(crate) fn finish(mut self) -> io::Result<(W, u64)> {
+        self.write_pending();
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok((self.writer, self.written)),
+        }
+    }
+}
+
+impl<W> Sink for WriterSink<W>
+where
+    W: Write,
+{
+    fn capacity(&self) -> usize {
+        usize::MAX
+    }
+
+    fn get(&self, pos: usize) -> u8 {
+        self.window[pos % WINDOW_SIZE]
+    }
+
+    fn set(&mut self, pos: usize, byte: u8) {
+        self.window[pos % WINDOW_SIZE] = byte;
+
+        self.pending.push(byte);
+        if self.pending.len() == Self::BATCH_SIZE {
+            self.write_pending();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Yaz0Archive;
+    use std::io::Cursor;
+
+    /// Decompresses the test file into `sink`, and checks it against the reference.
+    fn check_sink<S: Sink + AsRef<[u8]>>(mut sink: S) {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        Yaz0Archive::new(Cursor::new(data))
+            .unwrap()
+            .decompress_into(&mut sink)
+            .unwrap();
+
+        assert!(sink.as_ref() == reference_decompressed, "sink contents did not match reference!");
+    }
+
+    #[test]
+    fn vec_grows() {
+        check_sink(Vec::new());
+    }
+
+    #[cfg(feature = "smallvec")]
+    #[test]
+    fn smallvec_grows() {
+        check_sink(SmallVec::<[u8; 64]>::new());
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn bytes_mut_grows() {
+        check_sink(BytesMut::new());
+    }
+}
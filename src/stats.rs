@@ -0,0 +1,172 @@
+//! Breakdowns of the packets making up compressed streams, for comparing encoders and tuning
+//! compression levels.
+
+use std::io::{Read, Seek};
+
+use crate::ops::{Op, OpIter};
+use crate::{Error, Yaz0Archive};
+
+/// Shortest run needing a 3-byte packet.
+const LONG_RUN: usize = 0x12;
+
+/// Counts of each kind of packet in a stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacketStats {
+    /// Number of literal bytes.
+    pub literals: usize,
+    /// Number of copy runs encoded in 2-byte packets (runs of 3 to `0x11` bytes).
+    pub short_runs: usize,
+    /// Number of copy runs encoded in 3-byte packets (runs of `0x12` bytes or more).
+    pub long_runs: usize,
+    /// Total number of bytes produced by copy runs.
+    pub run_bytes: usize,
+    /// Sum of the distances of all copy runs.
+    pub run_distance_total: usize,
+}
+
+impl PacketStats {
+    /// Counts `op`.
+    pub(crate) fn add(&mut self, op: &Op) {
+        match *op {
+            Op::Literal(_) => self.literals += 1,
+            Op::Copy { dist, len } => {
+                if len >= LONG_RUN {
+                    self.long_runs += 1;
+                } else {
+                    self.short_runs += 1;
+                }
+                self.run_bytes += len;
+                self.run_distance_total += dist;
+            }
+        }
+    }
+
+    /// Counts the operations in `payload`, a packet stream decoding to `output_len` bytes.
+    pub(crate) fn add_payload(&mut self, payload: &[u8], output_len: usize) -> Result<(), Error> {
+        for op in OpIter::new(payload, output_len) {
+            self.add(&op?);
+        }
+
+        Ok(())
+    }
+
+    /// Total number of copy runs.
+    pub fn runs(&self) -> usize {
+        self.short_runs + self.long_runs
+    }
+
+    /// The mean length of the copy runs, or 0 if there are none.
+    pub fn avg_run_len(&self) -> f64 {
+        self.run_bytes as f64 / self.runs().max(1) as f64
+    }
+
+    /// The mean distance of the copy runs, or 0 if there are none.
+    pub fn avg_distance(&self) -> f64 {
+        self.run_distance_total as f64 / self.runs().max(1) as f64
+    }
+}
+
+/// Statistics about a compression, from
+/// [`Yaz0Writer::compress_and_write_with_stats`](crate::Yaz0Writer::compress_and_write_with_stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// Size of the uncompressed input.
+    pub input_len: usize,
+    /// Size of the compressed payload, not including the header.
+    pub compressed_len: usize,
+    /// The packets the payload was made up of.
+    pub packets: PacketStats,
+}
+
+impl CompressionStats {
+    /// The compression ratio achieved, as compressed size over input size.
+    pub fn ratio(&self) -> f64 {
+        self.compressed_len as f64 / self.input_len.max(1) as f64
+    }
+}
+
+/// Statistics about an existing compressed stream, from [`Yaz0Archive::inflate_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InflateStats {
+    /// Size of the compressed payload, not including the header, up to the end of the last packet.
+    pub compressed_len: usize,
+    /// Size of the decompressed data.
+    pub output_len: usize,
+    /// The packets the payload is made up of.
+    pub packets: PacketStats,
+}
+
+impl InflateStats {
+    /// The compression ratio of the stream, as compressed size over decompressed size.
+    pub fn ratio(&self) -> f64 {
+        self.compressed_len as f64 / self.output_len.max(1) as f64
+    }
+}
+
+impl<R> Yaz0Archive<R>
+where
+    R: Read + Seek,
+{
+    /// Counts the packets making up the compressed stream, without decompressing it.
+    pub fn inflate_stats(&mut self) -> Result<InflateStats, Error> {
+        let output_len = self.expected_size();
+        let mut packets = PacketStats::default();
+
+        let mut ops = OpIter::new(self.raw_compressed_reader()?, output_len);
+        for op in &mut ops {
+            packets.add(&op?);
+        }
+
+        Ok(InflateStats {
+            compressed_len: ops.offset(),
+            output_len,
+            packets,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{CompressionLevel, Yaz0Writer};
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    #[test]
+    fn matching_stats() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x20000];
+
+        let mut deflated = Vec::new();
+        let compression = Yaz0Writer::new(&mut deflated)
+            .compress_and_write_with_stats(data, CompressionLevel::Lookahead { quality: 10 })
+            .unwrap();
+
+        let inflation = Yaz0Archive::new(Cursor::new(&deflated)).unwrap().inflate_stats().unwrap();
+
+        assert_eq!(compression.packets, inflation.packets);
+        assert_eq!(compression.compressed_len, inflation.compressed_len);
+        assert_eq!(compression.compressed_len, deflated.len() - 0x10);
+
+        let packets = compression.packets;
+        assert_eq!(packets.literals + packets.run_bytes, data.len());
+        assert!(packets.short_runs > 0 && packets.long_runs > 0);
+        assert!(packets.avg_run_len() >= 3. && packets.avg_distance() <= 4096.);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn packet_kinds() {
+        let mut stats = PacketStats::default();
+        stats.add_payload(&[
+            0xf6, /* | id:  */ 0, 1, 2, 0xa,
+                  /*   run: */ 0x10, 0x03,
+                  /*   id:  */ 3, 0xb,
+                  /*   run: */ 0x00, 0x04, 0x01,
+            0xf0, /* | id:  */ 4, 5, 6, 7,
+        ], 0x20).unwrap();
+
+        assert_eq!((stats.literals, stats.short_runs, stats.long_runs), (10, 1, 1));
+        assert_eq!(stats.run_bytes, 3 + 0x13);
+        assert_eq!(stats.avg_distance(), 4.5);
+    }
+}
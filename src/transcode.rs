@@ -0,0 +1,120 @@
+//! Streaming conversion between Yaz0 and gzip/zlib.
+//!
+//! Converting from Yaz0 never holds more than the decompressor's window in memory. The compressor
+//! works on whole inputs, so converting *to* Yaz0 has to inflate the gzip/zlib stream fully first.
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use std::io::{Read, Write};
+
+use crate::deflate::CompressionLevel;
+use crate::inflate::Yaz0Archive;
+use crate::Error;
+
+/// Decompresses the Yaz0 file in `reader`, streaming the output into `writer`.
+/// Returns the number of decompressed bytes.
+fn inflate_to_writer<R, W>(reader: R, mut writer: W) -> Result<(W, u64), Error>
+where
+    R: Read,
+    W: Write,
+{
+    let written = Yaz0Archive::new(reader)?.decompress_to_writer(&mut writer)?;
+    Ok((writer, written))
+}
+
+/// Converts the Yaz0 file in `reader` to gzip, written to `writer` at gzip level `level`.
+/// Returns the number of decompressed bytes.
+pub fn yaz0_to_gzip<R, W>(reader: R, writer: W, level: Compression) -> Result<u64, Error>
+where
+    R: Read,
+    W: Write,
+{
+    let (encoder, len) = inflate_to_writer(reader, GzEncoder::new(writer, level))?;
+    encoder.finish()?;
+    Ok(len)
+}
+
+/// Converts the Yaz0 file in `reader` to zlib, written to `writer` at zlib level `level`.
+/// Returns the number of decompressed bytes.
+pub fn yaz0_to_zlib<R, W>(reader: R, writer: W, level: Compression) -> Result<u64, Error>
+where
+    R: Read,
+    W: Write,
+{
+    let (encoder, len) = inflate_to_writer(reader, ZlibEncoder::new(writer, level))?;
+    encoder.finish()?;
+    Ok(len)
+}
+
+/// Converts the gzip stream in `reader` to Yaz0, written to `writer` at [CompressionLevel] `level`.
+/// Returns the number of decompressed bytes.
+pub fn gzip_to_yaz0<R, W>(reader: R, writer: &mut W, level: CompressionLevel) -> Result<u64, Error>
+where
+    R: Read,
+    W: Write,
+{
+    crate::copy_compress(GzDecoder::new(reader), writer, level)
+}
+
+/// Converts the zlib stream in `reader` to Yaz0, written to `writer` at [CompressionLevel] `level`.
+/// Returns the number of decompressed bytes.
+pub fn zlib_to_yaz0<R, W>(reader: R, writer: &mut W, level: CompressionLevel) -> Result<u64, Error>
+where
+    R: Read,
+    W: Write,
+{
+    crate::copy_compress(ZlibDecoder::new(reader), writer, level)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    #[test]
+    fn gzip_roundtrip() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let mut gzipped = Vec::new();
+        let len = yaz0_to_gzip(Cursor::new(data), &mut gzipped, Compression::fast()).unwrap();
+        assert_eq!(len, reference_decompressed.len() as u64);
+
+        let mut inflated = Vec::new();
+        GzDecoder::new(&gzipped[..]).read_to_end(&mut inflated).unwrap();
+        assert!(inflated == reference_decompressed, "gzip transcode did not match reference!");
+
+        // and back again, on something small enough to compress quickly
+        let mut gzipped = Vec::new();
+        let mut encoder = GzEncoder::new(&mut gzipped, Compression::fast());
+        encoder.write_all(&reference_decompressed[..0x800]).unwrap();
+        encoder.finish().unwrap();
+
+        let mut yaz0 = Vec::new();
+        gzip_to_yaz0(&gzipped[..], &mut yaz0, CompressionLevel::Lookahead { quality: 10 }).unwrap();
+        let inflated = Yaz0Archive::new(Cursor::new(yaz0)).unwrap().decompress().unwrap();
+        assert_eq!(&inflated[..], &reference_decompressed[..0x800]);
+    }
+
+    #[test]
+    fn zlib_roundtrip() {
+        let data: Vec<u8> = (0..0x400).map(|i| (i % 7) as u8).collect();
+
+        let mut zlibbed = Vec::new();
+        let mut encoder = ZlibEncoder::new(&mut zlibbed, Compression::default());
+        encoder.write_all(&data).unwrap();
+        encoder.finish().unwrap();
+
+        let mut yaz0 = Vec::new();
+        zlib_to_yaz0(&zlibbed[..], &mut yaz0, CompressionLevel::Lookahead { quality: 10 }).unwrap();
+
+        let mut rezlibbed = Vec::new();
+        yaz0_to_zlib(Cursor::new(yaz0), &mut rezlibbed, Compression::default()).unwrap();
+
+        let mut inflated = Vec::new();
+        ZlibDecoder::new(&rezlibbed[..]).read_to_end(&mut inflated).unwrap();
+        assert_eq!(inflated, data);
+    }
+}
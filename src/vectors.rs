@@ -0,0 +1,98 @@
+//! Small conformance test vectors for Yaz0 decoders.
+//!
+//! Each vector is a complete Yaz0 file along with the data it must decompress to, exercising one
+//! feature of the format. Other implementations (and bindings to this one) can check themselves
+//! against these.
+
+/// A Yaz0 file, and the data it decompresses to.
+#[derive(Debug, Clone, Copy)]
+pub struct TestVector {
+    /// A short identifier for the vector.
+    pub name: &'static str,
+    /// What the vector exercises.
+    pub description: &'static str,
+    /// The complete Yaz0 file, header included.
+    pub compressed: &'static [u8],
+    /// The data the file decompresses to.
+    pub decompressed: &'static [u8],
+}
+
+/// Every test vector.
+#[rustfmt::skip]
+pub const VECTORS: &[TestVector] = &[
+    TestVector {
+        name: "empty",
+        description: "A file with a decompressed size of zero, and no payload at all.",
+        compressed: b"Yaz0\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00",
+        decompressed: b"",
+    },
+    TestVector {
+        name: "literals",
+        description: "A partial group of literals; the unused low bits of the code byte are zero.",
+        compressed: b"Yaz0\x00\x00\x00\x03\x00\x00\x00\x00\x00\x00\x00\x00\
+                      \xe0abc",
+        decompressed: b"abc",
+    },
+    TestVector {
+        name: "two-groups",
+        description: "Nine literals, taking a full group and one operation of a second.",
+        compressed: b"Yaz0\x00\x00\x00\x09\x00\x00\x00\x00\x00\x00\x00\x00\
+                      \xff01234567\
+                      \x808",
+        decompressed: b"012345678",
+    },
+    TestVector {
+        name: "two-byte-packet",
+        description: "A 2-byte copy packet: a run of 3 bytes (the shortest possible), 3 bytes back.",
+        compressed: b"Yaz0\x00\x00\x00\x06\x00\x00\x00\x00\x00\x00\x00\x00\
+                      \xe0abc\x10\x02",
+        decompressed: b"abcabc",
+    },
+    TestVector {
+        name: "overlapping-copy",
+        description: "A run of 0x11 bytes (the longest 2-byte packet), 1 byte back, so that it \
+                      copies bytes it has itself produced.",
+        compressed: b"Yaz0\x00\x00\x00\x12\x00\x00\x00\x00\x00\x00\x00\x00\
+                      \x80a\xf0\x00",
+        decompressed: b"aaaaaaaaaaaaaaaaaa",
+    },
+    TestVector {
+        name: "three-byte-packet",
+        description: "A 3-byte copy packet: a run of 0x12 bytes (the shortest 3-byte packet), \
+                      2 bytes back.",
+        compressed: b"Yaz0\x00\x00\x00\x14\x00\x00\x00\x00\x00\x00\x00\x00\
+                      \xc0ab\x00\x01\x00",
+        decompressed: b"abababababababababab",
+    },
+    TestVector {
+        name: "max-length-run",
+        description: "A run of 0x111 bytes, the longest a 3-byte packet can encode.",
+        compressed: b"Yaz0\x00\x00\x01\x12\x00\x00\x00\x00\x00\x00\x00\x00\
+                      \x80x\x00\x00\xff",
+        decompressed: &[b'x'; 0x112],
+    },
+    TestVector {
+        name: "header-fields",
+        description: "Nonzero alignment and reserved header fields, which don't affect decoding.",
+        compressed: b"Yaz0\x00\x00\x00\x02\x00\x00\x00\x80\xde\xad\xbe\xef\
+                      \xc0hi",
+        decompressed: b"hi",
+    },
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Yaz0Archive;
+    use std::io::Cursor;
+
+    #[test]
+    fn vectors_decode() {
+        for vector in VECTORS {
+            let mut archive = Yaz0Archive::new(Cursor::new(vector.compressed)).unwrap();
+            archive.verify().unwrap_or_else(|e| panic!("{}: {}", vector.name, e));
+            let decompressed = archive.decompress().unwrap();
+            assert!(decompressed == vector.decompressed, "{} decoded wrongly", vector.name);
+        }
+    }
+}
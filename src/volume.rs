@@ -0,0 +1,240 @@
+//! Splitting files across multiple fixed-size volumes, and reading them back as one stream.
+//!
+//! Volumes are named after a base path, with a three-digit index appended as an extra extension:
+//! `foo.szs` is split into `foo.szs.000`, `foo.szs.001`, and so on. This is useful for filesystems
+//! with a file size limit, like FAT32.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Returns the path of volume number `index` of the file at `base`.
+pub fn volume_path(base: &Path, index: usize) -> PathBuf {
+    let mut path = base.as_os_str().to_owned();
+    path.push(format!(".{:03}", index));
+    PathBuf::from(path)
+}
+
+/// A writer which splits its output across volumes of at most `max_size` bytes each.
+/// Volumes are only created once there's data to put in them.
+///
+/// Creating the first volume deletes any later volumes left from an earlier split, so that
+/// [`VolumeReader`] doesn't pick them up along with the new ones.
+#[derive(Debug)]
+pub struct VolumeWriter {
+    base: PathBuf,
+    max_size: u64,
+
+    /// The index of the next volume to create.
+    next_index: usize,
+    /// The volume being written, and how many bytes have been written to it.
+    current: Option<(File, u64)>,
+}
+
+impl VolumeWriter {
+    /// Creates a writer splitting its output across volumes named after `base`.
+    ///
+    /// # Panics
+    /// If `max_size` is zero.
+    pub fn new<P: AsRef<Path>>(base: P, max_size: u64) -> VolumeWriter {
+        assert!(max_size > 0, "volumes must be able to hold at least one byte");
+
+        VolumeWriter {
+            base: base.as_ref().to_owned(),
+            max_size,
+            next_index: 0,
+            current: None,
+        }
+    }
+
+    /// The number of volumes created so far.
+    pub fn volume_count(&self) -> usize {
+        self.next_index
+    }
+}
+
+impl Write for VolumeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // roll over to a new volume if the current one is full (or we haven't got one yet)
+        let full = match self.current {
+            Some((_, written)) => written >= self.max_size,
+            None => true,
+        };
+        if full {
+            if let Some((mut file, _)) = self.current.take() {
+                file.flush()?;
+            }
+            if self.next_index == 0 {
+                remove_volumes_from(&self.base, 1)?;
+            }
+            let file = File::create(volume_path(&self.base, self.next_index))?;
+            self.next_index += 1;
+            self.current = Some((file, 0));
+        }
+
+        let (file, written) = self.current.as_mut().unwrap();
+        let len = buf.len().min((self.max_size - *written) as usize);
+        let n = file.write(&buf[..len])?;
+        *written += n as u64;
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.current {
+            Some((ref mut file, _)) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Deletes the consecutive volumes of the file at `base`, starting from volume number `index`.
+fn remove_volumes_from(base: &Path, index: usize) -> io::Result<()> {
+    for index in index.. {
+        match fs::remove_file(volume_path(base, index)) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// A reader presenting a set of volumes as one seekable stream.
+#[derive(Debug)]
+pub struct VolumeReader {
+    /// Each volume, along with its length.
+    volumes: Vec<(File, u64)>,
+    pos: u64,
+}
+
+impl VolumeReader {
+    /// Opens all consecutive volumes of the file at `base`, starting from `base.000`.
+    pub fn open<P: AsRef<Path>>(base: P) -> io::Result<VolumeReader> {
+        let mut volumes = Vec::new();
+        loop {
+            let file = match File::open(volume_path(base.as_ref(), volumes.len())) {
+                Ok(file) => file,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound && !volumes.is_empty() => break,
+                Err(e) => return Err(e),
+            };
+            let len = file.metadata()?.len();
+            volumes.push((file, len));
+        }
+
+        Ok(VolumeReader { volumes, pos: 0 })
+    }
+
+    /// Total length of all volumes.
+    fn len(&self) -> u64 {
+        self.volumes.iter().map(|&(_, len)| len).sum()
+    }
+}
+
+impl Read for VolumeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // find the volume containing the read head
+        let mut start = 0;
+        for (file, len) in &mut self.volumes {
+            if self.pos < start + *len {
+                file.seek(SeekFrom::Start(self.pos - start))?;
+                let want = buf.len().min((start + *len - self.pos) as usize);
+                let n = file.read(&mut buf[..want])?;
+                self.pos += n as u64;
+                return Ok(n);
+            }
+            start += *len;
+        }
+
+        Ok(0)
+    }
+}
+
+impl Seek for VolumeReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{CompressionLevel, Yaz0Archive, Yaz0Writer};
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    #[test]
+    fn roundtrip_through_volumes() {
+        let dir = std::env::temp_dir().join(format!("yaz0-volumes-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("test.szs");
+
+        let data: Vec<u8> = (0..0x200).map(|i| (i * i % 251) as u8).collect();
+
+        let mut writer = VolumeWriter::new(&base, 0x40);
+        Yaz0Writer::new(&mut writer)
+            .compress_and_write(&data, CompressionLevel::Lookahead { quality: 10 })
+            .expect("Could not deflate");
+        writer.flush().unwrap();
+        assert!(writer.volume_count() > 1);
+
+        for i in 0..writer.volume_count() {
+            assert!(fs::metadata(volume_path(&base, i)).unwrap().len() <= 0x40);
+        }
+
+        let inflated = Yaz0Archive::new(VolumeReader::open(&base).unwrap())
+            .expect("Error creating Yaz0Archive")
+            .decompress()
+            .expect("Error deflating Yaz0 archive");
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(inflated, data);
+    }
+
+    #[test]
+    fn overwrite_with_fewer_volumes() {
+        let dir = std::env::temp_dir().join(format!("yaz0-volumes-overwrite-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("test.bin");
+
+        let mut writer = VolumeWriter::new(&base, 0x10);
+        writer.write_all(&[0xaa; 0x30]).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.volume_count(), 3);
+
+        let mut writer = VolumeWriter::new(&base, 0x10);
+        writer.write_all(&[0x55; 0x8]).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.volume_count(), 1);
+
+        let mut read = Vec::new();
+        VolumeReader::open(&base).unwrap().read_to_end(&mut read).unwrap();
+        let stale = volume_path(&base, 1).exists();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(read, [0x55; 0x8]);
+        assert!(!stale);
+    }
+}
@@ -0,0 +1,82 @@
+//! `wasm-bindgen` bindings, exposing compression and decompression of byte arrays to JavaScript
+//! (e.g. in-browser modding tools).
+//!
+//! Byte arrays come in as `Uint8Array`s and go back out as new ones. Progress callbacks are plain
+//! JS functions, called on the same thread as compression runs.
+
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+
+use crate::{CompressionLevel, Error, Yaz0Archive, Yaz0Writer};
+
+/// Turns a codec error into a JS exception.
+fn to_js_error(err: Error) -> JsValue {
+    JsError::new(&err.to_string()).into()
+}
+
+/// Picks the compression level for `quality`, defaulting to the best.
+fn level(quality: Option<u32>) -> CompressionLevel {
+    let quality = quality.unwrap_or(10).clamp(1, 10) as usize;
+    CompressionLevel::Lookahead { quality }
+}
+
+/// Calls the JS function `progress` with `done` and `total`, keeping the first exception it
+/// throws in `thrown`.
+fn report(progress: &Function, thrown: &mut Option<JsValue>, done: usize, total: usize) {
+    if thrown.is_some() {
+        return;
+    }
+
+    if let Err(e) = progress.call2(&JsValue::NULL, &(done as f64).into(), &(total as f64).into()) {
+        *thrown = Some(e);
+    }
+}
+
+/// Compresses `data` into a Yaz0 file, at `quality` from 1 to 10 (10 if not given).
+#[wasm_bindgen]
+pub fn compress(data: &[u8], quality: Option<u32>) -> Vec<u8> {
+    crate::compress(data, level(quality))
+}
+
+/// Decompresses the Yaz0 file in `data`.
+#[wasm_bindgen]
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, JsValue> {
+    crate::decompress(data).map_err(to_js_error)
+}
+
+/// Like `compress`, but calls `progress(bytesRead, totalBytes)` as compression goes.
+///
+/// If `progress` throws, compression still runs to the end, then the exception is rethrown.
+#[wasm_bindgen(js_name = compressWithProgress)]
+pub fn compress_with_progress(
+    data: &[u8],
+    quality: Option<u32>,
+    progress: &Function,
+) -> Result<Vec<u8>, JsValue> {
+    let mut thrown = None;
+
+    let mut compressed = Vec::with_capacity(crate::max_compressed_size(data.len()));
+    Yaz0Writer::new(&mut compressed)
+        .compress_and_write_with_callback(data, level(quality), |msg| {
+            report(progress, &mut thrown, msg.read_head, data.len())
+        })
+        .map_err(to_js_error)?;
+
+    thrown.map_or(Ok(compressed), Err)
+}
+
+/// Like `decompress`, but calls `progress(bytesProduced, totalBytes)` as decompression goes.
+///
+/// If `progress` throws, decompression still runs to the end, then the exception is rethrown.
+#[wasm_bindgen(js_name = decompressWithProgress)]
+pub fn decompress_with_progress(data: &[u8], progress: &Function) -> Result<Vec<u8>, JsValue> {
+    let mut thrown = None;
+
+    let mut archive = Yaz0Archive::from_bytes(data).map_err(to_js_error)?;
+    let total = archive.expected_size();
+    let decompressed = archive
+        .decompress_with_callback(|msg| report(progress, &mut thrown, msg.output_len, total))
+        .map_err(to_js_error)?;
+
+    thrown.map_or(Ok(decompressed), Err)
+}
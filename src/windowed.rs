@@ -0,0 +1,119 @@
+//! A streaming decoder with a configurable window, for memory-constrained targets.
+
+use std::io::{self, Read, Seek};
+
+use crate::header::Yaz0Header;
+use crate::ops::{ByteIter, WINDOW_SIZE};
+use crate::Error;
+
+/// Streams decompressed data out of a Yaz0 file, keeping only the last `N` bytes of output in
+/// memory.
+///
+/// Yaz0 copy runs can reach up to `0x1000` bytes back, which is the window needed to decode any
+/// file. If the files being decoded are known to only use shorter distances, a smaller `N` trades
+/// generality for a smaller footprint: copy runs reaching back further than `N` bytes are reported
+/// as [`Error::CorruptData`]. `N` is checked at compile time:
+///
+/// ```compile_fail
+/// # use std::io::Cursor;
+/// # use yaz0::windowed::WindowedDecoder;
+/// let decoder = WindowedDecoder::<_, 0x2000>::new(Cursor::new(b"Yaz0"));
+/// ```
+///
+/// The decompressed data is read out through [`Read`]; errors are reported as [`io::Error`]s.
+pub struct WindowedDecoder<R, const N: usize = WINDOW_SIZE> {
+    bytes: ByteIter<R, N>,
+    expected_size: usize,
+}
+
+impl<R, const N: usize> WindowedDecoder<R, N>
+where
+    R: Read,
+{
+    const WINDOW_IS_VALID: () = assert!(
+        N > 0 && N <= WINDOW_SIZE,
+        "decoder window must be between 1 and 0x1000 bytes"
+    );
+
+    /// Creates a decoder from a reader positioned at the start of a Yaz0 file.
+    pub fn new(mut reader: R) -> Result<WindowedDecoder<R, N>, Error>
+    where
+        R: Seek,
+    {
+        let () = Self::WINDOW_IS_VALID;
+
+        let header = Yaz0Header::parse(&mut reader)?;
+
+        Ok(WindowedDecoder {
+            bytes: ByteIter::new(reader, header.expected_size),
+            expected_size: header.expected_size,
+        })
+    }
+
+    /// Get the expected size of inflated data from the parsed header.
+    pub fn expected_size(&self) -> usize {
+        self.expected_size
+    }
+}
+
+impl<R, const N: usize> Read for WindowedDecoder<R, N>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        for slot in buf.iter_mut() {
+            match self.bytes.next() {
+                Some(byte) => *slot = byte?,
+                None => break,
+            }
+            n += 1;
+        }
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{CompressionLevel, Yaz0Writer};
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    #[test]
+    fn full_window() {
+        let data: &[u8] = include_bytes!("../data/test.yaz0");
+        let reference_decompressed: &[u8] = include_bytes!("../data/test");
+
+        let mut decoder = WindowedDecoder::<_>::new(Cursor::new(data)).unwrap();
+        let mut inflated = Vec::new();
+        decoder.read_to_end(&mut inflated).unwrap();
+
+        assert!(inflated == reference_decompressed, "windowed decode did not match reference!");
+    }
+
+    #[test]
+    fn small_window() {
+        // compress with a short lookback, so runs never reach back further than 0x199 bytes
+        let data: Vec<u8> = (0..0x800).map(|i| (i % 37 + i / 300) as u8).collect();
+        let mut deflated = Vec::new();
+        Yaz0Writer::new(&mut deflated)
+            .compress_and_write(&data, CompressionLevel::Lookahead { quality: 1 })
+            .unwrap();
+
+        let mut inflated = Vec::new();
+        WindowedDecoder::<_, 0x200>::new(Cursor::new(&deflated))
+            .unwrap()
+            .read_to_end(&mut inflated)
+            .unwrap();
+        assert_eq!(inflated, data);
+
+        // too small a window for those runs
+        let err = WindowedDecoder::<_, 0x10>::new(Cursor::new(&deflated))
+            .unwrap()
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,338 @@
+//! The Yay0 (SZP) format, a close relative of Yaz0 used by N64 and some GameCube titles.
+//!
+//! Yay0 encodes the same operations as Yaz0, but splits them into three separate streams rather
+//! than interleaving them: a stream of 32-bit mask words with a bit per operation (set for a
+//! literal), a stream of 16-bit links describing copy runs, and a stream of raw bytes holding
+//! the literals and the extra length byte of long copy runs. The header records where the link
+//! and byte streams start.
+
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::deflate::{compress_ops, CompressionLevel};
+use crate::{Error, Op, Sink};
+
+/// The header on a Yay0 file.
+#[derive(Debug)]
+pub struct Yay0Header {
+    /// Expected size of the decompressed file
+    pub expected_size: usize,
+    /// Offset of the link stream from the start of the header.
+    pub link_offset: u32,
+    /// Offset of the byte stream from the start of the header.
+    pub chunk_offset: u32,
+}
+
+impl Yay0Header {
+    /// Parses the header of a Yay0 file, provided via the passed reader.
+    /// Leaves the read head at the start of the mask stream.
+    pub fn parse<R>(reader: &mut R) -> Result<Yay0Header, Error>
+    where
+        R: Read,
+    {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != b"Yay0" {
+            return Err(Error::InvalidMagic);
+        }
+
+        Ok(Yay0Header {
+            expected_size: reader.read_u32::<BigEndian>()? as usize,
+            link_offset: reader.read_u32::<BigEndian>()?,
+            chunk_offset: reader.read_u32::<BigEndian>()?,
+        })
+    }
+
+    /// Writes the header of a Yay0 file to the passed writer.
+    /// Leaves the write head at the start of the mask stream.
+    pub fn write<W>(&self, writer: &mut W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        writer.write_all(b"Yay0")?;
+        writer.write_u32::<BigEndian>(self.expected_size as u32)?;
+        writer.write_u32::<BigEndian>(self.link_offset)?;
+        writer.write_u32::<BigEndian>(self.chunk_offset)?;
+
+        Ok(())
+    }
+}
+
+/// Wraps a reader of Yay0 data, providing decompression methods.
+#[derive(Debug)]
+pub struct Yay0Archive<R>
+where
+    R: Read + Seek,
+{
+    reader: R,
+
+    /// Where the header starts in the reader; the header's offsets are relative to it.
+    start: u64,
+    header: Yay0Header,
+}
+
+impl<R> Yay0Archive<R>
+where
+    R: Read + Seek,
+{
+    /// Creates a new `Yay0Archive` from a reader positioned at the start of the header.
+    pub fn new(mut reader: R) -> Result<Yay0Archive<R>, Error> {
+        let start = reader.stream_position()?;
+        let header = Yay0Header::parse(&mut reader)?;
+
+        Ok(Yay0Archive {
+            reader,
+            start,
+            header,
+        })
+    }
+
+    /// Get the expected size of inflated data from the parsed `Yay0Header`.
+    pub fn expected_size(&self) -> usize {
+        self.header.expected_size
+    }
+
+    /// Decompresses the Yay0 file, producing a [Vec] of the decompressed data.
+    pub fn decompress(&mut self) -> Result<Vec<u8>, Error> {
+        let mut dest = vec![0; self.expected_size()];
+        self.decompress_into(&mut dest[..])?;
+
+        Ok(dest)
+    }
+
+    /// Decompresses the Yay0 file into a destination [`Sink`].
+    ///
+    /// If the destination sink can't hold [`Yay0Archive::expected_size`] bytes,
+    /// [`Error::DestinationTooSmall`] is returned.
+    pub fn decompress_into<S>(&mut self, dest: &mut S) -> Result<(), Error>
+    where
+        S: Sink + ?Sized,
+    {
+        if dest.capacity() < self.expected_size() {
+            return Err(Error::DestinationTooSmall {
+                needed: self.expected_size(),
+                got: dest.capacity(),
+            });
+        }
+
+        // the streams can be laid out in any order, so read them all in at once
+        let mut file = Vec::new();
+        self.reader.seek(SeekFrom::Start(self.start))?;
+        self.reader.read_to_end(&mut file)?;
+
+        inflate(&file, &self.header, dest)
+    }
+}
+
+/// A cursor over one of the three streams of a Yay0 (or MIO0) file, held in memory as `file`.
+pub(crate) struct Stream<'a> {
+    pub(crate) file: &'a [u8],
+    pub(crate) pos: usize,
+}
+
+impl<'a> Stream<'a> {
+    pub(crate) fn read(&mut self, len: usize, reason: &'static str) -> Result<&'a [u8], Error> {
+        match self.file.get(self.pos..self.pos + len) {
+            Some(bytes) => {
+                self.pos += len;
+                Ok(bytes)
+            }
+            None => Err(Error::CorruptData {
+                offset: self.pos,
+                reason,
+                context: None,
+            }),
+        }
+    }
+}
+
+/// Decodes the Yay0 file `file`, with the already-parsed `header`, into `dest`.
+fn inflate<S>(file: &[u8], header: &Yay0Header, dest: &mut S) -> Result<(), Error>
+where
+    S: Sink + ?Sized,
+{
+    let mut masks = Stream { file, pos: 0x10 };
+    let mut links = Stream { file, pos: header.link_offset as usize };
+    let mut chunks = Stream { file, pos: header.chunk_offset as usize };
+
+    let end = header.expected_size;
+    let mut dest_pos = 0;
+
+    let mut mask: u32 = 0;
+    let mut ops_left = 0;
+
+    while dest_pos < end {
+        if ops_left == 0 {
+            mask = BigEndian::read_u32(masks.read(4, "mask stream ends early")?);
+            ops_left = 32;
+        }
+
+        if mask & 0x8000_0000 != 0 {
+            dest.set(dest_pos, chunks.read(1, "byte stream ends early")?[0]);
+            dest_pos += 1;
+        } else {
+            let link_offset = links.pos;
+            let link = BigEndian::read_u16(links.read(2, "link stream ends early")?) as usize;
+
+            let dist = (link & 0xfff) + 1;
+            let copy_len = match link >> 12 {
+                0 => chunks.read(1, "byte stream ends early")?[0] as usize + 0x12,
+                n => n + 2,
+            };
+
+            let run_base = match dest_pos.checked_sub(dist) {
+                Some(base) => base,
+                None => {
+                    return Err(Error::CorruptData {
+                        offset: link_offset,
+                        reason: "copy run reaches back before the start of the output",
+                        context: None,
+                    })
+                }
+            };
+
+            // the last run may overshoot the end of the output; ignore anything past it
+            for i in 0..copy_len.min(end - dest_pos) {
+                dest.set(dest_pos, dest.get(run_base + i));
+                dest_pos += 1;
+            }
+        }
+
+        mask <<= 1;
+        ops_left -= 1;
+    }
+
+    Ok(())
+}
+
+/// Compresses data into the Yay0 format, using the same compression levels as
+/// [`Yaz0Writer`](crate::Yaz0Writer).
+pub struct Yay0Writer<'a, W: 'a>
+where
+    W: Write,
+{
+    writer: &'a mut W,
+}
+
+impl<'a, W> Yay0Writer<'a, W>
+where
+    W: Write,
+{
+    /// Creates a new `Yay0Writer` writing to `writer`.
+    pub fn new(writer: &'a mut W) -> Yay0Writer<'a, W> {
+        Yay0Writer { writer }
+    }
+
+    /// Compresses `data` with [CompressionLevel] `level`, writing the Yay0 file (header
+    /// included) to the destination.
+    pub fn compress_and_write(self, data: &[u8], level: CompressionLevel) -> Result<(), Error> {
+        let mut masks: Vec<u32> = Vec::new();
+        let mut links: Vec<u16> = Vec::new();
+        let mut chunks: Vec<u8> = Vec::new();
+
+        for (i, op) in compress_ops(data, level).into_iter().enumerate() {
+            if i % 32 == 0 {
+                masks.push(0);
+            }
+
+            match op {
+                Op::Literal(byte) => {
+                    *masks.last_mut().unwrap() |= 0x8000_0000 >> (i % 32);
+                    chunks.push(byte);
+                }
+                Op::Copy { dist, len } if len >= 0x12 => {
+                    links.push((dist - 1) as u16);
+                    chunks.push((len - 0x12) as u8);
+                }
+                Op::Copy { dist, len } => {
+                    links.push((((len - 2) << 12) | (dist - 1)) as u16);
+                }
+            }
+        }
+
+        let link_offset = 0x10 + masks.len() * 4;
+        let header = Yay0Header {
+            expected_size: data.len(),
+            link_offset: link_offset as u32,
+            chunk_offset: (link_offset + links.len() * 2) as u32,
+        };
+        header.write(self.writer)?;
+
+        for mask in masks {
+            self.writer.write_u32::<BigEndian>(mask)?;
+        }
+        for link in links {
+            self.writer.write_u16::<BigEndian>(link)?;
+        }
+        self.writer.write_all(&chunks)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    #[test]
+    #[rustfmt::skip]
+    fn inflate_known() {
+        let data: &[u8] = &[
+            b'Y', b'a', b'y', b'0', 0, 0, 0, 0x1b, 0, 0, 0, 0x14, 0, 0, 0, 0x18,
+            // masks: 4 literals, run, literal, long run
+            0xf4, 0x00, 0x00, 0x00,
+            // links: dist 4, len 3; dist 5, len 0x13 (from the byte stream)
+            0x10, 0x03, 0x00, 0x04,
+            // bytes
+            0, 1, 2, 3, 4, 0x01,
+        ];
+
+        let out = Yay0Archive::new(Cursor::new(data)).unwrap().decompress().unwrap();
+        let mut expected = vec![0, 1, 2, 3, 0, 1, 2, 4];
+        for _ in 0..0x13 {
+            expected.push(expected[expected.len() - 5]);
+        }
+        expected.truncate(0x1b);
+        assert_eq!(&out[..], &expected[..]);
+    }
+
+    #[test]
+    fn inverts() {
+        let data: &[u8] = &include_bytes!("../data/test")[..0x20000];
+
+        for &level in &[
+            CompressionLevel::Naive { quality: 10 },
+            CompressionLevel::HashChain { quality: 7 },
+        ] {
+            let mut compressed = Vec::new();
+            Yay0Writer::new(&mut compressed).compress_and_write(data, level).unwrap();
+
+            let out = Yay0Archive::new(Cursor::new(&compressed)).unwrap().decompress().unwrap();
+            assert_eq!(&out[..], data);
+        }
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut compressed = Vec::new();
+        Yay0Writer::new(&mut compressed)
+            .compress_and_write(b"abcabcabcabc", CompressionLevel::Naive { quality: 10 })
+            .unwrap();
+
+        // point the first link back before the start of the output
+        let link_offset = BigEndian::read_u32(&compressed[8..]) as usize;
+        compressed[link_offset] = 0x0f;
+        let err = Yay0Archive::new(Cursor::new(&compressed)).unwrap().decompress().unwrap_err();
+        assert!(matches!(err, Error::CorruptData { .. }), "{:?}", err);
+
+        // and a truncated file
+        compressed.truncate(0x12);
+        let err = Yay0Archive::new(Cursor::new(&compressed)).unwrap().decompress().unwrap_err();
+        assert!(matches!(err, Error::CorruptData { .. }), "{:?}", err);
+
+        let err = Yay0Archive::new(Cursor::new(b"Yaz0")).unwrap_err();
+        assert!(matches!(err, Error::InvalidMagic), "{:?}", err);
+    }
+}
@@ -0,0 +1,51 @@
+//! Runs the `yaztool` binary against small crafted files.
+#![cfg(feature = "yaztool")]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// A Yaz0 file of four bytes whose last copy run is a byte longer than the header allows.
+#[rustfmt::skip]
+const OVERRUNNING: &[u8] = &[
+    b'Y', b'a', b'z', b'0', 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0,
+    0xc0, /* | id:  */ 1, 2,
+          /*   run: */ 0x10, 0x01,
+];
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("yaz0-cli-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn yaztool(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_yaztool")).args(args).output().unwrap()
+}
+
+#[test]
+fn lenient_tolerates_overrun() {
+    let dir = scratch_dir("lenient");
+    let input = dir.join("overrun.szs");
+    fs::write(&input, OVERRUNNING).unwrap();
+    let input = input.to_str().unwrap();
+
+    for extra in [&[][..], &["--mmap"][..]] {
+        let output = dir.join("out");
+        let out = output.to_str().unwrap();
+
+        let mut args = vec!["decompress", input, out, "--lenient"];
+        args.extend_from_slice(extra);
+        let result = yaztool(&args);
+        assert!(result.status.success(), "{:?}", result);
+        assert!(String::from_utf8_lossy(&result.stderr).contains("warning: copy run at 0x3"));
+        assert_eq!(fs::read(&output).unwrap(), [1, 2, 1, 2]);
+
+        // without --lenient, it's rejected as bad data
+        let mut args = vec!["decompress", input, out];
+        args.extend_from_slice(extra);
+        assert_eq!(yaztool(&args).status.code(), Some(yaz0::exit_code::DATA_ERROR));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}